@@ -1,30 +1,27 @@
-use std::{collections::HashMap, io::Stdout, time::Duration};
+use std::{collections::HashMap, time::Duration};
 use holani::{mikey::video::{LYNX_SCREEN_HEIGHT, LYNX_SCREEN_WIDTH}, suzy::registers::{Joystick, Switches}};
-use ratatui::{crossterm::{self, event::{Event, KeyCode, KeyEventKind}}, layout::{Constraint, Layout}, prelude::CrosstermBackend, style::Color, symbols::Marker, widgets::canvas::{Canvas, Painter, Shape}, Terminal};
+use ratatui::{crossterm::{self, event::{Event, KeyCode, KeyEventKind}}, layout::{Constraint, Layout, Rect}, prelude::Backend, style::{Color, Style}, symbols::Marker, widgets::{canvas::{Canvas, Painter, Shape}, Paragraph, Widget}, Terminal};
 
-use crate::runner::{runner_config::{Input, RunnerConfig}, Runner};
+use crate::{audio_view::AudioView, cheats_view::CheatsView, comlynx_view::ComlynxView, debugger_view::DebuggerView, hardware_view::HardwareView, profiler_view::ProfilerView, runner::{comlynx_link::ComlynxEndpoint, debugger::{DebugCommand, DebugEvent, Inspector}, runner_config::{Input, RunnerConfig}, scb::SpriteBounds, Runner, RunnerChannels}, sprites_view::SpritesView, video_view::VideoView};
 
 const BUTTON_DECAY: u8 = 15;
 const INPUT_POLL: Duration = Duration::from_millis(2);
+const DEBUGGER_TOGGLE: KeyCode = KeyCode::F(1);
+const AUTOFIRE_TOGGLE: KeyCode = KeyCode::F(2);
+const INSPECTOR_CYCLE: KeyCode = KeyCode::F(3);
+const TRACE_TOGGLE: KeyCode = KeyCode::F(4);
+const INSPECTORS: [Inspector; 7] = [Inspector::Hardware, Inspector::Sprites, Inspector::Video, Inspector::Audio, Inspector::Cheats, Inspector::Profiler, Inspector::Comlynx];
+const HIGHLIGHT: Color = Color::Magenta;
 
 macro_rules! set_button {
     ($slf: expr, $btn: expr, $value: expr) => {
-        match $btn {
-            Input::Pause => $slf.switches.set(Switches::pause, $value),
-            Input::Up => $slf.joystick.set(Joystick::up, $value),
-            Input::Down => $slf.joystick.set(Joystick::down, $value),
-            Input::Left => $slf.joystick.set(Joystick::left, $value),
-            Input::Right => $slf.joystick.set(Joystick::right, $value),
-            Input::Outside => $slf.joystick.set(Joystick::outside, $value),
-            Input::Inside => $slf.joystick.set(Joystick::inside, $value),
-            Input::Option1 => $slf.joystick.set(Joystick::option_1, $value),
-            Input::Option2 => $slf.joystick.set(Joystick::option_2, $value),
-        }
+        $btn.apply(&mut $slf.joystick, &mut $slf.switches, $value)
     }
 }
 
 struct ScreenView<'a> {
-    rgb_buffer: &'a Vec<u8>,
+    rgb_buffer: &'a [u8],
+    highlight: Option<SpriteBounds>,
 }
 
 impl Shape for ScreenView<'_> {
@@ -34,112 +31,373 @@ impl Shape for ScreenView<'_> {
             let y = i / LYNX_SCREEN_WIDTH as usize;
             painter.paint(x, y, Color::Rgb(rgb[0], rgb[1], rgb[2]));
         }); 
+
+        if let Some(b) = self.highlight {
+            let (width, height) = (LYNX_SCREEN_WIDTH as i32, LYNX_SCREEN_HEIGHT as i32);
+            let mut paint = |x: i32, y: i32| {
+                if (0..width).contains(&x) && (0..height).contains(&y) {
+                    painter.paint(x as usize, y as usize, HIGHLIGHT);
+                }
+            };
+            for x in b.left..=b.right {
+                paint(x, b.top);
+                paint(x, b.bottom);
+            }
+            for y in b.top..=b.bottom {
+                paint(b.left, y);
+                paint(b.right, y);
+            }
+        }
     }
 }
 
-pub(crate) struct App {
+/// Lynx screen sized canvas drawing a RGB buffer, with an optional outlined area.
+pub(crate) fn screen_canvas(rgb_buffer: &[u8], highlight: Option<SpriteBounds>) -> impl Widget + '_ {
+    Canvas::default()
+        .x_bounds([0., LYNX_SCREEN_WIDTH as f64])
+        .y_bounds([0., LYNX_SCREEN_HEIGHT as f64])
+        .marker(Marker::Block)
+        .paint(move |ctx| {
+            ctx.draw(&ScreenView { rgb_buffer, highlight });
+        })
+}
+
+/// One emulated Lynx, its core thread, screen and inputs.
+struct Player {
     keyboard_frames: HashMap<Input, u8>,
     joystick: Joystick,
     switches: Switches,
-    config: RunnerConfig,
+    sent_inputs: (u8, u8),
+    frame: u64,
+    button_mapping: HashMap<KeyCode, Input>,
     input_tx: kanal::Sender<(u8, u8)>,
     _runner: Runner,
     update_display_rx: kanal::Receiver<Vec<u8>>,
+    screen: Vec<u8>,
 }
 
-impl App {
-    pub fn new(config: RunnerConfig) -> Self {
-
+impl Player {
+    fn new(config: RunnerConfig) -> Result<(Self, kanal::Sender<DebugCommand>, kanal::Receiver<DebugEvent>), String> {
         let mut runner = Runner::new(config.clone());
-        let (input_tx, update_display_rx, _rotation) = runner.initialize_thread();
-    
-        Self {
+        let RunnerChannels { input_tx, update_display_rx, debug_tx, debug_rx } = runner.initialize_thread()?;
+        let player = Self {
             keyboard_frames: HashMap::new(),
             joystick: Joystick::empty(),
             switches: Switches::empty(),
-            config,
+            sent_inputs: (0, 0),
+            frame: 0,
+            button_mapping: config.button_mapping().clone(),
             input_tx,
             _runner: runner,
             update_display_rx,
+            screen: vec![],
+        };
+        Ok((player, debug_tx, debug_rx))
+    }
+
+    fn input_decay(&mut self) {
+        self.keyboard_frames.iter_mut().for_each(|(_, counter)| {
+            *counter = counter.saturating_sub(1);
+        });
+
+        self.keyboard_frames.retain(|btn, counter| {
+            if *counter == 0 {
+                set_button!(self, *btn, false);
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    /// Toggles the held autofire buttons, called once per displayed frame.
+    fn autofire(&mut self, rates: &HashMap<Input, u8>) {
+        for btn in self.keyboard_frames.keys() {
+            if let Some(rate) = rates.get(btn) {
+                let pressed = (self.frame / *rate as u64).is_multiple_of(2);
+                btn.apply(&mut self.joystick, &mut self.switches, pressed);
+            }
         }
     }
 
-    pub fn run(&mut self, terminal: &mut Terminal<CrosstermBackend<Stdout>>) {
-        let display_rx = self.update_display_rx.clone();
+    fn send_inputs(&mut self) {
+        let inputs = (self.joystick.bits(), self.switches.bits());
+        if inputs != self.sent_inputs {
+            self.input_tx.send(inputs).unwrap();
+            self.sent_inputs = inputs;
+        }
+    }
+}
+
+/// Screens of the players tiled in `area`, side by side for two players, on a grid for more.
+fn screen_areas(area: Rect, players: usize) -> Vec<Rect> {
+    if players <= 2 {
+        return Layout::horizontal(vec![Constraint::Ratio(1, players as u32); players]).split(area).to_vec();
+    }
+    let columns = players.div_ceil(2);
+    Layout::vertical([Constraint::Ratio(1, 2); 2]).split(area).iter()
+        .flat_map(|row| Layout::horizontal(vec![Constraint::Ratio(1, columns as u32); columns]).split(*row).to_vec())
+        .take(players)
+        .collect()
+}
+
+pub(crate) struct App {
+    autofire_enabled: bool,
+    config: RunnerConfig,
+    players: Vec<Player>,
+    debug_tx: kanal::Sender<DebugCommand>,
+    debug_rx: kanal::Receiver<DebugEvent>,
+    debugger: DebuggerView,
+    inspector: Option<Inspector>,
+    hardware: HardwareView,
+    sprites: SpritesView,
+    video: VideoView,
+    audio: AudioView,
+    cheats: CheatsView,
+    profiler: ProfilerView,
+    comlynx: ComlynxView,
+    /// Why the core halted, shown above the screens.
+    status: Option<String>,
+    /// Terminal events of a remote session, the local terminal is read when none.
+    events: Option<kanal::Receiver<Event>>,
+    redraw: bool,
+}
+
+impl App {
+    /// Starts the cores, returns the first error of a core that couldn't load the game.
+    pub fn new(config: RunnerConfig) -> Result<Self, String> {
+
+        // The debugger and inspector panels follow the first player.
+        let mut cable = ComlynxEndpoint::cable(config.players()).into_iter();
+        let (first, debug_tx, debug_rx) = Player::new(config.player_config(0, cable.next()))?;
+        let mut players = vec![first];
+        for n in 1..config.players() {
+            players.push(Player::new(config.player_config(n, cable.next()))?.0);
+        }
+        let debugger = DebuggerView::new(debug_tx.clone(), config.symbols().clone());
+        let video = VideoView::new(debug_tx.clone());
+        let cheats = CheatsView::new(debug_tx.clone(), config.cheats().clone());
+        let profiler = ProfilerView::new(debug_tx.clone());
+    
+        Ok(Self {
+            autofire_enabled: config.autofire_enabled(),
+            config,
+            players,
+            debug_tx,
+            debug_rx,
+            debugger,
+            inspector: None,
+            hardware: HardwareView::new(),
+            sprites: SpritesView::new(),
+            video,
+            audio: AudioView::new(),
+            cheats,
+            profiler,
+            comlynx: ComlynxView::new(),
+            status: None,
+            events: None,
+            redraw: false,
+        })
+    }
+
+    /// App reading its keys from `events` instead of the local terminal, it exits once the sender is dropped.
+    pub fn with_events(config: RunnerConfig, events: kanal::Receiver<Event>) -> Result<Self, String> {
+        let mut app = Self::new(config)?;
+        app.events = Some(events);
+        Ok(app)
+    }
+
+    pub fn run<B: Backend>(&mut self, terminal: &mut Terminal<B>) {
+        let debug_rx = self.debug_rx.clone();
         let mut exit = false;
         while !exit {
             exit = self.handle_keyboard();
-            if let Ok(Some(rgb_buffer)) = display_rx.try_recv() {           
-                terminal.draw(move |f| {
-                    let [_, main] = Layout::vertical([Constraint::Length(1), Constraint::Fill(1)]).areas(f.area());
-                        let canvas = Canvas::default()
-                        .x_bounds([0., LYNX_SCREEN_WIDTH as f64])
-                        .y_bounds([0., LYNX_SCREEN_HEIGHT as f64])
-                        .marker(Marker::Block)
-                        .paint(|ctx| {
-                            ctx.draw(&ScreenView { rgb_buffer: &rgb_buffer });
-                        });
-                
-                    f.render_widget(canvas, main);
-                }).unwrap();
-
-                self.input_decay();
+            let autofire = match self.autofire_enabled {
+                true => Some(self.config.autofire()),
+                false => None,
+            };
+            for player in &mut self.players {
+                if let Ok(Some(rgb_buffer)) = player.update_display_rx.try_recv() {
+                    player.screen = rgb_buffer;
+                    self.redraw = true;
+
+                    player.frame += 1;
+                    player.input_decay();
+                    if let Some(rates) = autofire {
+                        player.autofire(rates);
+                    }
+                    player.send_inputs();
+                }
+            }
+            while let Ok(Some(event)) = debug_rx.try_recv() {
+                match event {
+                    DebugEvent::State(state) => self.debugger.update(*state),
+                    DebugEvent::Hardware(state) => self.hardware.update(*state),
+                    DebugEvent::Sprites(sprites) => self.sprites.update(sprites),
+                    DebugEvent::Video(state) => self.video.update(*state),
+                    DebugEvent::Audio(state) => self.audio.update(*state),
+                    DebugEvent::Cheats(state) => self.cheats.update(*state),
+                    DebugEvent::Profile(report) => self.profiler.update(*report),
+                    DebugEvent::Comlynx(state) => self.comlynx.update(*state),
+                    DebugEvent::Halted(message) => self.status = Some(message),
+                }
+                self.redraw = true;
+            }
+            if self.redraw {
+                self.draw(terminal);
+                self.redraw = false;
             }
         }
     }
 
-    fn read_inputs(&mut self) -> bool {
-        while let Ok(has_event) = crossterm::event::poll(INPUT_POLL) {
-            if !has_event {
-                return false;
+    fn draw<B: Backend>(&self, terminal: &mut Terminal<B>) {
+        terminal.draw(|f| {
+            let [status, main] = Layout::vertical([Constraint::Length(1), Constraint::Fill(1)]).areas(f.area());
+            if let Some(message) = &self.status {
+                f.render_widget(Paragraph::new(message.as_str()).style(Style::default().fg(Color::Red)), status);
+            }
+
+            let screen_area = match (self.debugger.visible(), self.inspector) {
+                (false, None) => main,
+                (debugger, inspector) => {
+                    let [screen_area, panel_area] = Layout::horizontal([Constraint::Percentage(50), Constraint::Fill(1)]).areas(main);
+                    match (debugger, inspector) {
+                        (true, _) => self.debugger.draw(f, panel_area),
+                        (_, Some(Inspector::Hardware)) => self.hardware.draw(f, panel_area),
+                        (_, Some(Inspector::Sprites)) => self.sprites.draw(f, panel_area),
+                        (_, Some(Inspector::Video)) => self.video.draw(f, panel_area),
+                        (_, Some(Inspector::Audio)) => self.audio.draw(f, panel_area),
+                        (_, Some(Inspector::Cheats)) => self.cheats.draw(f, panel_area),
+                        (_, Some(Inspector::Profiler)) => self.profiler.draw(f, panel_area),
+                        (_, Some(Inspector::Comlynx)) => self.comlynx.draw(f, panel_area),
+                        _ => (),
+                    }
+                    screen_area
+                }
+            };
+
+            let highlight = match self.inspector {
+                Some(Inspector::Sprites) => self.sprites.selected_bounds(),
+                _ => None,
+            };
+            for (n, (player, area)) in self.players.iter().zip(screen_areas(screen_area, self.players.len())).enumerate() {
+                let highlight = highlight.filter(|_| n == 0);
+                f.render_widget(screen_canvas(&player.screen, highlight), area);
             }
-            let Ok(event) = crossterm::event::read() else {
-                return false;
+        }).unwrap();
+    }
+
+    /// Next terminal event within `INPUT_POLL`, `Err` once the remote session is closed.
+    fn next_event(&self) -> Result<Option<Event>, ()> {
+        match &self.events {
+            Some(events) => match events.recv_timeout(INPUT_POLL) {
+                Ok(event) => Ok(Some(event)),
+                Err(kanal::ReceiveErrorTimeout::Timeout) => Ok(None),
+                Err(_) => Err(()),
+            },
+            None => match crossterm::event::poll(INPUT_POLL) {
+                Ok(true) => Ok(crossterm::event::read().ok()),
+                _ => Ok(None),
+            },
+        }
+    }
+
+    fn read_inputs(&mut self) -> bool {
+        loop {
+            let event = match self.next_event() {
+                Err(()) => return true,
+                Ok(None) => return false,
+                Ok(Some(event)) => event,
             };
+            if let Event::Resize(_, _) = event {
+                self.redraw = true;
+            }
             if let Event::Key(input) = event {
                 let code = input.code;
                 let pressed = input.kind == KeyEventKind::Press || input.kind == KeyEventKind::Repeat;
                 if !pressed {
                     return false;
                 }
+                if self.debugger.handle_key(code) {
+                    self.redraw = true;
+                    continue;
+                }
+                let consumed = match self.inspector {
+                    Some(Inspector::Sprites) => self.sprites.handle_key(code),
+                    Some(Inspector::Video) => self.video.handle_key(code),
+                    Some(Inspector::Cheats) => self.cheats.handle_key(code),
+                    Some(Inspector::Profiler) => self.profiler.handle_key(code),
+                    _ => false,
+                };
+                if consumed {
+                    self.redraw = true;
+                    continue;
+                }
                 if code == KeyCode::Esc {
                     return true;
                 }
-                if let Some(btn) = self.config.button_mapping().get_key_value(&code) {
-                    let input = *btn.1;
-                    set_button!(self, input, true);
-                    self.keyboard_frames.insert(input, BUTTON_DECAY);
-                } 
+                if code == DEBUGGER_TOGGLE {
+                    self.set_inspector(None);
+                    self.debugger.toggle();
+                    self.redraw = true;
+                    continue;
+                }
+                if code == INSPECTOR_CYCLE {
+                    self.cycle_inspector();
+                    self.redraw = true;
+                    continue;
+                }
+                if code == TRACE_TOGGLE {
+                    self.debug_tx.send(DebugCommand::ToggleTrace).unwrap();
+                    continue;
+                }
+                if code == AUTOFIRE_TOGGLE {
+                    self.toggle_autofire();
+                    continue;
+                }
+                for player in &mut self.players {
+                    if let Some(btn) = player.button_mapping.get_key_value(&code) {
+                        let input = *btn.1;
+                        let autofire = self.autofire_enabled && self.config.autofire().contains_key(&input);
+                        if !autofire || !player.keyboard_frames.contains_key(&input) {
+                            set_button!(player, input, true);
+                        }
+                        player.keyboard_frames.insert(input, BUTTON_DECAY);
+                    }
+                }
             }
-        }   
-        false
+        }
     }
 
-    fn input_decay(&mut self) {
-        self.keyboard_frames.iter_mut().for_each(|(_, counter)| {
-            *counter = counter.saturating_sub(1);
-        });
+    fn set_inspector(&mut self, inspector: Option<Inspector>) {
+        self.inspector = inspector;
+        self.debug_tx.send(DebugCommand::Inspect(inspector)).unwrap();
+    }
 
-        self.keyboard_frames.retain(|btn, counter| {
-            if *counter == 0 {
-                set_button!(self, *btn, false);
-                false
-            } else {
-                true
+    /// Shows the inspector panels in turn, then none.
+    fn cycle_inspector(&mut self) {
+        if self.debugger.visible() {
+            self.debugger.toggle();
+        }
+        let next = match self.inspector {
+            None => INSPECTORS.first().copied(),
+            Some(current) => INSPECTORS.iter().skip_while(|i| **i != current).nth(1).copied(),
+        };
+        self.set_inspector(next);
+    }
+
+    fn toggle_autofire(&mut self) {
+        self.autofire_enabled = !self.autofire_enabled;
+        for player in &mut self.players {
+            for btn in player.keyboard_frames.keys() {
+                btn.apply(&mut player.joystick, &mut player.switches, true);
             }
-        });
+        }
     }
 
     pub fn handle_keyboard(&mut self) -> bool {
-        let sw = self.switches;
-        let joy = self.joystick;
-
         let exit_requested = self.read_inputs();
-
-        if joy != self.joystick || sw != self.switches {
-            self.input_tx.send((self.joystick.bits(), self.switches.bits())).unwrap();
-        }
-
+        self.players.iter_mut().for_each(Player::send_inputs);
         exit_requested
     }
-}
\ No newline at end of file
+}
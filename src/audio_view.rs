@@ -0,0 +1,94 @@
+use ratatui::{layout::{Constraint, Layout, Rect}, style::{Color, Style}, symbols::Marker, text::Line, widgets::{Axis, Block, Chart, Dataset, GraphType, Paragraph}, Frame};
+
+use crate::{registers::*, runner::debugger::AudioState};
+
+const CHANNEL_COLORS: [Color; AUDIO_CHANNELS as usize] = [Color::Green, Color::Yellow, Color::Cyan, Color::Magenta];
+const LEFT_COLOR: Color = Color::LightBlue;
+const RIGHT_COLOR: Color = Color::LightRed;
+
+fn points(samples: impl Iterator<Item = f64>) -> Vec<(f64, f64)> {
+    samples.enumerate().map(|(x, y)| (x as f64, y)).collect()
+}
+
+fn chart<'a>(title: Line<'a>, datasets: Vec<Dataset<'a>>, len: usize, range: f64) -> Chart<'a> {
+    Chart::new(datasets)
+        .block(Block::bordered().title(title))
+        .x_axis(Axis::default().bounds([0., len.max(1) as f64]))
+        .y_axis(Axis::default().bounds([-range, range]))
+}
+
+/// Oscilloscope of the four audio channels and the stereo output, with each channel mixer settings.
+pub(crate) struct AudioView {
+    state: Option<AudioState>,
+}
+
+impl AudioView {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: None,
+        }
+    }
+
+    pub(crate) fn update(&mut self, state: AudioState) {
+        self.state = Some(state);
+    }
+
+    pub(crate) fn draw(&self, f: &mut Frame, area: Rect) {
+        let Some(state) = &self.state else {
+            f.render_widget(Paragraph::new("Waiting for the core..."), area);
+            return;
+        };
+
+        let hw = &state.hardware;
+        let len = state.output.len();
+        let areas = Layout::vertical([Constraint::Ratio(1, AUDIO_CHANNELS as u32 + 1); AUDIO_CHANNELS as usize + 1]).split(area);
+
+        let channels: Vec<Vec<(f64, f64)>> = (0..AUDIO_CHANNELS as usize)
+            .map(|n| points(state.channels.iter().map(|c| c[n] as f64)))
+            .collect();
+        for (n, data) in channels.iter().enumerate() {
+            let base = AUDIO + n as u16 * AUDIO_LEN;
+            let ctl = hw.byte(base + AUD_CTL);
+            let backup = hw.byte(base + AUD_TBACK);
+            let (left, right) = attenuation(hw.byte(ATTEN_A + n as u16));
+            let panned = hw.byte(MPAN) & (0x11 << n) != 0;
+            // MSTEREO bits disable a channel on the left (high nibble) or right (low nibble) output.
+            let mstereo = hw.byte(MSTEREO);
+            let enabled = |bit: u8| match mstereo & (bit << n) == 0 {
+                true => "on",
+                false => "off",
+            };
+            let title = Line::styled(
+                format!(
+                    "Ch{} vol {} {} {} bkup {:02X} L {} {:X} R {} {:X}{}",
+                    n,
+                    hw.byte(base + AUD_VOL) as i8,
+                    audio_frequency(ctl, backup).map(|hz| format!("{:.0}Hz", hz)).unwrap_or_else(|| "-".to_string()),
+                    clock_name(ctl),
+                    backup,
+                    enabled(0x10),
+                    left,
+                    enabled(0x01),
+                    right,
+                    if panned { " pan" } else { "" },
+                ),
+                Style::default().fg(CHANNEL_COLORS[n]),
+            );
+            let dataset = Dataset::default()
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(CHANNEL_COLORS[n]))
+                .data(data);
+            f.render_widget(chart(title, vec![dataset], len, i8::MAX as f64), areas[n]);
+        }
+
+        let left = points(state.output.iter().map(|(l, _)| *l as f64));
+        let right = points(state.output.iter().map(|(_, r)| *r as f64));
+        let datasets = vec![
+            Dataset::default().name("L").marker(Marker::Braille).graph_type(GraphType::Line).style(Style::default().fg(LEFT_COLOR)).data(&left),
+            Dataset::default().name("R").marker(Marker::Braille).graph_type(GraphType::Line).style(Style::default().fg(RIGHT_COLOR)).data(&right),
+        ];
+        let title = Line::raw(format!("Output MSTEREO:{:02X} MPAN:{:02X}", hw.byte(MSTEREO), hw.byte(MPAN)));
+        f.render_widget(chart(title, datasets, len, i16::MAX as f64), areas[AUDIO_CHANNELS as usize]);
+    }
+}
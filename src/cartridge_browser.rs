@@ -0,0 +1,233 @@
+use std::{collections::HashMap, path::{Path, PathBuf}};
+use ratatui::{crossterm::{self, event::{Event, KeyCode, KeyEventKind}}, layout::{Constraint, Layout}, prelude::Backend, style::{Color, Modifier, Style}, text::Line, widgets::{Block, Paragraph}, Frame, Terminal};
+
+use crate::{cartridge_info::CartridgeInfo, runner::archive::{self, is_archive, ARCHIVE_EXTENSIONS}};
+
+pub(crate) const CARTRIDGE_EXTENSIONS: [&str; 3] = ["lnx", "o", "lyx"];
+const PAGE: usize = 10;
+
+/// Cartridge image extension, case insensitive.
+pub(crate) fn is_cartridge(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| CARTRIDGE_EXTENSIONS.iter().any(|c| ext.eq_ignore_ascii_case(c)))
+}
+
+struct Entry {
+    name: String,
+    path: PathBuf,
+    /// Image in the archive `path`.
+    member: Option<String>,
+    dir: bool,
+}
+
+/// Picks the cartridge to run when none was given, with the header of the selected one.
+///
+/// Enter opens a directory or runs the cartridge, Backspace goes to the parent directory, Esc quits.
+/// Archives are opened as directories when they hold several images.
+pub(crate) struct CartridgeBrowser {
+    dir: PathBuf,
+    entries: Vec<Entry>,
+    selected: usize,
+    info: Option<Result<CartridgeInfo, String>>,
+    message: String,
+    /// Images of the archives listed so far.
+    archives: HashMap<PathBuf, Vec<String>>,
+}
+
+impl CartridgeBrowser {
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        let mut browser = Self {
+            dir: PathBuf::new(),
+            entries: vec![],
+            selected: 0,
+            info: None,
+            message: String::new(),
+            archives: HashMap::new(),
+        };
+        browser.open(dir);
+        browser
+    }
+
+    /// Lists the sub-directories, cartridges and archives of `dir`, hidden ones left out, or the images
+    /// of the archive `dir`.
+    fn open(&mut self, dir: PathBuf) {
+        let dir = dir.canonicalize().unwrap_or(dir);
+        if dir.is_file() {
+            self.open_archive(dir);
+            return;
+        }
+        let read = match std::fs::read_dir(&dir) {
+            Ok(read) => read,
+            Err(err) => {
+                self.message = format!("{}: {}", dir.display(), err);
+                return;
+            }
+        };
+        let mut entries: Vec<Entry> = read
+            .flatten()
+            .map(|entry| Entry {
+                name: entry.file_name().to_string_lossy().to_string(),
+                dir: entry.path().is_dir(),
+                path: entry.path(),
+                member: None,
+            })
+            .filter(|entry| !entry.name.starts_with('.') && (entry.dir || is_cartridge(&entry.path) || is_archive(&entry.path)))
+            .collect();
+        entries.sort_by(|a, b| b.dir.cmp(&a.dir).then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase())));
+        if let Some(parent) = dir.parent() {
+            entries.insert(0, Entry { name: "..".to_string(), path: parent.to_path_buf(), member: None, dir: true });
+        }
+        self.show(dir, entries);
+    }
+
+    /// Images of the archive `path`, listed once.
+    fn images(&mut self, path: &Path) -> Result<Vec<String>, String> {
+        if let Some(images) = self.archives.get(path) {
+            return Ok(images.clone());
+        }
+        let images = archive::images(path, is_cartridge)?;
+        self.archives.insert(path.to_path_buf(), images.clone());
+        Ok(images)
+    }
+
+    fn open_archive(&mut self, path: PathBuf) {
+        let images = match self.images(&path) {
+            Ok(images) => images,
+            Err(err) => {
+                self.message = err;
+                return;
+            }
+        };
+        let mut entries: Vec<Entry> = images.into_iter()
+            .map(|name| Entry { name: name.clone(), path: path.clone(), member: Some(name), dir: false })
+            .collect();
+        entries.sort_by_key(|entry| entry.name.to_lowercase());
+        if let Some(parent) = path.parent() {
+            entries.insert(0, Entry { name: "..".to_string(), path: parent.to_path_buf(), member: None, dir: true });
+        }
+        self.show(path, entries);
+    }
+
+    fn show(&mut self, dir: PathBuf, entries: Vec<Entry>) {
+        self.dir = dir;
+        self.entries = entries;
+        self.selected = 0;
+        self.message.clear();
+        self.preview();
+    }
+
+    fn preview(&mut self) {
+        let Some((path, member)) = self.entries.get(self.selected)
+            .filter(|entry| !entry.dir)
+            .map(|entry| (entry.path.clone(), entry.member.clone()))
+        else {
+            self.info = None;
+            return;
+        };
+        // The only image of an archive, reading the archive itself reports none or several.
+        let member = match member {
+            None if is_archive(&path) => self.images(&path).ok().filter(|images| images.len() == 1).and_then(|images| images.into_iter().next()),
+            member => member,
+        };
+        self.info = Some(CartridgeInfo::read(&path, member.as_deref()));
+    }
+
+    /// Runs an archive holding a single image, lists its images otherwise.
+    fn open_or_run(&mut self, path: PathBuf) -> Option<(PathBuf, Option<String>)> {
+        match self.images(&path) {
+            Ok(images) if images.len() == 1 => Some((path, images.into_iter().next())),
+            Ok(images) if images.is_empty() => {
+                self.message = format!("{}: no Lynx image in the archive.", path.display());
+                None
+            }
+            Ok(_) => {
+                self.open_archive(path);
+                None
+            }
+            Err(err) => {
+                self.message = err;
+                None
+            }
+        }
+    }
+
+    fn select(&mut self, selected: usize) {
+        self.selected = selected.min(self.entries.len().saturating_sub(1));
+        self.preview();
+    }
+
+    /// Runs until a cartridge is chosen, with the image to load when it's an archive, `None` on Esc.
+    pub(crate) fn run<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Option<(PathBuf, Option<String>)> {
+        loop {
+            terminal.draw(|f| self.draw(f)).unwrap();
+            let Ok(Event::Key(input)) = crossterm::event::read() else {
+                continue;
+            };
+            if input.kind != KeyEventKind::Press {
+                continue;
+            }
+            match input.code {
+                KeyCode::Esc => return None,
+                KeyCode::Up => self.select(self.selected.saturating_sub(1)),
+                KeyCode::Down => self.select(self.selected + 1),
+                KeyCode::PageUp => self.select(self.selected.saturating_sub(PAGE)),
+                KeyCode::PageDown => self.select(self.selected + PAGE),
+                KeyCode::Home => self.select(0),
+                KeyCode::End => self.select(usize::MAX),
+                KeyCode::Backspace | KeyCode::Left => {
+                    if let Some(parent) = self.dir.parent() {
+                        self.open(parent.to_path_buf());
+                    }
+                }
+                KeyCode::Enter | KeyCode::Right => match self.entries.get(self.selected) {
+                    Some(entry) if entry.dir => self.open(entry.path.clone()),
+                    Some(entry) if input.code == KeyCode::Right && entry.member.is_none() && is_archive(&entry.path) => self.open(entry.path.clone()),
+                    Some(_) if input.code == KeyCode::Right => (),
+                    Some(entry) if entry.member.is_none() && is_archive(&entry.path) => {
+                        if let Some(cartridge) = self.open_or_run(entry.path.clone()) {
+                            return Some(cartridge);
+                        }
+                    }
+                    Some(entry) => return Some((entry.path.clone(), entry.member.clone())),
+                    None => (),
+                },
+                _ => (),
+            }
+        }
+    }
+
+    fn draw(&self, f: &mut Frame) {
+        let [title, main, status] = Layout::vertical([Constraint::Length(1), Constraint::Fill(1), Constraint::Length(1)]).areas(f.area());
+        let [list, preview] = Layout::horizontal([Constraint::Percentage(50), Constraint::Fill(1)]).areas(main);
+
+        f.render_widget(Paragraph::new(format!("Select a cartridge ({}, {}) - {}", CARTRIDGE_EXTENSIONS.join(", "), ARCHIVE_EXTENSIONS.join(", "), self.dir.display())), title);
+
+        let height = list.height.saturating_sub(2) as usize;
+        let first = self.selected.saturating_sub(height.saturating_sub(1));
+        let lines: Vec<Line> = self.entries.iter().enumerate().skip(first).take(height).map(|(i, entry)| {
+            let (text, style) = match entry.dir {
+                true => (format!("{}/", entry.name), Style::default().fg(Color::Cyan)),
+                false => (entry.name.clone(), Style::default()),
+            };
+            match i == self.selected {
+                true => Line::styled(text, style.add_modifier(Modifier::REVERSED)),
+                false => Line::styled(text, style),
+            }
+        }).collect();
+        f.render_widget(Paragraph::new(lines).block(Block::bordered().title("Files")), list);
+
+        let lines: Vec<Line> = match &self.info {
+            None => vec![],
+            Some(Ok(info)) => info.lines().into_iter().map(Line::raw).collect(),
+            Some(Err(err)) => vec![Line::styled(err.clone(), Style::default().fg(Color::Red))],
+        };
+        f.render_widget(Paragraph::new(lines).block(Block::bordered().title("Cartridge")), preview);
+
+        let help = match self.message.is_empty() {
+            true => "Enter: open/run  Backspace: parent  Esc: quit".to_string(),
+            false => self.message.clone(),
+        };
+        f.render_widget(Paragraph::new(help), status);
+    }
+}
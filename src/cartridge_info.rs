@@ -0,0 +1,164 @@
+use std::path::Path;
+
+use crate::{cartridge_browser::is_cartridge, runner::archive};
+
+const LNX_MAGIC: &[u8; 4] = b"LYNX";
+const LNX_HEADER_LEN: usize = 64;
+/// BLL homebrew `.o` header, load address and length follow big endian.
+const BLL_MAGIC: [u8; 2] = [0x80, 0x08];
+const BLL_HEADER_LEN: usize = 10;
+const EEPROMS: [&str; 6] = ["none", "93C46", "93C56", "93C66", "93C76", "93C86"];
+
+/// NUL padded header string.
+fn header_str(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+pub(crate) enum CartridgeFormat {
+    Lnx {
+        name: String,
+        manufacturer: String,
+        version: u16,
+        /// Bank sizes in bytes.
+        bank0: u32,
+        bank1: u32,
+        rotation: &'static str,
+        eeprom: &'static str,
+    },
+    /// BLL homebrew program.
+    Homebrew {
+        load: u16,
+        length: u16,
+    },
+    /// Headerless `.lyx` image.
+    Raw,
+}
+
+/// Cartridge image description, from its header.
+pub(crate) struct CartridgeInfo {
+    pub size: u64,
+    pub format: CartridgeFormat,
+}
+
+impl CartridgeInfo {
+    /// Of the file `path`, or of the image `member` of the archive `path`, only the header is read.
+    pub(crate) fn read(path: &Path, member: Option<&str>) -> Result<Self, String> {
+        let (header, size) = archive::read_header(path, member, is_cartridge, LNX_HEADER_LEN as u64)?;
+        let header = header.as_slice();
+
+        let word = |offset: usize| u16::from_le_bytes([header[offset], header[offset + 1]]);
+        let format = if header.len() == LNX_HEADER_LEN && header.starts_with(LNX_MAGIC) {
+            CartridgeFormat::Lnx {
+                name: header_str(&header[10..42]),
+                manufacturer: header_str(&header[42..58]),
+                version: word(8),
+                bank0: word(4) as u32 * 256,
+                bank1: word(6) as u32 * 256,
+                rotation: match header[58] {
+                    1 => "left",
+                    2 => "right",
+                    _ => "none",
+                },
+                eeprom: EEPROMS.get((header[60] & 0x07) as usize).copied().unwrap_or("unknown"),
+            }
+        } else if header.len() >= BLL_HEADER_LEN && header.starts_with(&BLL_MAGIC) {
+            CartridgeFormat::Homebrew {
+                load: u16::from_be_bytes([header[2], header[3]]),
+                length: u16::from_be_bytes([header[4], header[5]]),
+            }
+        } else {
+            CartridgeFormat::Raw
+        };
+        Ok(Self { size, format })
+    }
+
+    /// Header description, a line per field.
+    pub(crate) fn lines(&self) -> Vec<String> {
+        let mut lines = match &self.format {
+            CartridgeFormat::Lnx { name, manufacturer, version, bank0, bank1, rotation, eeprom } => vec![
+                "Format: LNX".to_string(),
+                format!("Title: {}", name),
+                format!("Manufacturer: {}", manufacturer),
+                format!("Version: {}", version),
+                format!("Bank 0: {} KB", bank0 / 1024),
+                format!("Bank 1: {} KB", bank1 / 1024),
+                format!("Rotation: {}", rotation),
+                format!("EEPROM: {}", eeprom),
+            ],
+            CartridgeFormat::Homebrew { load, length } => vec![
+                "Format: BLL homebrew".to_string(),
+                format!("Load address: ${:04X}", load),
+                format!("Length: {} bytes", length),
+            ],
+            CartridgeFormat::Raw => vec!["Format: raw image, no header".to_string()],
+        };
+        lines.push(format!("File size: {} KB", self.size.div_ceil(1024)));
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::*;
+
+    fn lnx() -> Vec<u8> {
+        let mut data = vec![0; LNX_HEADER_LEN + 0x400];
+        data[..4].copy_from_slice(LNX_MAGIC);
+        data[4..6].copy_from_slice(&0x200u16.to_le_bytes());
+        data[8..10].copy_from_slice(&1u16.to_le_bytes());
+        data[10..15].copy_from_slice(b"Title");
+        data[42..47].copy_from_slice(b"Maker");
+        data[58] = 2;
+        data[60] = 1;
+        data
+    }
+
+    #[test]
+    fn reads_headers() {
+        let dir = std::env::temp_dir().join(format!("holani-cartridge-info-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("game.lnx");
+        std::fs::write(&path, lnx()).unwrap();
+        let info = CartridgeInfo::read(&path, None).unwrap();
+        assert_eq!(info.size, (LNX_HEADER_LEN + 0x400) as u64);
+        let CartridgeFormat::Lnx { name, manufacturer, version, bank0, bank1, rotation, eeprom } = info.format else {
+            panic!("not LNX");
+        };
+        assert_eq!((name.as_str(), manufacturer.as_str(), version), ("Title", "Maker", 1));
+        assert_eq!((bank0, bank1, rotation, eeprom), (0x20000, 0, "right", "93C46"));
+
+        let path = dir.join("game.o");
+        std::fs::write(&path, [0x80, 0x08, 0x04, 0x00, 0x00, 0x10, 0, 0, 0, 0, 0xea]).unwrap();
+        let info = CartridgeInfo::read(&path, None).unwrap();
+        assert!(matches!(info.format, CartridgeFormat::Homebrew { load: 0x400, length: 0x10 }));
+        assert_eq!(info.size, 11);
+
+        let path = dir.join("game.lyx");
+        std::fs::write(&path, b"LYNX").unwrap();
+        assert!(matches!(CartridgeInfo::read(&path, None).unwrap().format, CartridgeFormat::Raw));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reads_archived_header() {
+        let dir = std::env::temp_dir().join(format!("holani-cartridge-info-gz-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("game.lnx.gz");
+        let mut gz = GzEncoder::new(vec![], Compression::default());
+        gz.write_all(&lnx()).unwrap();
+        std::fs::write(&path, gz.finish().unwrap()).unwrap();
+
+        let info = CartridgeInfo::read(&path, None).unwrap();
+        assert_eq!(info.size, (LNX_HEADER_LEN + 0x400) as u64);
+        assert!(matches!(info.format, CartridgeFormat::Lnx { .. }));
+        assert!(CartridgeInfo::read(&path, Some("other.lnx")).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,165 @@
+use std::path::PathBuf;
+use ratatui::{crossterm::event::KeyCode, layout::{Constraint, Layout, Rect}, style::{Color, Style}, text::{Line, Span}, widgets::{Block, Paragraph}, Frame};
+
+use crate::{debugger_view::parse_address, runner::{cheats::{save_cheats, Cheat, SearchFilter}, debugger::{CheatsState, DebugCommand}}};
+
+const MAX_CHEAT_LINES: usize = 8;
+
+/// Memory search and cheats.
+///
+/// Prompt commands: `n` new search, `= <value>`, `c` changed, `u` unchanged, `+` increased, `-` decreased
+/// narrow it down, `f <addr> [value]` freeze, `p <addr> <value>` poke once, `t <id>` enable/disable,
+/// `d <id>` delete, `w` save the cheats file.
+pub(crate) struct CheatsView {
+    state: Option<CheatsState>,
+    prompt: Option<String>,
+    message: String,
+    command_tx: kanal::Sender<DebugCommand>,
+    path: Option<PathBuf>,
+}
+
+impl CheatsView {
+    pub(crate) fn new(command_tx: kanal::Sender<DebugCommand>, path: Option<PathBuf>) -> Self {
+        Self {
+            state: None,
+            prompt: None,
+            message: String::new(),
+            command_tx,
+            path,
+        }
+    }
+
+    pub(crate) fn update(&mut self, state: CheatsState) {
+        self.state = Some(state);
+    }
+
+    fn send(&self, command: DebugCommand) {
+        self.command_tx.send(command).unwrap();
+    }
+
+    /// Returns `true` if the key was consumed.
+    pub(crate) fn handle_key(&mut self, code: KeyCode) -> bool {
+        if let Some(prompt) = self.prompt.as_mut() {
+            match code {
+                KeyCode::Char(c) => prompt.push(c),
+                KeyCode::Backspace => {
+                    prompt.pop();
+                }
+                KeyCode::Enter => {
+                    let line = self.prompt.take().unwrap_or_default();
+                    self.execute(&line);
+                }
+                KeyCode::Esc => self.prompt = None,
+                _ => (),
+            }
+            return true;
+        }
+
+        match code {
+            KeyCode::Char(':') => self.prompt = Some(String::new()),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Current value of a search result.
+    fn current_value(&self, addr: u16) -> Option<u8> {
+        self.state.as_ref()?.results.iter().find(|(a, _, _)| *a == addr).map(|(_, _, value)| *value)
+    }
+
+    fn execute(&mut self, line: &str) {
+        let args: Vec<&str> = line.split_whitespace().collect();
+        let Some((cmd, args)) = args.split_first() else {
+            return;
+        };
+        let addr = args.first().and_then(|a| parse_address(a));
+        let value = args.get(1).and_then(|v| parse_address(v)).and_then(|v| u8::try_from(v).ok());
+        let id = args.first().and_then(|id| id.parse::<u32>().ok());
+
+        let command = match (*cmd, addr, value, id) {
+            ("n", _, _, _) => DebugCommand::Search(None),
+            ("c", _, _, _) => DebugCommand::Search(Some(SearchFilter::Changed)),
+            ("u", _, _, _) => DebugCommand::Search(Some(SearchFilter::Unchanged)),
+            ("+", _, _, _) => DebugCommand::Search(Some(SearchFilter::Increased)),
+            ("-", _, _, _) => DebugCommand::Search(Some(SearchFilter::Decreased)),
+            ("=", Some(v), _, _) if v <= u8::MAX as u16 => DebugCommand::Search(Some(SearchFilter::Exact(v as u8))),
+            ("f", Some(addr), _, _) => match value.or_else(|| self.current_value(addr)) {
+                Some(value) => DebugCommand::AddCheat(Cheat::new(addr, value)),
+                None => {
+                    self.message = format!("${:04X} isn't a search result, a value is needed.", addr);
+                    return;
+                }
+            },
+            ("p", Some(addr), Some(value), _) => DebugCommand::WriteMemory(addr, vec![value]),
+            ("t", _, _, Some(id)) => DebugCommand::ToggleCheat(id),
+            ("d", _, _, Some(id)) => DebugCommand::RemoveCheat(id),
+            ("w", _, _, _) => {
+                self.save();
+                return;
+            }
+            ("=" | "f" | "p" | "t" | "d", _, _, _) => {
+                self.message = format!("Invalid arguments for '{}'.", cmd);
+                return;
+            }
+            _ => {
+                self.message = format!("Unknown command '{}'.", cmd);
+                return;
+            }
+        };
+
+        self.message.clear();
+        self.send(command);
+    }
+
+    fn save(&mut self) {
+        let (Some(path), Some(state)) = (&self.path, &self.state) else {
+            return;
+        };
+        self.message = match save_cheats(path, &state.cheats) {
+            Ok(()) => format!("Saved to {}.", path.display()),
+            Err(err) => err,
+        };
+    }
+
+    pub(crate) fn draw(&self, f: &mut Frame, area: Rect) {
+        let Some(state) = &self.state else {
+            f.render_widget(Paragraph::new("Waiting for the core..."), area);
+            return;
+        };
+
+        let cheats_height = state.cheats.len().clamp(1, MAX_CHEAT_LINES) as u16 + 2;
+        let [results, cheats, status] = Layout::vertical([
+            Constraint::Fill(1),
+            Constraint::Length(cheats_height),
+            Constraint::Length(1),
+        ]).areas(area);
+
+        let title = match state.candidates {
+            None => "Search - ':n' to start".to_string(),
+            Some(n) => format!("Search - {} candidates", n),
+        };
+        let mut lines = vec![Line::styled("addr  prev cur", Style::default().fg(Color::Cyan))];
+        lines.extend(state.results.iter().map(|(addr, previous, current)| {
+            let style = match previous == current {
+                true => Style::default(),
+                false => Style::default().fg(Color::Yellow),
+            };
+            Line::styled(format!("{:04X}  {:02X}   {:02X} ({})", addr, previous, current, current), style)
+        }));
+        f.render_widget(Paragraph::new(lines).block(Block::bordered().title(title)), results);
+
+        let lines: Vec<Line> = match state.cheats.is_empty() {
+            true => vec![Line::raw("No cheats, ':f <addr> [value]' to add one.")],
+            false => state.cheats.iter().take(MAX_CHEAT_LINES).map(|cheat| {
+                Line::raw(format!("{:>3} {:04X} = {:02X} {}", cheat.id, cheat.address, cheat.value, if cheat.enabled { "on" } else { "off" }))
+            }).collect(),
+        };
+        f.render_widget(Paragraph::new(lines).block(Block::bordered().title("Cheats")), cheats);
+
+        let status_line = match &self.prompt {
+            Some(prompt) => Line::from(vec![Span::styled(":", Style::default().fg(Color::Yellow)), Span::raw(prompt.clone())]),
+            None => Line::raw(self.message.clone()),
+        };
+        f.render_widget(Paragraph::new(status_line), status);
+    }
+}
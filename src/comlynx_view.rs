@@ -0,0 +1,88 @@
+use ratatui::{layout::{Constraint, Layout, Rect}, style::{Color, Style}, text::{Line, Span}, widgets::{Block, Paragraph}, Frame};
+
+use crate::runner::{comlynx_sniffer::{Parity, SerialTransfer}, debugger::ComlynxState};
+
+const TX_COLOR: Color = Color::Yellow;
+const RX_COLOR: Color = Color::Cyan;
+const ERROR_COLOR: Color = Color::Red;
+const DUMP_BYTES: usize = 8;
+const DUMP_HEIGHT: u16 = 10;
+
+fn style(transfer: &SerialTransfer) -> Style {
+    match transfer.transmit {
+        true => Style::default().fg(TX_COLOR),
+        false => Style::default().fg(RX_COLOR),
+    }
+}
+
+/// Comlynx bytes sent and received, as a list and a hex/ASCII dump.
+pub(crate) struct ComlynxView {
+    state: Option<ComlynxState>,
+}
+
+impl ComlynxView {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: None,
+        }
+    }
+
+    pub(crate) fn update(&mut self, state: ComlynxState) {
+        self.state = Some(state);
+    }
+
+    pub(crate) fn draw(&self, f: &mut Frame, area: Rect) {
+        let Some(state) = &self.state else {
+            f.render_widget(Paragraph::new("Waiting for the core..."), area);
+            return;
+        };
+
+        let [list, dump] = Layout::vertical([
+            Constraint::Fill(1),
+            Constraint::Length(DUMP_HEIGHT),
+        ]).areas(area);
+
+        let title = format!(
+            "Comlynx {} - SERCTL written:{:02X} ({}) read:{:02X}",
+            match state.linked {
+                true => "link",
+                false => "SERDAT stores and reads",
+            },
+            state.control,
+            Parity::new(state.control).name(),
+            state.status,
+        );
+        let rows = list.height.saturating_sub(3) as usize;
+        let mut lines = vec![Line::styled(format!("{:>12} dir data    9th errors", "tick"), Style::default().fg(Color::Cyan))];
+        lines.extend(state.transfers.iter().skip(state.transfers.len().saturating_sub(rows)).map(|transfer| {
+            let errors = transfer.errors();
+            let errors_style = match errors.as_str() {
+                "-" => Style::default(),
+                _ => Style::default().fg(ERROR_COLOR),
+            };
+            Line::from(vec![
+                Span::raw(format!("{:>12} ", transfer.tick)),
+                Span::styled(format!("{}  {:02X} '{}'  {}  ", transfer.direction(), transfer.data, transfer.ascii(), transfer.bit9 as u8), style(transfer)),
+                Span::styled(errors, errors_style),
+            ])
+        }));
+        f.render_widget(Paragraph::new(lines).block(Block::bordered().title(title)), list);
+
+        let rows = DUMP_HEIGHT.saturating_sub(2) as usize;
+        let chunks: Vec<&[SerialTransfer]> = state.transfers.chunks(DUMP_BYTES).collect();
+        let lines: Vec<Line> = chunks.iter().skip(chunks.len().saturating_sub(rows)).map(|chunk| {
+            let mut spans: Vec<Span> = chunk.iter().map(|t| Span::styled(format!("{:02X} ", t.data), style(t))).collect();
+            spans.push(Span::raw("   ".repeat(DUMP_BYTES - chunk.len())));
+            spans.push(Span::raw(" "));
+            spans.extend(chunk.iter().map(|t| Span::styled(t.ascii().to_string(), style(t))));
+            Line::from(spans)
+        }).collect();
+        let title = Line::from(vec![
+            Span::raw("Bytes "),
+            Span::styled("TX", Style::default().fg(TX_COLOR)),
+            Span::raw(" "),
+            Span::styled("RX", Style::default().fg(RX_COLOR)),
+        ]);
+        f.render_widget(Paragraph::new(lines).block(Block::bordered().title(title)), dump);
+    }
+}
@@ -0,0 +1,267 @@
+use std::sync::Arc;
+use ratatui::{crossterm::event::KeyCode, layout::{Constraint, Layout, Rect}, style::{Color, Modifier, Style}, text::{Line, Span}, widgets::{Block, Paragraph}, Frame};
+
+use crate::{disasm::{decode, disassemble}, runner::{breakpoint::Breakpoint, debugger::{DebugCommand, DebugState}}, symbols::Symbols};
+
+const MEMORY_ROW: usize = 16;
+const MAX_BREAKPOINT_LINES: usize = 6;
+
+pub(crate) fn parse_address(s: &str) -> Option<u16> {
+    let s = s.trim();
+    let hex = s.strip_prefix('$').or_else(|| s.strip_prefix("0x")).unwrap_or(s);
+    u16::from_str_radix(hex, 16).ok()
+}
+
+/// Debugger panels: CPU registers, disassembly around PC, memory hex view and a command prompt.
+///
+/// Keys: `F5` run/pause, `F9` breakpoint at PC, `F10` step over, `F11` step, `:` prompt.
+/// Prompt commands: `b`, `wr`, `ww`, `wa` add a breakpoint or watchpoint (see [`Breakpoint::parse`]),
+/// `d <id>` delete breakpoint, `m <addr>` memory view, `c` continue, `p` pause, `s` step, `n` step over.
+pub(crate) struct DebuggerView {
+    visible: bool,
+    state: Option<DebugState>,
+    prompt: Option<String>,
+    message: String,
+    command_tx: kanal::Sender<DebugCommand>,
+    symbols: Option<Arc<Symbols>>,
+}
+
+impl DebuggerView {
+    pub(crate) fn new(command_tx: kanal::Sender<DebugCommand>, symbols: Option<Arc<Symbols>>) -> Self {
+        Self {
+            visible: false,
+            state: None,
+            prompt: None,
+            message: String::new(),
+            command_tx,
+            symbols,
+        }
+    }
+
+    /// Symbol name or hexadecimal address.
+    fn resolve(&self, s: &str) -> Option<u16> {
+        self.symbols.as_ref().and_then(|symbols| symbols.address(s)).or_else(|| parse_address(s))
+    }
+
+    pub(crate) fn visible(&self) -> bool {
+        self.visible
+    }
+
+    pub(crate) fn toggle(&mut self) {
+        self.visible = !self.visible;
+        self.send(DebugCommand::Attach(self.visible));
+    }
+
+    pub(crate) fn update(&mut self, state: DebugState) {
+        if state.paused && !self.paused() {
+            self.message = state.break_reason.clone()
+                .unwrap_or_else(|| format!("Paused at ${:04X}.", state.registers.pc));
+        }
+        self.state = Some(state);
+    }
+
+    fn paused(&self) -> bool {
+        self.state.as_ref().is_some_and(|s| s.paused)
+    }
+
+    fn send(&self, command: DebugCommand) {
+        self.command_tx.send(command).unwrap();
+    }
+
+    /// Returns `true` if the key was consumed by the debugger.
+    pub(crate) fn handle_key(&mut self, code: KeyCode) -> bool {
+        if let Some(prompt) = self.prompt.as_mut() {
+            match code {
+                KeyCode::Char(c) => prompt.push(c),
+                KeyCode::Backspace => {
+                    prompt.pop();
+                }
+                KeyCode::Enter => {
+                    let line = self.prompt.take().unwrap_or_default();
+                    self.execute(&line);
+                }
+                KeyCode::Esc => self.prompt = None,
+                _ => (),
+            }
+            return true;
+        }
+
+        if !self.visible {
+            return false;
+        }
+
+        match code {
+            KeyCode::F(5) if self.paused() => self.send(DebugCommand::Run),
+            KeyCode::F(5) => self.send(DebugCommand::Pause),
+            KeyCode::F(9) => {
+                if let Some(state) = &self.state {
+                    let pc = state.registers.pc;
+                    match state.breakpoints.iter().find(|bp| bp.exec_address() == Some(pc)) {
+                        Some(bp) => self.send(DebugCommand::RemoveBreakpoint(bp.id)),
+                        None => self.send(DebugCommand::AddBreakpoint(Breakpoint::exec(pc))),
+                    }
+                }
+            }
+            KeyCode::F(10) => self.send(DebugCommand::StepOver),
+            KeyCode::F(11) => self.send(DebugCommand::Step),
+            KeyCode::Char(':') => self.prompt = Some(String::new()),
+            _ => return false,
+        }
+        true
+    }
+
+    fn execute(&mut self, line: &str) {
+        let args: Vec<&str> = line.split_whitespace().collect();
+        let Some((cmd, args)) = args.split_first() else {
+            return;
+        };
+        let addr = args.first().and_then(|a| self.resolve(a));
+
+        let command = match (*cmd, addr) {
+            ("c", _) => DebugCommand::Run,
+            ("p", _) => DebugCommand::Pause,
+            ("s", _) => DebugCommand::Step,
+            ("n", _) => DebugCommand::StepOver,
+            ("b" | "wr" | "ww" | "wa", _) => match Breakpoint::parse(cmd, args, &|s| self.resolve(s)) {
+                Ok(bp) => DebugCommand::AddBreakpoint(bp),
+                Err(err) => {
+                    self.message = err;
+                    return;
+                }
+            },
+            ("d", _) => match args.first().and_then(|id| id.parse::<u32>().ok()) {
+                Some(id) => DebugCommand::RemoveBreakpoint(id),
+                None => {
+                    self.message = "'d' expects a breakpoint id.".to_string();
+                    return;
+                }
+            },
+            ("m", Some(addr)) => DebugCommand::MemoryView(addr),
+            ("m", None) => {
+                self.message = "'m' expects an address.".to_string();
+                return;
+            }
+            _ => {
+                self.message = format!("Unknown command '{}'.", cmd);
+                return;
+            }
+        };
+
+        self.message.clear();
+        self.send(command);
+    }
+
+    pub(crate) fn draw(&self, f: &mut Frame, area: Rect) {
+        let Some(state) = &self.state else {
+            f.render_widget(Paragraph::new("Waiting for the core..."), area);
+            return;
+        };
+
+        let breakpoints_height = match state.breakpoints.len() {
+            0 => 0,
+            n => n.min(MAX_BREAKPOINT_LINES) as u16 + 2,
+        };
+        let [registers, code, breakpoints, memory, status] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Fill(1),
+            Constraint::Length(breakpoints_height),
+            Constraint::Length(10),
+            Constraint::Length(1),
+        ]).areas(area);
+
+        f.render_widget(self.registers_view(state), registers);
+        f.render_widget(self.code_view(state, code.height.saturating_sub(2) as usize), code);
+        f.render_widget(self.breakpoints_view(state), breakpoints);
+        f.render_widget(self.memory_view(state), memory);
+        f.render_widget(self.status_view(), status);
+    }
+
+    fn registers_view(&self, state: &DebugState) -> Paragraph<'_> {
+        let r = &state.registers;
+        let run_state = match state.paused {
+            true => Span::styled(" PAUSED ", Style::default().fg(Color::Black).bg(Color::Yellow)),
+            false => Span::styled(" RUNNING ", Style::default().fg(Color::Black).bg(Color::Green)),
+        };
+
+        let location = self.symbols.as_ref().and_then(|symbols| symbols.describe(r.pc))
+            .map(|name| format!(" {}", name))
+            .unwrap_or_default();
+
+        Paragraph::new(Line::from(vec![
+            Span::raw(format!("PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} S:{:02X} P:{} ", r.pc, r.a, r.x, r.y, r.s, r.flags())),
+            run_state,
+            Span::styled(location, Style::default().fg(Color::Cyan)),
+        ])).block(Block::bordered().title(format!("CPU - frame {}", state.frame)))
+    }
+
+    fn code_view(&self, state: &DebugState, lines: usize) -> Paragraph<'_> {
+        let pc = state.registers.pc;
+        let code = &state.code;
+        let pc_offset = pc.wrapping_sub(state.code_address) as usize;
+
+        // Disassembling backwards is ambiguous, start from the earliest offset whose decoding lands on PC.
+        let start = (0..pc_offset).find(|start| {
+            let mut offset = *start;
+            while offset < pc_offset {
+                offset += decode(code[offset]).size() as usize;
+            }
+            offset == pc_offset
+        }).unwrap_or(pc_offset);
+
+        let mut offset = start;
+        let mut text = vec![];
+        let mut pc_line = 0;
+        while offset + 3 <= code.len() {
+            let addr = state.code_address.wrapping_add(offset as u16);
+            let (ins, len) = disassemble(addr, &code[offset..], self.symbols.as_deref());
+            let bytes: Vec<String> = code[offset..offset + len as usize].iter().map(|b| format!("{:02X}", b)).collect();
+            let breakpoint = state.breakpoints.iter().any(|bp| bp.exec_address() == Some(addr));
+            let marker = match (addr == pc, breakpoint) {
+                (true, true) => "*>",
+                (true, false) => " >",
+                (false, true) => "* ",
+                (false, false) => "  ",
+            };
+            if let Some(label) = self.symbols.as_ref().and_then(|symbols| symbols.name(addr)) {
+                text.push(Line::styled(format!("        {}:", label), Style::default().fg(Color::Cyan)));
+            }
+            let line = format!("{} {:04X}  {:<9} {}", marker, addr, bytes.join(" "), ins);
+            if addr == pc {
+                pc_line = text.len();
+                text.push(Line::styled(line, Style::default().add_modifier(Modifier::REVERSED)));
+            } else {
+                text.push(Line::raw(line));
+            }
+            offset += len as usize;
+        }
+
+        // Keep PC in view, a few instructions above it.
+        let skip = pc_line.saturating_sub(lines / 4);
+        Paragraph::new(text.into_iter().skip(skip).collect::<Vec<_>>()).block(Block::bordered().title("Code"))
+    }
+
+    fn breakpoints_view(&self, state: &DebugState) -> Paragraph<'_> {
+        let text: Vec<Line> = state.breakpoints.iter().map(|bp| Line::raw(bp.to_string())).collect();
+        Paragraph::new(text).block(Block::bordered().title("Breakpoints"))
+    }
+
+    fn memory_view(&self, state: &DebugState) -> Paragraph<'_> {
+        let text: Vec<Line> = state.memory.chunks(MEMORY_ROW).enumerate().map(|(row, bytes)| {
+            let addr = state.memory_address.wrapping_add((row * MEMORY_ROW) as u16);
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            let ascii: String = bytes.iter().map(|b| if b.is_ascii_graphic() { *b as char } else { '.' }).collect();
+            Line::raw(format!("{:04X}: {} {}", addr, hex.join(" "), ascii))
+        }).collect();
+
+        Paragraph::new(text).block(Block::bordered().title("Memory"))
+    }
+
+    fn status_view(&self) -> Paragraph<'_> {
+        match &self.prompt {
+            Some(prompt) => Paragraph::new(format!(":{}", prompt)),
+            None if !self.message.is_empty() => Paragraph::new(self.message.as_str()),
+            None => Paragraph::new("F5 run/pause  F9 breakpoint  F10 step over  F11 step  : command")
+                .style(Style::default().fg(Color::DarkGray)),
+        }
+    }
+}
@@ -0,0 +1,374 @@
+use crate::symbols::Symbols;
+
+/// 65SC02 addressing modes.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Mode {
+    Imp,
+    Acc,
+    Imm,
+    Zp,
+    Zpx,
+    Zpy,
+    Izx,
+    Izy,
+    Izp,
+    Abs,
+    Abx,
+    Aby,
+    Ind,
+    Iax,
+    Rel,
+}
+
+impl Mode {
+    pub(crate) fn size(&self) -> u16 {
+        match self {
+            Mode::Imp | Mode::Acc => 1,
+            Mode::Imm | Mode::Zp | Mode::Zpx | Mode::Zpy | Mode::Izx | Mode::Izy | Mode::Izp | Mode::Rel => 2,
+            Mode::Abs | Mode::Abx | Mode::Aby | Mode::Ind | Mode::Iax => 3,
+        }
+    }
+}
+
+/// Data memory access of an instruction, stack and vector accesses aside.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct Instruction {
+    pub mnemonic: &'static str,
+    pub mode: Mode,
+}
+
+impl Instruction {
+    pub(crate) fn size(&self) -> u16 {
+        self.mode.size()
+    }
+
+    pub(crate) fn access(&self) -> Option<Access> {
+        match self.mode {
+            Mode::Imp | Mode::Acc | Mode::Imm | Mode::Rel | Mode::Ind | Mode::Iax => return None,
+            _ => (),
+        }
+        match self.mnemonic {
+            "JMP" | "JSR" | "NOP" => None,
+            "STA" | "STX" | "STY" | "STZ" => Some(Access::Write),
+            "ASL" | "LSR" | "ROL" | "ROR" | "INC" | "DEC" | "TSB" | "TRB" => Some(Access::ReadWrite),
+            _ => Some(Access::Read),
+        }
+    }
+
+    /// Data address accessed by the instruction, `bytes` starts at the opcode.
+    pub(crate) fn effective_address<F: Fn(u16) -> u8>(&self, bytes: &[u8], x: u8, y: u8, peek: F) -> Option<u16> {
+        let byte = |i: usize| bytes.get(i).copied().unwrap_or(0);
+        let zp = byte(1);
+        let abs = u16::from_le_bytes([byte(1), byte(2)]);
+        let zp_word = |addr: u8| u16::from_le_bytes([peek(addr as u16), peek(addr.wrapping_add(1) as u16)]);
+
+        self.access()?;
+
+        Some(match self.mode {
+            Mode::Zp => zp as u16,
+            Mode::Zpx => zp.wrapping_add(x) as u16,
+            Mode::Zpy => zp.wrapping_add(y) as u16,
+            Mode::Izx => zp_word(zp.wrapping_add(x)),
+            Mode::Izy => zp_word(zp).wrapping_add(y as u16),
+            Mode::Izp => zp_word(zp),
+            Mode::Abs => abs,
+            Mode::Abx => abs.wrapping_add(x as u16),
+            Mode::Aby => abs.wrapping_add(y as u16),
+            _ => return None,
+        })
+    }
+}
+
+/// Undefined opcodes with an operand are decoded as `NOP` of their length, the others as 1 byte `???`.
+pub(crate) fn decode(opcode: u8) -> Instruction {
+    let (mnemonic, mode) = match opcode {
+        0x00 => ("BRK", Mode::Imp),
+        0x01 => ("ORA", Mode::Izx),
+        0x04 => ("TSB", Mode::Zp),
+        0x05 => ("ORA", Mode::Zp),
+        0x06 => ("ASL", Mode::Zp),
+        0x08 => ("PHP", Mode::Imp),
+        0x09 => ("ORA", Mode::Imm),
+        0x0a => ("ASL", Mode::Acc),
+        0x0c => ("TSB", Mode::Abs),
+        0x0d => ("ORA", Mode::Abs),
+        0x0e => ("ASL", Mode::Abs),
+        0x10 => ("BPL", Mode::Rel),
+        0x11 => ("ORA", Mode::Izy),
+        0x12 => ("ORA", Mode::Izp),
+        0x14 => ("TRB", Mode::Zp),
+        0x15 => ("ORA", Mode::Zpx),
+        0x16 => ("ASL", Mode::Zpx),
+        0x18 => ("CLC", Mode::Imp),
+        0x19 => ("ORA", Mode::Aby),
+        0x1a => ("INC", Mode::Acc),
+        0x1c => ("TRB", Mode::Abs),
+        0x1d => ("ORA", Mode::Abx),
+        0x1e => ("ASL", Mode::Abx),
+        0x20 => ("JSR", Mode::Abs),
+        0x21 => ("AND", Mode::Izx),
+        0x24 => ("BIT", Mode::Zp),
+        0x25 => ("AND", Mode::Zp),
+        0x26 => ("ROL", Mode::Zp),
+        0x28 => ("PLP", Mode::Imp),
+        0x29 => ("AND", Mode::Imm),
+        0x2a => ("ROL", Mode::Acc),
+        0x2c => ("BIT", Mode::Abs),
+        0x2d => ("AND", Mode::Abs),
+        0x2e => ("ROL", Mode::Abs),
+        0x30 => ("BMI", Mode::Rel),
+        0x31 => ("AND", Mode::Izy),
+        0x32 => ("AND", Mode::Izp),
+        0x34 => ("BIT", Mode::Zpx),
+        0x35 => ("AND", Mode::Zpx),
+        0x36 => ("ROL", Mode::Zpx),
+        0x38 => ("SEC", Mode::Imp),
+        0x39 => ("AND", Mode::Aby),
+        0x3a => ("DEC", Mode::Acc),
+        0x3c => ("BIT", Mode::Abx),
+        0x3d => ("AND", Mode::Abx),
+        0x3e => ("ROL", Mode::Abx),
+        0x40 => ("RTI", Mode::Imp),
+        0x41 => ("EOR", Mode::Izx),
+        0x45 => ("EOR", Mode::Zp),
+        0x46 => ("LSR", Mode::Zp),
+        0x48 => ("PHA", Mode::Imp),
+        0x49 => ("EOR", Mode::Imm),
+        0x4a => ("LSR", Mode::Acc),
+        0x4c => ("JMP", Mode::Abs),
+        0x4d => ("EOR", Mode::Abs),
+        0x4e => ("LSR", Mode::Abs),
+        0x50 => ("BVC", Mode::Rel),
+        0x51 => ("EOR", Mode::Izy),
+        0x52 => ("EOR", Mode::Izp),
+        0x55 => ("EOR", Mode::Zpx),
+        0x56 => ("LSR", Mode::Zpx),
+        0x58 => ("CLI", Mode::Imp),
+        0x59 => ("EOR", Mode::Aby),
+        0x5a => ("PHY", Mode::Imp),
+        0x5d => ("EOR", Mode::Abx),
+        0x5e => ("LSR", Mode::Abx),
+        0x60 => ("RTS", Mode::Imp),
+        0x61 => ("ADC", Mode::Izx),
+        0x64 => ("STZ", Mode::Zp),
+        0x65 => ("ADC", Mode::Zp),
+        0x66 => ("ROR", Mode::Zp),
+        0x68 => ("PLA", Mode::Imp),
+        0x69 => ("ADC", Mode::Imm),
+        0x6a => ("ROR", Mode::Acc),
+        0x6c => ("JMP", Mode::Ind),
+        0x6d => ("ADC", Mode::Abs),
+        0x6e => ("ROR", Mode::Abs),
+        0x70 => ("BVS", Mode::Rel),
+        0x71 => ("ADC", Mode::Izy),
+        0x72 => ("ADC", Mode::Izp),
+        0x74 => ("STZ", Mode::Zpx),
+        0x75 => ("ADC", Mode::Zpx),
+        0x76 => ("ROR", Mode::Zpx),
+        0x78 => ("SEI", Mode::Imp),
+        0x79 => ("ADC", Mode::Aby),
+        0x7a => ("PLY", Mode::Imp),
+        0x7c => ("JMP", Mode::Iax),
+        0x7d => ("ADC", Mode::Abx),
+        0x7e => ("ROR", Mode::Abx),
+        0x80 => ("BRA", Mode::Rel),
+        0x81 => ("STA", Mode::Izx),
+        0x84 => ("STY", Mode::Zp),
+        0x85 => ("STA", Mode::Zp),
+        0x86 => ("STX", Mode::Zp),
+        0x88 => ("DEY", Mode::Imp),
+        0x89 => ("BIT", Mode::Imm),
+        0x8a => ("TXA", Mode::Imp),
+        0x8c => ("STY", Mode::Abs),
+        0x8d => ("STA", Mode::Abs),
+        0x8e => ("STX", Mode::Abs),
+        0x90 => ("BCC", Mode::Rel),
+        0x91 => ("STA", Mode::Izy),
+        0x92 => ("STA", Mode::Izp),
+        0x94 => ("STY", Mode::Zpx),
+        0x95 => ("STA", Mode::Zpx),
+        0x96 => ("STX", Mode::Zpy),
+        0x98 => ("TYA", Mode::Imp),
+        0x99 => ("STA", Mode::Aby),
+        0x9a => ("TXS", Mode::Imp),
+        0x9c => ("STZ", Mode::Abs),
+        0x9d => ("STA", Mode::Abx),
+        0x9e => ("STZ", Mode::Abx),
+        0xa0 => ("LDY", Mode::Imm),
+        0xa1 => ("LDA", Mode::Izx),
+        0xa2 => ("LDX", Mode::Imm),
+        0xa4 => ("LDY", Mode::Zp),
+        0xa5 => ("LDA", Mode::Zp),
+        0xa6 => ("LDX", Mode::Zp),
+        0xa8 => ("TAY", Mode::Imp),
+        0xa9 => ("LDA", Mode::Imm),
+        0xaa => ("TAX", Mode::Imp),
+        0xac => ("LDY", Mode::Abs),
+        0xad => ("LDA", Mode::Abs),
+        0xae => ("LDX", Mode::Abs),
+        0xb0 => ("BCS", Mode::Rel),
+        0xb1 => ("LDA", Mode::Izy),
+        0xb2 => ("LDA", Mode::Izp),
+        0xb4 => ("LDY", Mode::Zpx),
+        0xb5 => ("LDA", Mode::Zpx),
+        0xb6 => ("LDX", Mode::Zpy),
+        0xb8 => ("CLV", Mode::Imp),
+        0xb9 => ("LDA", Mode::Aby),
+        0xba => ("TSX", Mode::Imp),
+        0xbc => ("LDY", Mode::Abx),
+        0xbd => ("LDA", Mode::Abx),
+        0xbe => ("LDX", Mode::Aby),
+        0xc0 => ("CPY", Mode::Imm),
+        0xc1 => ("CMP", Mode::Izx),
+        0xc4 => ("CPY", Mode::Zp),
+        0xc5 => ("CMP", Mode::Zp),
+        0xc6 => ("DEC", Mode::Zp),
+        0xc8 => ("INY", Mode::Imp),
+        0xc9 => ("CMP", Mode::Imm),
+        0xca => ("DEX", Mode::Imp),
+        0xcc => ("CPY", Mode::Abs),
+        0xcd => ("CMP", Mode::Abs),
+        0xce => ("DEC", Mode::Abs),
+        0xd0 => ("BNE", Mode::Rel),
+        0xd1 => ("CMP", Mode::Izy),
+        0xd2 => ("CMP", Mode::Izp),
+        0xd5 => ("CMP", Mode::Zpx),
+        0xd6 => ("DEC", Mode::Zpx),
+        0xd8 => ("CLD", Mode::Imp),
+        0xd9 => ("CMP", Mode::Aby),
+        0xda => ("PHX", Mode::Imp),
+        0xdd => ("CMP", Mode::Abx),
+        0xde => ("DEC", Mode::Abx),
+        0xe0 => ("CPX", Mode::Imm),
+        0xe1 => ("SBC", Mode::Izx),
+        0xe4 => ("CPX", Mode::Zp),
+        0xe5 => ("SBC", Mode::Zp),
+        0xe6 => ("INC", Mode::Zp),
+        0xe8 => ("INX", Mode::Imp),
+        0xe9 => ("SBC", Mode::Imm),
+        0xea => ("NOP", Mode::Imp),
+        0xec => ("CPX", Mode::Abs),
+        0xed => ("SBC", Mode::Abs),
+        0xee => ("INC", Mode::Abs),
+        0xf0 => ("BEQ", Mode::Rel),
+        0xf1 => ("SBC", Mode::Izy),
+        0xf2 => ("SBC", Mode::Izp),
+        0xf5 => ("SBC", Mode::Zpx),
+        0xf6 => ("INC", Mode::Zpx),
+        0xf8 => ("SED", Mode::Imp),
+        0xf9 => ("SBC", Mode::Aby),
+        0xfa => ("PLX", Mode::Imp),
+        0xfd => ("SBC", Mode::Abx),
+        0xfe => ("INC", Mode::Abx),
+        // Undefined opcodes skipping an operand.
+        0x02 | 0x22 | 0x42 | 0x62 | 0x82 | 0xc2 | 0xe2 => ("NOP", Mode::Imm),
+        0x44 => ("NOP", Mode::Zp),
+        0x54 | 0xd4 | 0xf4 => ("NOP", Mode::Zpx),
+        0x5c | 0xdc | 0xfc => ("NOP", Mode::Abs),
+        _ => ("???", Mode::Imp),
+    };
+    Instruction { mnemonic, mode }
+}
+
+/// Disassembles the instruction at `pc`, `bytes` starts at `pc`, missing operand bytes read as 0.
+/// Operand addresses are replaced by their names if `symbols` has them.
+/// Returns the instruction text and its length.
+pub(crate) fn disassemble(pc: u16, bytes: &[u8], symbols: Option<&Symbols>) -> (String, u16) {
+    let byte = |i: usize| bytes.get(i).copied().unwrap_or(0);
+    let ins = decode(byte(0));
+    let zp = byte(1);
+    let abs = u16::from_le_bytes([byte(1), byte(2)]);
+
+    let name = |addr: u16| symbols.and_then(|s| s.name(addr)).map(str::to_string);
+    let zp_text = name(zp as u16).unwrap_or_else(|| format!("${:02X}", zp));
+    let abs_text = name(abs).unwrap_or_else(|| format!("${:04X}", abs));
+
+    let operand = match ins.mode {
+        Mode::Imp => String::new(),
+        Mode::Acc => "A".to_string(),
+        Mode::Imm => format!("#${:02X}", zp),
+        Mode::Zp => zp_text,
+        Mode::Zpx => format!("{},X", zp_text),
+        Mode::Zpy => format!("{},Y", zp_text),
+        Mode::Izx => format!("({},X)", zp_text),
+        Mode::Izy => format!("({}),Y", zp_text),
+        Mode::Izp => format!("({})", zp_text),
+        Mode::Abs => abs_text,
+        Mode::Abx => format!("{},X", abs_text),
+        Mode::Aby => format!("{},Y", abs_text),
+        Mode::Ind => format!("({})", abs_text),
+        Mode::Iax => format!("({},X)", abs_text),
+        Mode::Rel => {
+            let target = branch_target(pc, zp);
+            name(target).unwrap_or_else(|| format!("${:04X}", target))
+        }
+    };
+
+    let text = match operand.is_empty() {
+        true => ins.mnemonic.to_string(),
+        false => format!("{} {}", ins.mnemonic, operand),
+    };
+
+    (text, ins.size())
+}
+
+pub(crate) fn branch_target(pc: u16, offset: u8) -> u16 {
+    pc.wrapping_add(2).wrapping_add(offset as i8 as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_lengths() {
+        let lengths = [(0xea, 1), (0x0a, 1), (0xa9, 2), (0xb1, 2), (0x72, 2), (0xad, 3), (0x7c, 3), (0x20, 3)];
+        for (opcode, size) in lengths {
+            assert_eq!(decode(opcode).size(), size, "{:02X}", opcode);
+        }
+        for opcode in [0x02, 0x22, 0x42, 0x62, 0x82, 0xc2, 0xe2, 0x44, 0x54, 0xd4, 0xf4] {
+            assert_eq!((decode(opcode).mnemonic, decode(opcode).size()), ("NOP", 2), "{:02X}", opcode);
+        }
+        for opcode in [0x5c, 0xdc, 0xfc] {
+            assert_eq!((decode(opcode).mnemonic, decode(opcode).size()), ("NOP", 3), "{:02X}", opcode);
+        }
+        for opcode in [0x03, 0x0b, 0xfb] {
+            assert_eq!((decode(opcode).mnemonic, decode(opcode).size()), ("???", 1), "{:02X}", opcode);
+        }
+        assert!(decode(0x44).access().is_none());
+    }
+
+    #[test]
+    fn effective_addresses() {
+        // Zero page pointers: $10 -> $1234, $FF wraps to $00 -> $5678 high byte from $00.
+        let peek = |addr: u16| match addr {
+            0x10 => 0x34,
+            0x11 => 0x12,
+            0xff => 0x78,
+            0x00 => 0x56,
+            _ => 0,
+        };
+        let address = |bytes: &[u8], x: u8, y: u8| decode(bytes[0]).effective_address(bytes, x, y, peek);
+        assert_eq!(address(&[0xa5, 0x80], 0, 0), Some(0x0080));
+        assert_eq!(address(&[0xb5, 0xf0], 0x20, 0), Some(0x0010));
+        assert_eq!(address(&[0xb6, 0xf0], 0, 0x11), Some(0x0001));
+        assert_eq!(address(&[0xa1, 0x0e], 0x02, 0), Some(0x1234));
+        assert_eq!(address(&[0xa1, 0xfe], 0x01, 0), Some(0x5678));
+        assert_eq!(address(&[0xb1, 0x10], 0, 0x10), Some(0x1244));
+        assert_eq!(address(&[0xb2, 0x10], 0, 0), Some(0x1234));
+        assert_eq!(address(&[0xad, 0x00, 0xfd], 0, 0), Some(0xfd00));
+        assert_eq!(address(&[0xbd, 0xff, 0xff], 0x02, 0), Some(0x0001));
+        assert_eq!(address(&[0xb9, 0x00, 0x20], 0, 0x05), Some(0x2005));
+        assert_eq!(address(&[0x8d, 0x00, 0x20], 0, 0), Some(0x2000));
+        for bytes in [[0xea, 0, 0], [0x0a, 0, 0], [0xa9, 0x10, 0], [0xd0, 0x10, 0], [0x6c, 0x00, 0x20], [0x7c, 0x00, 0x20], [0x4c, 0x00, 0x20], [0x5c, 0x00, 0x20]] {
+            assert_eq!(address(&bytes, 1, 1), None, "{:02X}", bytes[0]);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::{fmt::Write as _, path::PathBuf, process::ExitCode};
+use log::error;
+
+use crate::{headless::Headless, runner::runner_config::RunnerConfig};
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// FNV-1a, stable across builds and platforms unlike `DefaultHasher`.
+pub(crate) fn frame_hash(rgb_buffer: &[u8]) -> u64 {
+    rgb_buffer.iter().fold(FNV_OFFSET, |hash, b| (hash ^ *b as u64).wrapping_mul(FNV_PRIME))
+}
+
+/// Runs a cartridge headlessly and compares every frame hash against a golden file.
+///
+/// Golden file format, one frame per line: `<frame> <hash>`, hash as 16 hex digits.
+pub(crate) struct FrameTest {
+    config: RunnerConfig,
+    frames: u64,
+    golden: PathBuf,
+    update: bool,
+}
+
+impl FrameTest {
+    pub fn new(config: RunnerConfig, frames: u64, golden: PathBuf, update: bool) -> Self {
+        Self {
+            config,
+            frames,
+            golden,
+            update,
+        }
+    }
+
+    pub fn run(&mut self) -> ExitCode {
+        let mut headless = Headless::new(self.config.clone(), Some(self.frames), None);
+        let mut hashes: Vec<u64> = vec![];
+
+        if let Err(err) = headless.run_with(|_, rgb_buffer| {
+            hashes.push(frame_hash(rgb_buffer));
+            true
+        }) {
+            error!("{}", err);
+            return ExitCode::FAILURE;
+        }
+
+        if hashes.len() as u64 != self.frames {
+            println!("Ran {} frames out of {}.", hashes.len(), self.frames);
+            return ExitCode::FAILURE;
+        }
+
+        if self.update {
+            return self.write_golden(&hashes);
+        }
+
+        let golden = match self.read_golden() {
+            Ok(golden) => golden,
+            Err(err) => {
+                println!("Couldn't read golden file {}: {}", self.golden.display(), err);
+                return ExitCode::FAILURE;
+            }
+        };
+
+        for (frame, hash) in hashes.iter().enumerate() {
+            match golden.get(frame) {
+                None => {
+                    println!("FAIL: golden file ends at frame {}, {} frames were run.", golden.len(), hashes.len());
+                    return ExitCode::FAILURE;
+                }
+                Some(expected) if expected != hash => {
+                    println!("FAIL: frame {} diverges, expected {:016x} got {:016x}.", frame, expected, hash);
+                    return ExitCode::FAILURE;
+                }
+                _ => (),
+            }
+        }
+
+        println!("OK: {} frames match {}.", hashes.len(), self.golden.display());
+        ExitCode::SUCCESS
+    }
+
+    fn read_golden(&self) -> Result<Vec<u64>, String> {
+        let text = std::fs::read_to_string(&self.golden).map_err(|e| e.to_string())?;
+        let mut hashes = vec![];
+
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((frame, hash)) = line.split_once(char::is_whitespace) else {
+                return Err(format!("line {}: expected '<frame> <hash>'.", n + 1));
+            };
+            let frame = frame.parse::<usize>().map_err(|_| format!("line {}: invalid frame '{}'.", n + 1, frame))?;
+            if frame != hashes.len() {
+                return Err(format!("line {}: expected frame {}, found {}.", n + 1, hashes.len(), frame));
+            }
+            let hash = u64::from_str_radix(hash.trim(), 16).map_err(|_| format!("line {}: invalid hash '{}'.", n + 1, hash))?;
+            hashes.push(hash);
+        }
+
+        Ok(hashes)
+    }
+
+    fn write_golden(&self, hashes: &[u64]) -> ExitCode {
+        let mut text = String::new();
+        for (frame, hash) in hashes.iter().enumerate() {
+            let _ = writeln!(text, "{} {:016x}", frame, hash);
+        }
+
+        match std::fs::write(&self.golden, text) {
+            Ok(_) => {
+                println!("Golden file {} written, {} frames.", self.golden.display(), hashes.len());
+                ExitCode::SUCCESS
+            }
+            Err(err) => {
+                println!("Couldn't write golden file {}: {}", self.golden.display(), err);
+                ExitCode::FAILURE
+            }
+        }
+    }
+}
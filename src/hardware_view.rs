@@ -0,0 +1,158 @@
+use ratatui::{layout::{Constraint, Layout, Rect}, style::{Color, Style}, text::{Line, Span}, widgets::{Block, Paragraph}, Frame};
+
+use crate::{registers::*, runner::debugger::HardwareState};
+
+fn flag(set: bool, name: &str) -> String {
+    match set {
+        true => name.to_string(),
+        false => "-".repeat(name.len()),
+    }
+}
+
+fn title(text: &str) -> Line<'static> {
+    Line::styled(text.to_string(), Style::default().fg(Color::Cyan))
+}
+
+/// Live Mikey and Suzy registers decoded into named fields.
+pub(crate) struct HardwareView {
+    state: Option<HardwareState>,
+}
+
+impl HardwareView {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: None,
+        }
+    }
+
+    pub(crate) fn update(&mut self, state: HardwareState) {
+        self.state = Some(state);
+    }
+
+    pub(crate) fn draw(&self, f: &mut Frame, area: Rect) {
+        let Some(hw) = &self.state else {
+            f.render_widget(Paragraph::new("Waiting for the core..."), area);
+            return;
+        };
+
+        let [mikey, suzy] = Layout::horizontal([Constraint::Percentage(55), Constraint::Fill(1)]).areas(area);
+        f.render_widget(Paragraph::new(self.mikey_lines(hw)).block(Block::bordered().title("Mikey")), mikey);
+        f.render_widget(Paragraph::new(self.suzy_lines(hw)).block(Block::bordered().title("Suzy")), suzy);
+    }
+
+    fn mikey_lines(&self, hw: &HardwareState) -> Vec<Line<'static>> {
+        let mut lines = vec![title("Timers     bkup cnt  clock  flags")];
+        let intset = hw.byte(INTSET);
+        for n in 0..TIMER_COUNT {
+            let base = TIMERS + n * TIMER_LEN;
+            let ctla = hw.byte(base + TIM_CTLA);
+            let ctlb = hw.byte(base + TIM_CTLB);
+            lines.push(Line::raw(format!(
+                " {}:{:<7} {:02X}   {:02X}   {:<6} {} {} {} {} {}",
+                n,
+                TIMER_NAMES[n as usize],
+                hw.byte(base + TIM_BKUP),
+                hw.byte(base + TIM_CNT),
+                clock_name(ctla),
+                flag(ctla & 0x08 != 0, "cnt"),
+                flag(ctla & 0x10 != 0, "rld"),
+                flag(ctla & 0x80 != 0, "int"),
+                flag(ctlb & 0x08 != 0, "done"),
+                flag(intset & (1 << n) != 0, "irq"),
+            )));
+        }
+
+        lines.push(title("Audio  vol out fb    shift bkup cnt  clock  freq    atten L/R"));
+        for n in 0..AUDIO_CHANNELS {
+            let base = AUDIO + n * AUDIO_LEN;
+            let ctl = hw.byte(base + AUD_CTL);
+            let (left, right) = attenuation(hw.byte(ATTEN_A + n));
+            let shift = ((hw.byte(base + AUD_MISC) as u16 & 0xf0) << 4) | hw.byte(base + AUD_L8SHFT) as u16;
+            let feedback = ((ctl as u16 & 0x80) << 1) | hw.byte(base + AUD_SHFTFB) as u16;
+            lines.push(Line::raw(format!(
+                " {}     {:>4} {:>4} {:03X}   {:03X}   {:02X}   {:02X}   {:<6} {:<7} {:X}/{:X}{}",
+                n,
+                hw.byte(base + AUD_VOL) as i8,
+                hw.byte(base + AUD_OUTVAL) as i8,
+                feedback,
+                shift,
+                hw.byte(base + AUD_TBACK),
+                hw.byte(base + AUD_COUNT),
+                clock_name(ctl),
+                audio_frequency(ctl, hw.byte(base + AUD_TBACK)).map(|hz| format!("{:.0}Hz", hz)).unwrap_or_default(),
+                left,
+                right,
+                match hw.byte(MPAN) & (0x11 << n) != 0 {
+                    true => " pan",
+                    false => "",
+                },
+            )));
+        }
+        lines.push(Line::raw(format!(" MSTEREO:{:02X}", hw.byte(MSTEREO))));
+
+        let dispctl = hw.byte(DISPCTL);
+        lines.push(title("Display"));
+        lines.push(Line::raw(format!(
+            " DISPADR:{:04X} PBKUP:{:02X} {} {} {}",
+            hw.word(DISPADR),
+            hw.byte(PBKUP),
+            flag(dispctl & 0x01 != 0, "dma"),
+            flag(dispctl & 0x02 != 0, "flip"),
+            flag(dispctl & 0x04 != 0, "4bit"),
+        )));
+        lines.push(title("System"));
+        lines.push(Line::raw(format!(
+            " INTSET:{:02X} SYSCTL1:{:02X} IODIR:{:02X} IODAT:{:02X} SERCTL:{:02X}",
+            intset,
+            hw.byte(SYSCTL1),
+            hw.byte(IODIR),
+            hw.byte(IODAT),
+            hw.byte(SERCTL),
+        )));
+
+        lines.push(title("Palette"));
+        let swatches: Vec<Span> = (0..PALETTE_LEN).map(|n| {
+            let (r, g, b) = palette_rgb(hw.byte(GREEN0 + n), hw.byte(BLUERED0 + n));
+            Span::styled(format!("{:X}", n), Style::default().fg(Color::Black).bg(Color::Rgb(r, g, b)))
+        }).collect();
+        lines.push(Line::from([vec![Span::raw(" ")], swatches].concat()));
+
+        lines
+    }
+
+    fn suzy_lines(&self, hw: &HardwareState) -> Vec<Line<'static>> {
+        let sprctl0 = hw.byte(SPRCTL0);
+        let sprctl1 = hw.byte(SPRCTL1);
+        let sprsys = hw.byte(SPRSYS);
+
+        vec![
+            title("Sprite engine"),
+            Line::raw(format!(" SCBNEXT:{:04X} SCBADR:{:04X}", hw.word(SCBNEXT), hw.word(SCBADR))),
+            Line::raw(format!(" PROCADR:{:04X} SPRDLINE:{:04X}", hw.word(PROCADR), hw.word(SPRDLINE))),
+            Line::raw(format!(" VIDBAS:{:04X} COLLBAS:{:04X}", hw.word(VIDBAS), hw.word(COLLBAS))),
+            Line::raw(format!(" HOFF:{:04X} VOFF:{:04X}", hw.word(HOFF), hw.word(VOFF))),
+            Line::raw(format!(" SPRCTL0:{:02X} {}bpp {} {} {}", sprctl0, sprite_bpp(sprctl0), sprite_type(sprctl0), flag(sprctl0 & 0x20 != 0, "hflip"), flag(sprctl0 & 0x10 != 0, "vflip"))),
+            Line::raw(format!(" SPRCTL1:{:02X} SPRCOLL:{:02X}", sprctl1, hw.byte(SPRCOLL))),
+            Line::raw(format!(" HPOS:{:04X} VPOS:{:04X}", hw.word(HPOSSTRT), hw.word(VPOSSTRT))),
+            Line::raw(format!(" HSIZ:{:04X} VSIZ:{:04X}", hw.word(SPRHSIZ), hw.word(SPRVSIZ))),
+            Line::raw(format!(" STRETCH:{:04X} TILT:{:04X}", hw.word(STRETCH), hw.word(TILT))),
+            Line::raw(format!(
+                " SPRSYS:{:02X} {} {} BUSEN:{:02X} SPRGO:{:02X}",
+                sprsys,
+                flag(sprsys & 0x01 != 0, "busy"),
+                flag(sprsys & 0x80 != 0, "math"),
+                hw.byte(SUZYBUSEN),
+                hw.byte(SPRGO),
+            )),
+            title("Math"),
+            Line::raw(format!(" ABCD:{:04X}{:04X}", hw.word(MATHD + 2), hw.word(MATHD))),
+            Line::raw(format!(" EFGH:{:04X}{:04X}", hw.word(MATHH + 2), hw.word(MATHH))),
+            Line::raw(format!(" JKLM:{:04X}{:04X}", hw.word(MATHM + 2), hw.word(MATHM))),
+            Line::raw(format!(" NP:{:04X}", hw.word(MATHP))),
+            title("Collision"),
+            Line::raw(format!(" COLLOFF:{:04X} SPRCOLL:{:02X}", hw.word(COLLOFF), hw.byte(SPRCOLL))),
+            title("Inputs"),
+            Line::raw(format!(" JOYSTICK:{:02X} SWITCHES:{:02X}", hw.byte(JOYSTICK), hw.byte(SWITCHES))),
+        ]
+    }
+}
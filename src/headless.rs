@@ -0,0 +1,70 @@
+use std::{process::ExitCode, time::{Duration, Instant}};
+use log::{error, info};
+
+use crate::runner::{runner_config::RunnerConfig, Runner, RunnerChannels};
+
+pub(crate) struct Headless {
+    config: RunnerConfig,
+    frames: Option<u64>,
+    duration: Option<Duration>,
+}
+
+impl Headless {
+    pub fn new(config: RunnerConfig, frames: Option<u64>, duration: Option<Duration>) -> Self {
+        Self {
+            config,
+            frames,
+            duration,
+        }
+    }
+
+    pub fn run(&mut self) -> ExitCode {
+        match self.run_with(|_, _| true) {
+            Ok(frames) => {
+                info!("Headless run completed after {} frames.", frames);
+                ExitCode::SUCCESS
+            }
+            Err(err) => {
+                error!("{}", err);
+                ExitCode::FAILURE
+            }
+        }
+    }
+
+    /// Runs the core until the frame or time limit is reached, or `on_frame` returns `false`.
+    /// `on_frame` gets every displayed frame with its number, returns the number of frames run.
+    pub fn run_with<F>(&mut self, mut on_frame: F) -> Result<u64, String>
+    where
+        F: FnMut(u64, &[u8]) -> bool,
+    {
+        let mut runner = Runner::new(self.config.clone());
+        let RunnerChannels { input_tx, update_display_rx, .. } = runner.initialize_thread()?;
+
+        let deadline = self.duration.map(|d| Instant::now() + d);
+        let mut frame = 0u64;
+
+        while self.frames.is_none_or(|frames| frame < frames) {
+            let rgb_buffer = match deadline {
+                None => update_display_rx.recv().map_err(|_| "Core runner stopped unexpectedly.".to_string())?,
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    match update_display_rx.recv_timeout(remaining) {
+                        Ok(rgb_buffer) => rgb_buffer,
+                        Err(kanal::ReceiveErrorTimeout::Timeout) => break,
+                        Err(_) => return Err("Core runner stopped unexpectedly.".to_string()),
+                    }
+                }
+            };
+
+            let keep_going = on_frame(frame, &rgb_buffer);
+            frame += 1;
+            if !keep_going {
+                break;
+            }
+        }
+
+        input_tx.close().unwrap();
+
+        Ok(frame)
+    }
+}
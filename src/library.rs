@@ -0,0 +1,363 @@
+use std::{collections::HashMap, fs, path::{Path, PathBuf}, time::{Duration, SystemTime, UNIX_EPOCH}};
+use log::warn;
+use sha1::{Digest, Sha1};
+
+use crate::{cartridge_browser::is_cartridge, runner::archive::{self, is_archive}};
+
+/// Metadata database bundled in the executable.
+const BUNDLED_DATABASE: &str = include_str!("../data/lynx_games.tsv");
+const LNX_HEADER_LEN: usize = 64;
+
+/// Lowercase letters and digits of a title, without the `(region)` and `[flags]` parts.
+fn title_key(title: &str) -> String {
+    let mut depth = 0;
+    title.chars()
+        .filter(|c| {
+            match c {
+                '(' | '[' => depth += 1,
+                ')' | ']' => depth -= 1,
+                _ => return depth == 0 && c.is_ascii_alphanumeric(),
+            }
+            false
+        })
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// `key="value"` attribute in a XML tag.
+fn xml_attr(tag: &str, key: &str) -> Option<String> {
+    let start = tag.find(&format!(" {}=\"", key))? + key.len() + 3;
+    let len = tag[start..].find('"')?;
+    Some(tag[start..start + len]
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&"))
+}
+
+#[derive(Clone)]
+pub(crate) struct GameEntry {
+    pub crc: Option<u32>,
+    pub sha1: Option<String>,
+    pub title: String,
+    pub year: Option<u16>,
+    pub publisher: String,
+    pub players: u8,
+    pub comlynx: bool,
+}
+
+/// Games known by their image hashes or titles.
+pub(crate) struct GameDatabase {
+    entries: Vec<GameEntry>,
+}
+
+impl GameDatabase {
+    pub(crate) fn bundled() -> Self {
+        let mut database = Self { entries: vec![] };
+        database.add(BUNDLED_DATABASE);
+        database
+    }
+
+    /// Adds a database file, same format as the bundled one or a No-Intro (Logiqx XML) DAT, which only
+    /// brings titles and hashes.
+    pub(crate) fn load(&mut self, path: &Path) -> Result<(), String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        match text.contains("<datafile") {
+            true => self.add_dat(&text),
+            false => self.add(&text),
+        }
+        Ok(())
+    }
+
+    fn add(&mut self, text: &str) {
+        let hex = |s: &str| u32::from_str_radix(s, 16).ok();
+        for line in text.lines().filter(|line| !line.trim().is_empty() && !line.starts_with('#')) {
+            let fields: Vec<&str> = line.split('\t').map(str::trim).collect();
+            let [crc, sha1, title, year, publisher, players, comlynx] = fields[..] else {
+                warn!("Game database: invalid line '{}'.", line);
+                continue;
+            };
+            self.entries.push(GameEntry {
+                crc: hex(crc),
+                sha1: Some(sha1.to_lowercase()).filter(|sha1| sha1.len() == 40),
+                title: title.to_string(),
+                year: year.parse().ok(),
+                publisher: publisher.to_string(),
+                players: players.parse().unwrap_or(1),
+                comlynx: comlynx == "yes",
+            });
+        }
+    }
+
+    fn add_dat(&mut self, text: &str) {
+        for game in text.split("<game ").skip(1) {
+            let game = game.split("</game>").next().unwrap_or_default();
+            let (Some(title), Some(rom)) = (xml_attr(&format!(" {}", game), "name"), game.split("<rom ").nth(1)) else {
+                continue;
+            };
+            let rom = format!(" {}", rom);
+            self.entries.push(GameEntry {
+                crc: xml_attr(&rom, "crc").and_then(|crc| u32::from_str_radix(&crc, 16).ok()),
+                sha1: xml_attr(&rom, "sha1").map(|sha1| sha1.to_lowercase()),
+                title,
+                year: None,
+                publisher: String::new(),
+                players: 1,
+                comlynx: false,
+            });
+        }
+    }
+
+    /// Entry of an image, by hash then by title. Metadata missing from a hash match, a DAT entry, is
+    /// completed from an entry with the same title.
+    fn identify(&self, crc: u32, sha1: &str, titles: &[&str]) -> Option<GameEntry> {
+        let by_title = |title: &str| {
+            let key = title_key(title);
+            self.entries.iter().find(|entry| entry.year.is_some() && !key.is_empty() && title_key(&entry.title) == key)
+        };
+        let hashed = self.entries.iter()
+            .find(|entry| entry.sha1.as_deref() == Some(sha1))
+            .or_else(|| self.entries.iter().find(|entry| entry.sha1.is_none() && entry.crc == Some(crc)));
+        match hashed {
+            Some(entry) if entry.year.is_none() => {
+                let metadata = by_title(&entry.title).cloned();
+                Some(GameEntry { title: entry.title.clone(), ..metadata.unwrap_or_else(|| entry.clone()) })
+            }
+            Some(entry) => Some(entry.clone()),
+            None => titles.iter().find_map(|title| by_title(title)).cloned(),
+        }
+    }
+}
+
+/// A cartridge found in the library directories.
+pub(crate) struct LibraryItem {
+    pub path: PathBuf,
+    /// Image in the archive `path`.
+    pub member: Option<String>,
+    pub sha1: String,
+    pub title: String,
+    pub entry: Option<GameEntry>,
+    pub play_time: Duration,
+    /// Seconds since the Unix epoch.
+    pub last_played: Option<u64>,
+}
+
+/// Cartridges of the library directories, with their play statistics kept in `stats_path`, a
+/// `<sha1> <seconds played> <last played>` line per game.
+pub(crate) struct Library {
+    pub items: Vec<LibraryItem>,
+    stats_path: PathBuf,
+}
+
+impl Library {
+    pub(crate) fn scan(dirs: &[PathBuf], database: &GameDatabase, stats_path: PathBuf) -> Self {
+        let stats = Self::load_stats(&stats_path);
+        let mut paths = vec![];
+        dirs.iter().for_each(|dir| Self::find_cartridges(dir, &mut paths));
+        paths.sort();
+        paths.dedup();
+
+        let images = paths.into_iter().flat_map(|path| {
+            let images = match is_archive(&path) {
+                true => archive::extract_images(&path, is_cartridge).map(|images| images.into_iter().map(|(name, data)| (Some(name), data)).collect()),
+                false => fs::read(&path).map(|data| vec![(None, data)]).map_err(|e| format!("{}: {}", path.display(), e)),
+            };
+            let images: Vec<(Option<String>, Vec<u8>)> = images.unwrap_or_else(|err| {
+                warn!("Library: {}", err);
+                vec![]
+            });
+            images.into_iter().map(move |(member, data)| (path.clone(), member, data))
+        });
+
+        let mut items: Vec<LibraryItem> = images.map(|(path, member, data)| {
+            let lnx = data.starts_with(b"LYNX") && data.len() >= LNX_HEADER_LEN;
+            let image = match lnx {
+                true => &data[LNX_HEADER_LEN..],
+                false => &data[..],
+            };
+            let crc = crc32fast::hash(image);
+            let sha1: String = Sha1::digest(image).iter().map(|b| format!("{:02x}", b)).collect();
+            let header_title = lnx.then(|| {
+                let name = &data[10..42];
+                String::from_utf8_lossy(&name[..name.iter().position(|b| *b == 0).unwrap_or(name.len())]).trim().to_string()
+            });
+            let file = member.as_ref().map(PathBuf::from).unwrap_or_else(|| path.clone());
+            let stem = file.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+            let titles: Vec<&str> = header_title.iter().map(String::as_str).chain([stem.as_str()]).collect();
+            let entry = database.identify(crc, &sha1, &titles);
+            let title = entry.as_ref().map(|entry| entry.title.clone())
+                .or(header_title.filter(|title| !title.is_empty()))
+                .unwrap_or(stem);
+            let (play_time, last_played) = stats.get(&sha1).copied().unwrap_or_default();
+            LibraryItem { path, member, sha1, title, entry, play_time: Duration::from_secs(play_time), last_played }
+        }).collect();
+        items.sort_by_key(|item| item.title.to_lowercase());
+        Self { items, stats_path }
+    }
+
+    fn find_cartridges(dir: &Path, paths: &mut Vec<PathBuf>) {
+        let Ok(read) = fs::read_dir(dir) else {
+            warn!("Library: can't read {}.", dir.display());
+            return;
+        };
+        for entry in read.flatten() {
+            let path = entry.path();
+            // Symlinked directories aren't followed, a link loop would recurse forever.
+            if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                Self::find_cartridges(&path, paths);
+            } else if is_cartridge(&path) || is_archive(&path) {
+                paths.push(path);
+            }
+        }
+    }
+
+    fn load_stats(path: &Path) -> HashMap<String, (u64, Option<u64>)> {
+        let Ok(text) = fs::read_to_string(path) else {
+            return HashMap::new();
+        };
+        text.lines().filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let sha1 = fields.next()?.to_string();
+            let play_time = fields.next()?.parse().ok()?;
+            let last_played = fields.next().and_then(|last| last.parse().ok()).filter(|last| *last > 0);
+            Some((sha1, (play_time, last_played)))
+        }).collect()
+    }
+
+    fn save_stats(&self) -> Result<(), String> {
+        let mut stats = Self::load_stats(&self.stats_path);
+        for item in self.items.iter().filter(|item| item.last_played.is_some()) {
+            stats.insert(item.sha1.clone(), (item.play_time.as_secs(), item.last_played));
+        }
+        let mut lines: Vec<String> = stats.iter()
+            .map(|(sha1, (play_time, last_played))| format!("{} {} {}", sha1, play_time, last_played.unwrap_or_default()))
+            .collect();
+        lines.sort();
+        lines.push(String::new());
+        fs::write(&self.stats_path, lines.join("\n")).map_err(|e| format!("{}: {}", self.stats_path.display(), e))
+    }
+
+    /// Adds a play session of `item` that just ended.
+    pub(crate) fn played(&mut self, item: usize, duration: Duration) -> Result<(), String> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        self.items[item].play_time += duration;
+        self.items[item].last_played = Some(now);
+        self.save_stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA1: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    fn database(text: &str) -> GameDatabase {
+        let mut database = GameDatabase { entries: vec![] };
+        database.add(text);
+        database
+    }
+
+    #[test]
+    fn keys_titles() {
+        assert_eq!(title_key("Gauntlet - The Third Encounter (USA, Europe)"), "gauntletthethirdencounter");
+        assert_eq!(title_key("Bill & Ted's Excellent Adventure [b1] (1991)"), "billtedsexcellentadventure");
+        assert_eq!(title_key("S.T.U.N. Runner"), "stunrunner");
+        assert_eq!(title_key("(Proto)"), "");
+    }
+
+    #[test]
+    fn reads_dat_entries() {
+        let tag = r#"<rom name="Klax (USA, Europe).lnx" size="131072" crc="0A1B2C3D" sha1="ABCDEF"/>"#;
+        assert_eq!(xml_attr(tag, "crc").as_deref(), Some("0A1B2C3D"));
+        assert_eq!(xml_attr(tag, "name").as_deref(), Some("Klax (USA, Europe).lnx"));
+        assert_eq!(xml_attr(tag, "md5"), None);
+        assert_eq!(xml_attr(r#"<game name="Bill &amp; Ted&apos;s &quot;Adventure&quot;">"#, "name").as_deref(), Some("Bill & Ted's \"Adventure\""));
+
+        let mut database = GameDatabase { entries: vec![] };
+        database.add_dat(r#"<?xml version="1.0"?>
+<datafile>
+    <header><name>Atari - Lynx</name></header>
+    <game name="Klax (USA, Europe)">
+        <description>Klax (USA, Europe)</description>
+        <rom name="Klax (USA, Europe).lnx" size="131072" crc="0a1b2c3d" sha1="0123456789ABCDEF0123456789ABCDEF01234567"/>
+    </game>
+    <game name="No Rom"></game>
+</datafile>"#);
+        assert_eq!(database.entries.len(), 1);
+        let entry = &database.entries[0];
+        assert_eq!((entry.title.as_str(), entry.crc, entry.sha1.as_deref(), entry.year), ("Klax (USA, Europe)", Some(0x0a1b2c3d), Some(SHA1), None));
+    }
+
+    #[test]
+    fn identifies_by_hash_then_title() {
+        let mut database = database(&format!(
+            "# comment\n\
+            -\t-\tKlax\t1990\tAtari\t1\tno\n\
+            00000001\t{}\tRampage\t1990\tAtari\t4\tyes\n\
+            00000002\t-\tToki\t1991\tAtari\t1\tno\n\
+            invalid line\n",
+            SHA1,
+        ));
+        assert_eq!(database.entries.len(), 3);
+
+        // SHA1 first, then the CRC of entries without SHA1.
+        assert_eq!(database.identify(0, SHA1, &["Klax"]).map(|entry| entry.title).as_deref(), Some("Rampage"));
+        assert_eq!(database.identify(2, "", &["Klax"]).map(|entry| entry.title).as_deref(), Some("Toki"));
+        assert_eq!(database.identify(1, "", &[]).map(|entry| entry.title), None);
+        // Titles in turn, header then file name.
+        assert_eq!(database.identify(9, "", &["Unknown", "klax (USA)"]).map(|entry| entry.title).as_deref(), Some("Klax"));
+        assert!(database.identify(9, "", &["Unknown", ""]).is_none());
+
+        // A DAT entry matched by hash gets the metadata of the entry with its title.
+        database.add_dat(r#"<game name="Klax (USA, Europe)"><rom name="Klax.lnx" crc="00000003"/></game>"#);
+        let entry = database.identify(3, "", &[]).unwrap();
+        assert_eq!((entry.title.as_str(), entry.year, entry.publisher.as_str()), ("Klax (USA, Europe)", Some(1990), "Atari"));
+        database.add_dat(r#"<game name="Homebrew"><rom name="Homebrew.lnx" crc="00000004"/></game>"#);
+        let entry = database.identify(4, "", &[]).unwrap();
+        assert_eq!((entry.title.as_str(), entry.year), ("Homebrew", None));
+    }
+
+    #[test]
+    fn parses_the_bundled_database() {
+        let database = GameDatabase::bundled();
+        assert_eq!(database.entries.len(), BUNDLED_DATABASE.lines().filter(|line| !line.starts_with('#')).count());
+        assert!(database.entries.iter().all(|entry| entry.year.is_some() && !entry.publisher.is_empty()));
+    }
+
+    #[test]
+    fn keeps_play_stats() {
+        let dir = std::env::temp_dir().join(format!("holani-library-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let stats_path = dir.join("stats");
+        std::fs::write(&stats_path, "bbbb 30 1700000000\ncccc 10 0\ninvalid\n").unwrap();
+        let stats = Library::load_stats(&stats_path);
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats["bbbb"], (30, Some(1_700_000_000)));
+        assert_eq!(stats["cccc"], (10, None));
+
+        let item = |sha1: &str, play_time: u64, last_played: Option<u64>| LibraryItem {
+            path: PathBuf::new(),
+            member: None,
+            sha1: sha1.to_string(),
+            title: String::new(),
+            entry: None,
+            play_time: Duration::from_secs(play_time),
+            last_played,
+        };
+        let mut library = Library { items: vec![item("aaaa", 0, None), item("bbbb", 30, Some(1_700_000_000))], stats_path: stats_path.clone() };
+        library.played(0, Duration::from_secs(90)).unwrap();
+        library.items[1].play_time = Duration::from_secs(45);
+        library.save_stats().unwrap();
+
+        let stats = Library::load_stats(&stats_path);
+        assert_eq!(stats.len(), 3);
+        assert_eq!(stats["aaaa"].0, 90);
+        assert!(stats["aaaa"].1.is_some_and(|last| last >= 1_700_000_000));
+        assert_eq!(stats["bbbb"], (45, Some(1_700_000_000)));
+        assert_eq!(stats["cccc"], (10, None));
+        assert!(Library::load_stats(&dir.join("missing")).is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
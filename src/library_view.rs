@@ -0,0 +1,246 @@
+use std::{cmp::Ordering, time::{Duration, Instant}};
+use ratatui::{crossterm::{self, event::{Event, KeyCode, KeyEventKind}}, layout::{Constraint, Layout}, prelude::Backend, style::{Color, Modifier, Style}, text::Line, widgets::{Block, Paragraph}, Frame, Terminal};
+
+use crate::{app::App, library::{Library, LibraryItem}, runner::runner_config::RunnerConfig};
+
+const PAGE: usize = 10;
+/// Width of the columns after the title.
+const COLUMNS_WIDTH: usize = 48;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SortKey {
+    Title,
+    Year,
+    Publisher,
+    Players,
+    Comlynx,
+    PlayTime,
+    LastPlayed,
+}
+
+const SORT_KEYS: [SortKey; 7] = [SortKey::Title, SortKey::Year, SortKey::Publisher, SortKey::Players, SortKey::Comlynx, SortKey::PlayTime, SortKey::LastPlayed];
+
+impl SortKey {
+    fn name(&self) -> &'static str {
+        match self {
+            SortKey::Title => "title",
+            SortKey::Year => "year",
+            SortKey::Publisher => "publisher",
+            SortKey::Players => "players",
+            SortKey::Comlynx => "comlynx",
+            SortKey::PlayTime => "play time",
+            SortKey::LastPlayed => "last played",
+        }
+    }
+
+    fn compare(&self, a: &LibraryItem, b: &LibraryItem) -> Ordering {
+        let (ea, eb) = (a.entry.as_ref(), b.entry.as_ref());
+        let by_key = match self {
+            SortKey::Title => Ordering::Equal,
+            SortKey::Year => ea.and_then(|e| e.year).cmp(&eb.and_then(|e| e.year)),
+            SortKey::Publisher => ea.map(|e| e.publisher.to_lowercase()).cmp(&eb.map(|e| e.publisher.to_lowercase())),
+            SortKey::Players => ea.map(|e| e.players).cmp(&eb.map(|e| e.players)),
+            SortKey::Comlynx => ea.map(|e| e.comlynx).cmp(&eb.map(|e| e.comlynx)),
+            SortKey::PlayTime => a.play_time.cmp(&b.play_time),
+            SortKey::LastPlayed => a.last_played.cmp(&b.last_played),
+        };
+        by_key.then_with(|| a.title.to_lowercase().cmp(&b.title.to_lowercase()))
+    }
+}
+
+/// `YYYY-MM-DD`, UTC, of seconds since the Unix epoch.
+fn date(secs: u64) -> String {
+    let days = (secs / 86400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+fn play_time(duration: Duration) -> String {
+    match duration.as_secs() {
+        0 => "-".to_string(),
+        secs => format!("{}h{:02}", secs / 3600, secs / 60 % 60),
+    }
+}
+
+/// Library list, Enter plays the selected game and comes back here when it's exited.
+///
+/// `/` searches titles and publishers, `s` changes the sort column, `r` reverses the order.
+pub(crate) struct LibraryView {
+    library: Library,
+    search: String,
+    searching: bool,
+    sort: SortKey,
+    reverse: bool,
+    /// Items listed, filtered and sorted.
+    shown: Vec<usize>,
+    selected: usize,
+    message: String,
+}
+
+impl LibraryView {
+    pub(crate) fn new(library: Library) -> Self {
+        let mut view = Self {
+            library,
+            search: String::new(),
+            searching: false,
+            sort: SortKey::Title,
+            reverse: false,
+            shown: vec![],
+            selected: 0,
+            message: String::new(),
+        };
+        view.refresh();
+        view
+    }
+
+    fn refresh(&mut self) {
+        let search = self.search.to_lowercase();
+        let items = &self.library.items;
+        self.shown = (0..items.len())
+            .filter(|i| {
+                let item = &items[*i];
+                item.title.to_lowercase().contains(&search)
+                    || item.entry.as_ref().is_some_and(|entry| entry.publisher.to_lowercase().contains(&search))
+            })
+            .collect();
+        self.shown.sort_by(|a, b| self.sort.compare(&items[*a], &items[*b]));
+        if self.reverse {
+            self.shown.reverse();
+        }
+        self.select(self.selected);
+    }
+
+    fn select(&mut self, selected: usize) {
+        self.selected = selected.min(self.shown.len().saturating_sub(1));
+    }
+
+    /// Runs until Esc, the games with `config` for everything but the cartridge.
+    pub(crate) fn run<B: Backend>(&mut self, terminal: &mut Terminal<B>, config: &RunnerConfig) {
+        loop {
+            terminal.draw(|f| self.draw(f)).unwrap();
+            let Ok(Event::Key(input)) = crossterm::event::read() else {
+                continue;
+            };
+            if input.kind != KeyEventKind::Press {
+                continue;
+            }
+            if self.searching {
+                match input.code {
+                    KeyCode::Char(c) => self.search.push(c),
+                    KeyCode::Backspace => {
+                        self.search.pop();
+                    }
+                    KeyCode::Enter => self.searching = false,
+                    KeyCode::Esc => {
+                        self.searching = false;
+                        self.search.clear();
+                    }
+                    _ => (),
+                }
+                self.refresh();
+                continue;
+            }
+            match input.code {
+                KeyCode::Esc => return,
+                KeyCode::Up => self.select(self.selected.saturating_sub(1)),
+                KeyCode::Down => self.select(self.selected + 1),
+                KeyCode::PageUp => self.select(self.selected.saturating_sub(PAGE)),
+                KeyCode::PageDown => self.select(self.selected + PAGE),
+                KeyCode::Home => self.select(0),
+                KeyCode::End => self.select(usize::MAX),
+                KeyCode::Char('/') => self.searching = true,
+                KeyCode::Char('s') => {
+                    let next = SORT_KEYS.iter().position(|key| *key == self.sort).map_or(0, |i| (i + 1) % SORT_KEYS.len());
+                    self.sort = SORT_KEYS[next];
+                    self.refresh();
+                }
+                KeyCode::Char('r') => {
+                    self.reverse = !self.reverse;
+                    self.refresh();
+                }
+                KeyCode::Enter => self.play(terminal, config),
+                _ => (),
+            }
+        }
+    }
+
+    fn play<B: Backend>(&mut self, terminal: &mut Terminal<B>, config: &RunnerConfig) {
+        let Some(item) = self.shown.get(self.selected).copied() else {
+            return;
+        };
+        let path = self.library.items[item].path.clone();
+        let mut config = config.clone();
+        config.set_cheats(path.with_extension("cht"));
+        config.set_cartridge(path);
+        if let Some(member) = &self.library.items[item].member {
+            config.set_cartridge_member(member.clone());
+        }
+
+        let start = Instant::now();
+        self.message = match App::new(config) {
+            Err(err) => err,
+            Ok(mut app) => {
+                app.run(terminal);
+                match self.library.played(item, start.elapsed()) {
+                    Ok(()) => String::new(),
+                    Err(err) => format!("Play time not saved: {}", err),
+                }
+            }
+        };
+        self.refresh();
+        let _ = terminal.clear();
+    }
+
+    fn draw(&self, f: &mut Frame) {
+        let [title, list, status] = Layout::vertical([Constraint::Length(1), Constraint::Fill(1), Constraint::Length(1)]).areas(f.area());
+
+        let order = match self.reverse {
+            true => ", reversed",
+            false => "",
+        };
+        f.render_widget(Paragraph::new(format!("Library - {} of {} games, by {}{}", self.shown.len(), self.library.items.len(), self.sort.name(), order)), title);
+
+        let title_width = (list.width as usize).saturating_sub(COLUMNS_WIDTH + 2).max(8);
+        let height = list.height.saturating_sub(3) as usize;
+        let first = self.selected.saturating_sub(height.saturating_sub(1));
+        let header = format!("{:<title_width$} year publisher      pl lnk  played last played", "title");
+        let mut lines = vec![Line::styled(header, Style::default().fg(Color::Cyan))];
+        lines.extend(self.shown.iter().enumerate().skip(first).take(height).map(|(i, item)| {
+            let item = &self.library.items[*item];
+            let entry = item.entry.as_ref();
+            let text = format!(
+                "{:<title_width$} {:>4} {:<14} {:>2} {:<3} {:>7} {:>11}",
+                item.title.chars().take(title_width).collect::<String>(),
+                entry.and_then(|e| e.year).map(|year| year.to_string()).unwrap_or_else(|| "-".to_string()),
+                entry.map(|e| e.publisher.chars().take(14).collect::<String>()).unwrap_or_else(|| "-".to_string()),
+                entry.map(|e| e.players.to_string()).unwrap_or_else(|| "-".to_string()),
+                match entry.map(|e| e.comlynx) {
+                    Some(true) => "yes",
+                    _ => "-",
+                },
+                play_time(item.play_time),
+                item.last_played.map(date).unwrap_or_else(|| "-".to_string()),
+            );
+            match i == self.selected {
+                true => Line::styled(text, Style::default().add_modifier(Modifier::REVERSED)),
+                false => Line::raw(text),
+            }
+        }));
+        f.render_widget(Paragraph::new(lines).block(Block::bordered().title("Games")), list);
+
+        let help = match (self.searching, self.message.is_empty()) {
+            (true, _) => format!("Search: {}_", self.search),
+            (false, false) => self.message.clone(),
+            (false, true) if !self.search.is_empty() => format!("'{}' - Enter: play  /: search  s: sort  r: reverse  Esc: quit", self.search),
+            (false, true) => "Enter: play  /: search  s: sort  r: reverse  Esc: quit".to_string(),
+        };
+        f.render_widget(Paragraph::new(help), status);
+    }
+}
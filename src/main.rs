@@ -1,21 +1,56 @@
 use app::App;
-use clap::Parser;
+use cartridge_browser::{is_cartridge, CartridgeBrowser};
+use clap::{Parser, Subcommand};
+use debugger_view::parse_address;
+use frame_test::FrameTest;
+use headless::Headless;
 use keycodes::translate_keycode;
+use library::{GameDatabase, Library};
+use library_view::LibraryView;
 use ratatui::crossterm::{event::KeyCode, terminal::{disable_raw_mode, enable_raw_mode}};
-use runner::runner_config::{Input, RunnerConfig};
-use std::path::PathBuf;
+use runner::{archive::{self, is_archive}, comlynx_link::ComlynxEndpoint, comlynx_sniffer::ComlynxLog, netplay::{NetplaySettings, MAX_DELAY, MAX_ROLLBACK}, spectator::SpectatorStream, runner_config::{Input, RunnerConfig}};
+use std::{path::PathBuf, process::ExitCode, time::Duration};
+use ssh::SshAuth;
+use symbols::Symbols;
+use viewer::Viewer;
+
+/// Default buttons mappings of the players 2 to 4.
+const PLAYER_BUTTONS: [&str; 3] = ["i,k,j,l,u,o,7,8,0", "t,g,f,h,r,y,3,4,5", "z,x,c,v,a,s,b,n,m"];
 
 pub(crate) mod keycodes;
 pub(crate) mod runner;
 pub(crate) mod sound_source;
 pub(crate) mod app;
+pub(crate) mod headless;
+pub(crate) mod frame_test;
+pub(crate) mod disasm;
+pub(crate) mod debugger_view;
+pub(crate) mod symbols;
+pub(crate) mod registers;
+pub(crate) mod hardware_view;
+pub(crate) mod sprites_view;
+pub(crate) mod video_view;
+pub(crate) mod audio_view;
+pub(crate) mod cheats_view;
+pub(crate) mod profiler_view;
+pub(crate) mod comlynx_view;
+pub(crate) mod viewer;
+pub(crate) mod ssh;
+pub(crate) mod cartridge_info;
+pub(crate) mod cartridge_browser;
+pub(crate) mod library;
+pub(crate) mod library_view;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Args {
-    /// Cartright, can be .o or a .lnx file
+    /// Cartright, a .o, .lnx or .lyx file or a .zip, .gz or .7z archive, picked in a file browser when missing or a directory
     #[arg(short, long)]
-    cartridge: PathBuf,
+    cartridge: Option<PathBuf>,
+
+    /// Image to load from a cartridge archive holding several, picked in the file browser when missing
+    #[arg(long, requires = "cartridge")]
+    cartridge_member: Option<String>,
 
     /// ROM override
     #[arg(short, long)]
@@ -30,19 +65,227 @@ struct Args {
     )]
     buttons: Option<Vec<String>>,
 
+    /// Lynx instances linked by Comlynx in this process, their screens are tiled
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=4), conflicts_with_all = ["headless", "comlynx_listen", "comlynx_connect"])]
+    players: u8,
+
+    /// Buttons mapping of the next player, same format as --buttons, repeated for each player
+    #[arg(long)]
+    player_buttons: Vec<String>,
+
+    /// Debug symbols, cc65 .dbg or VICE label file
+    #[arg(short, long)]
+    symbols: Option<PathBuf>,
+
+    /// Listen for GDB remote connections on localhost:<port>, watchpoints only see CPU instruction operands, not stack, vector or Suzy accesses
+    #[arg(long)]
+    gdb_port: Option<u16>,
+
+    /// Log executed CPU instructions to a file, F4 pauses and resumes logging
+    #[arg(long)]
+    trace: Option<PathBuf>,
+
+    /// Trace: only log instructions within <start>-<end>,...
+    #[arg(long, value_delimiter = ',', requires = "trace")]
+    trace_range: Vec<String>,
+
+    /// Trace: keep the last <N> instructions, written when a breakpoint hits or the core panics
+    #[arg(long, requires = "trace")]
+    trace_ring: Option<usize>,
+
+    /// Profile executed code from power-on, the report is written to <file> on exit
+    #[arg(long)]
+    profile: Option<PathBuf>,
+
+    /// Profiler: idle code excluded from the frame budget, <start>-<end> or symbol,...
+    #[arg(long, value_delimiter = ',')]
+    profile_idle: Vec<String>,
+
     /// Mute sound
     #[arg(short, long, default_value_t = false)]
     mute: bool,
 
+    /// Autofire rates in frames <input>:<rate>,...
+    #[arg(
+        short,
+        long,
+        value_delimiter = ',',
+        default_value = "outside:2,inside:2"
+    )]
+    autofire: Vec<String>,
+
+    /// Enable autofire at startup, F2 toggles it
+    #[arg(long, default_value_t = false)]
+    autofire_on: bool,
+
     /// Enable Comlynx
     #[arg(short('x'), long, default_value_t = false)]
     comlynx: bool,
+
+    /// Comlynx over TCP: wait for a peer connection on <addr>
+    #[arg(long, conflicts_with = "comlynx_connect")]
+    comlynx_listen: Option<String>,
+
+    /// Comlynx over TCP: connect to a peer listening on <addr>
+    #[arg(long)]
+    comlynx_connect: Option<String>,
+
+    /// Netplay: exchange inputs over UDP with the instance at <addr>, both run the same pair of linked Lynx
+    #[arg(long, requires = "netplay_bind", requires = "netplay_player", conflicts_with_all = ["comlynx", "comlynx_listen", "comlynx_connect", "players", "input_script", "play_movie", "record_movie"])]
+    netplay_peer: Option<String>,
+
+    /// Netplay: local UDP address
+    #[arg(long, requires = "netplay_peer")]
+    netplay_bind: Option<String>,
+
+    /// Netplay: local player, 1 or 2, the peer takes the other one
+    #[arg(long, requires = "netplay_peer", value_parser = clap::value_parser!(u8).range(1..=2))]
+    netplay_player: Option<u8>,
+
+    /// Netplay: frames between a local input and the frame it's applied on
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(0..=MAX_DELAY as i64))]
+    netplay_delay: u8,
+
+    /// Netplay: frames run ahead on predicted peer inputs, rolled back when wrong, 0 to wait for them
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=MAX_ROLLBACK as i64))]
+    netplay_rollback: u8,
+
+    /// Log the Comlynx bytes sent and received to a file
+    #[arg(long)]
+    comlynx_log: Option<PathBuf>,
+
+    /// Broadcast the screen and sound to read-only viewers connecting to <addr>
+    #[arg(long, conflicts_with = "headless")]
+    spectator_listen: Option<String>,
+
+    /// Watch the instance broadcasting on <addr>, no cartridge needed
+    #[arg(long, conflicts_with_all = ["spectator_listen", "headless", "players", "netplay_peer", "comlynx_listen", "comlynx_connect"])]
+    spectate: Option<String>,
+
+    /// Serve the TUI over SSH on <addr>, every session runs its own game
+    #[arg(long, conflicts_with_all = ["headless", "spectate", "spectator_listen", "netplay_peer", "comlynx_listen", "comlynx_connect", "gdb_port", "trace", "profile", "record_movie", "comlynx_log"])]
+    ssh_listen: Option<String>,
+
+    /// SSH: host private key file in OpenSSH format, a new key is generated on every start without it
+    #[arg(long, requires = "ssh_listen")]
+    ssh_host_key: Option<PathBuf>,
+
+    /// SSH: OpenSSH authorized_keys file of the public keys allowed in
+    #[arg(long, requires = "ssh_listen")]
+    ssh_authorized_keys: Option<PathBuf>,
+
+    /// SSH: file holding the password asked to the sessions, HOLANI_SSH_PASSWORD is read without it
+    #[arg(long, requires = "ssh_listen")]
+    ssh_password_file: Option<PathBuf>,
+
+    /// SSH: sessions running at the same time, others are turned away
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u8).range(1..))]
+    ssh_max_sessions: u8,
+
+    /// Game library of the cartridges found in <dir>,..., identified against the metadata database
+    #[arg(long, value_delimiter = ',', conflicts_with_all = ["cartridge", "headless", "ssh_listen", "spectate"])]
+    library: Vec<PathBuf>,
+
+    /// Library: additional database, same TSV format as data/lynx_games.tsv or a No-Intro DAT
+    #[arg(long, requires = "library")]
+    library_dat: Option<PathBuf>,
+
+    /// Library: play time and last played file, ~/.holani-tui-library by default
+    #[arg(long, requires = "library")]
+    library_file: Option<PathBuf>,
+
+    /// Input script, frame stamped inputs applied by the core
+    #[arg(short, long, conflicts_with = "play_movie")]
+    input_script: Option<PathBuf>,
+
+    /// Record inputs to a movie file
+    #[arg(long, conflicts_with = "play_movie")]
+    record_movie: Option<PathBuf>,
+
+    /// Play a movie file from power-on, live inputs are ignored
+    #[arg(long)]
+    play_movie: Option<PathBuf>,
+
+    /// Run without terminal UI nor sound
+    #[arg(long, default_value_t = false)]
+    headless: bool,
+
+    /// Headless: number of frames to run
+    #[arg(long, requires = "headless")]
+    frames: Option<u64>,
+
+    /// Headless: maximum run time in seconds
+    #[arg(long, requires = "headless")]
+    seconds: Option<f64>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-fn main() {
+#[derive(Subcommand)]
+enum Command {
+    /// Run headlessly and compare per-frame hashes against a golden file
+    Test {
+        /// Golden file, <frame> <hash> per line
+        #[arg(short, long)]
+        golden: PathBuf,
+
+        /// Number of frames to run
+        #[arg(short, long)]
+        frames: u64,
+
+        /// Write the golden file instead of comparing against it
+        #[arg(short, long, default_value_t = false)]
+        update: bool,
+    },
+}
+
+fn main() -> ExitCode {
 
     env_logger::init();
-    let config = process_args();
+    let args = Args::parse();
+
+    if let Some(addr) = &args.spectate {
+        return spectate(addr, args.mute);
+    }
+
+    if cfg!(feature = "comlynx_shared_memory") && (args.players > 1 || args.comlynx_listen.is_some() || args.comlynx_connect.is_some() || args.netplay_peer.is_some()) {
+        println!("Error: --players, netplay and the Comlynx TCP link need a build with --no-default-features.");
+        return ExitCode::FAILURE;
+    }
+
+    if !cfg!(feature = "comlynx_shared_memory") && args.comlynx && args.players < 2 && args.comlynx_listen.is_none() && args.comlynx_connect.is_none() {
+        println!("Error: This build has no shared memory Comlynx link, use --comlynx-listen or --comlynx-connect.");
+        return ExitCode::FAILURE;
+    }
+
+    let mut config = process_args(&args);
+
+    if let Some(Command::Test { golden, frames, update }) = &args.command {
+        return FrameTest::new(config, *frames, golden.clone(), *update).run();
+    }
+
+    if let Some(addr) = &args.ssh_listen {
+        if config.cartridge().is_none() {
+            println!("Error: A cartridge is required.");
+            return ExitCode::FAILURE;
+        }
+        let auth = match SshAuth::load(&args.ssh_authorized_keys, &args.ssh_password_file) {
+            Ok(auth) => auth,
+            Err(err) => {
+                println!("Error: {}", err);
+                return ExitCode::FAILURE;
+            }
+        };
+        return ssh::serve(addr, &args.ssh_host_key, auth, args.ssh_max_sessions as usize, config);
+    }
+
+    if args.headless {
+        let duration = args.seconds.map(Duration::from_secs_f64);
+        return Headless::new(config, args.frames, duration).run();
+    }
+
+    let library = (!args.library.is_empty()).then(|| open_library(&args));
 
     let mut terminal = ratatui::init(); 
     
@@ -50,30 +293,197 @@ fn main() {
     // execute!(stdout, EnterAlternateScreen, Hide).unwrap();
     enable_raw_mode().unwrap();
 
-    let mut app = App::new(config);
-    
-    app.run(&mut terminal);
+    if let Some(library) = library {
+        LibraryView::new(library).run(&mut terminal, &config);
+        disable_raw_mode().unwrap();
+        ratatui::restore();
+        return ExitCode::SUCCESS;
+    }
+
+    if config.cartridge().is_none() || several_images(&config) {
+        let dir = args.cartridge.clone().unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
+        match CartridgeBrowser::new(dir).run(&mut terminal) {
+            Some((cartridge, member)) => {
+                config.set_cheats(cartridge.with_extension("cht"));
+                config.set_cartridge(cartridge);
+                if let Some(member) = member {
+                    config.set_cartridge_member(member);
+                }
+            }
+            None => {
+                disable_raw_mode().unwrap();
+                ratatui::restore();
+                return ExitCode::SUCCESS;
+            }
+        }
+    }
+
+    let played = App::new(config).map(|mut app| app.run(&mut terminal));
 
     disable_raw_mode().unwrap();
     ratatui::restore();
+
+    match played {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            println!("Error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
 }
   
-fn process_args() -> RunnerConfig {
-    let args = Args::parse();
+/// Read-only view of a `--spectator-listen` instance.
+fn spectate(addr: &str, mute: bool) -> ExitCode {
+    let stream = match SpectatorStream::connect(addr) {
+        Ok(stream) => stream,
+        Err(err) => {
+            println!("Error: Couldn't connect to the spectator stream. {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut terminal = ratatui::init();
+    enable_raw_mode().unwrap();
+
+    Viewer::new(addr.to_string(), stream, mute).run(&mut terminal);
 
+    disable_raw_mode().unwrap();
+    ratatui::restore();
+
+    ExitCode::SUCCESS
+}
+
+/// Cartridge archive holding several images, none chosen.
+fn several_images(config: &RunnerConfig) -> bool {
+    config.cartridge().as_ref()
+        .filter(|cartridge| config.cartridge_member().is_none() && is_archive(cartridge))
+        .and_then(|cartridge| archive::images(cartridge, is_cartridge).ok())
+        .is_some_and(|images| images.len() > 1)
+}
+
+fn open_library(args: &Args) -> Library {
+    let mut database = GameDatabase::bundled();
+    if let Some(dat) = &args.library_dat {
+        if let Err(err) = database.load(dat) {
+            panic!("Library database: {}", err);
+        }
+    }
+    let stats_path = args.library_file.clone().unwrap_or_else(|| {
+        std::env::var_os("HOME").map(PathBuf::from).unwrap_or_default().join(".holani-tui-library")
+    });
+    Library::scan(&args.library, &database, stats_path)
+}
+
+fn process_args(args: &Args) -> RunnerConfig {
     let mut config = RunnerConfig::new();
-    if let Some(rom) = args.rom {
-        config.set_rom(rom);
+    if let Some(rom) = &args.rom {
+        config.set_rom(rom.clone());
+    }
+    if let Some(cartridge) = args.cartridge.as_ref().filter(|path| !path.is_dir()) {
+        config.set_cartridge(cartridge.clone());
+        config.set_cheats(cartridge.with_extension("cht"));
+    }
+    if let Some(member) = &args.cartridge_member {
+        config.set_cartridge_member(member.clone());
+    }
+    let headless = args.headless || args.command.is_some();
+    // SSH sessions play no sound on the server.
+    config.set_mute(args.mute || headless || args.ssh_listen.is_some());
+    config.set_comlynx(args.comlynx || args.comlynx_listen.is_some() || args.comlynx_connect.is_some());
+    if let Some(addr) = &args.comlynx_listen {
+        config.set_comlynx_link(ComlynxEndpoint::Listen(addr.clone()));
+    }
+    if let Some(addr) = &args.comlynx_connect {
+        config.set_comlynx_link(ComlynxEndpoint::Connect(addr.clone()));
+    }
+    if let (Some(peer), Some(bind), Some(player)) = (&args.netplay_peer, &args.netplay_bind, args.netplay_player) {
+        config.set_netplay(NetplaySettings { bind: bind.clone(), peer: peer.clone(), player, delay: args.netplay_delay, rollback: args.netplay_rollback });
+    }
+    if let Some(addr) = &args.spectator_listen {
+        config.set_spectator_listen(addr.clone());
+    }
+    if let Some(log) = &args.comlynx_log {
+        match ComlynxLog::create(log) {
+            Ok(log) => config.set_comlynx_log(log),
+            Err(err) => panic!("Comlynx log: {}", err),
+        }
+    }
+    config.set_headless(headless);
+    if let Some(script) = &args.input_script {
+        config.set_input_script(script.clone());
+    }
+    if let Some(symbols) = &args.symbols {
+        match Symbols::load(symbols) {
+            Ok(symbols) => config.set_symbols(symbols),
+            Err(err) => panic!("Symbols: {}", err),
+        }
+    }
+    if let Some(port) = args.gdb_port {
+        config.set_gdb_port(port);
+    }
+    if let Some(trace) = &args.trace {
+        config.set_trace(trace.clone());
+    }
+    for range in &args.trace_range {
+        let bounds = range.split_once('-').and_then(|(start, end)| Some((parse_address(start)?, parse_address(end)?)));
+        let Some((start, end)) = bounds else {
+            panic!("Trace range: expected <start>-<end>, got '{}'.", range);
+        };
+        config.add_trace_range(start, end);
+    }
+    if let Some(size) = args.trace_ring {
+        config.set_trace_ring(size);
+    }
+    if let Some(profile) = &args.profile {
+        config.set_profile(profile.clone());
+    }
+    for idle in &args.profile_idle {
+        let range = match idle.split_once('-') {
+            Some((start, end)) => parse_address(start).zip(parse_address(end)),
+            None => config.symbols().as_ref().and_then(|symbols| symbols.extent(idle)),
+        };
+        let Some((start, end)) = range else {
+            panic!("Profiler idle: expected <start>-<end> or a symbol, got '{}'.", idle);
+        };
+        config.add_profile_idle(start, end);
+    }
+    if let Some(movie) = &args.record_movie {
+        config.set_record_movie(movie.clone());
+    }
+    if let Some(movie) = &args.play_movie {
+        config.set_play_movie(movie.clone());
     }
-    config.set_cartridge(args.cartridge);
-    config.set_mute(args.mute);
-    config.set_comlynx(args.comlynx);
 
-    let btns = args.buttons.unwrap();
+    for af in &args.autofire {
+        let Some((btn, rate)) = af.split_once(':') else {
+            panic!("Autofire: expected <input>:<rate>, got '{}'.", af);
+        };
+        let btn = btn.parse::<Input>().unwrap_or_else(|err| panic!("Autofire: {}", err));
+        let Ok(rate) = rate.parse::<u8>() else {
+            panic!("Autofire: invalid rate '{}'.", rate);
+        };
+        config.set_autofire(btn, rate);
+    }
+    config.set_autofire_enabled(args.autofire_on);
+
+    for (key, btn) in parse_buttons(args.buttons.as_ref().unwrap()) {
+        config.set_button_mapping(key, btn);
+    }
+    for player in 1..args.players as usize {
+        let btns = args.player_buttons.get(player - 1).map(String::as_str).unwrap_or(PLAYER_BUTTONS[player - 1]);
+        let btns: Vec<String> = btns.split(',').map(str::to_string).collect();
+        config.add_player(parse_buttons(&btns).into_iter().collect());
+    }
+
+    config
+}
+
+
+fn parse_buttons(btns: &[String]) -> Vec<(KeyCode, Input)> {
     if btns.len() != 9 {
         panic!("Buttons mapping should be 9 keys.");
     }
-    for (s, btn) in btns.iter().zip([
+    btns.iter().zip([
         Input::Up,
         Input::Down,
         Input::Left,
@@ -83,14 +493,11 @@ fn process_args() -> RunnerConfig {
         Input::Option1,
         Input::Option2,
         Input::Pause,
-    ]) {
+    ]).map(|(s, btn)| {
         let key = translate_keycode(s);
         if key == KeyCode::Null {
             panic!("Buttons mapping: Unknown key '{}'.", s.as_str());
         }
-        config.set_button_mapping(key, btn);
-    }
-
-    config
+        (key, btn)
+    }).collect()
 }
-
@@ -0,0 +1,76 @@
+use ratatui::{crossterm::event::KeyCode, layout::{Constraint, Layout, Rect}, style::{Color, Style}, text::Line, widgets::{Block, Paragraph, Sparkline}, Frame};
+
+use crate::runner::{debugger::DebugCommand, profiler::ProfileReport};
+
+const BUDGET_HEIGHT: u16 = 6;
+
+/// Hottest routines and frame budget usage, `Delete` resets the counters.
+pub(crate) struct ProfilerView {
+    report: Option<ProfileReport>,
+    command_tx: kanal::Sender<DebugCommand>,
+}
+
+impl ProfilerView {
+    pub(crate) fn new(command_tx: kanal::Sender<DebugCommand>) -> Self {
+        Self {
+            report: None,
+            command_tx,
+        }
+    }
+
+    pub(crate) fn update(&mut self, report: ProfileReport) {
+        self.report = Some(report);
+    }
+
+    /// Returns `true` if the key was consumed.
+    pub(crate) fn handle_key(&mut self, code: KeyCode) -> bool {
+        match code {
+            KeyCode::Delete => self.command_tx.send(DebugCommand::ResetProfile).unwrap(),
+            _ => return false,
+        }
+        true
+    }
+
+    pub(crate) fn draw(&self, f: &mut Frame, area: Rect) {
+        let Some(report) = &self.report else {
+            f.render_widget(Paragraph::new("Waiting for the core..."), area);
+            return;
+        };
+
+        let [routines, budget] = Layout::vertical([
+            Constraint::Fill(1),
+            Constraint::Length(BUDGET_HEIGHT),
+        ]).areas(area);
+
+        let title = format!("Profile - {} frames, {} ticks", report.frames, report.total_ticks);
+        let mut lines = vec![Line::styled(format!("{:>12} {:>6}  addr  routine", "ticks", "%"), Style::default().fg(Color::Cyan))];
+        lines.extend(report.rows.iter().map(|row| {
+            Line::raw(format!(
+                "{:>12} {:>6.2}  {:04X}  {}",
+                row.ticks,
+                report.percent(row.ticks),
+                row.address,
+                row.name.as_deref().unwrap_or_default(),
+            ))
+        }));
+        f.render_widget(Paragraph::new(lines).block(Block::bordered().title(title)), routines);
+
+        let Some((average, peak)) = report.budget_usage() else {
+            f.render_widget(Paragraph::new("Frame budget needs --profile-idle.").block(Block::bordered().title("Frame budget")), budget);
+            return;
+        };
+        // Busy percentage of each frame, the latest frames that fit on the right.
+        let width = budget.width.saturating_sub(2) as usize;
+        let data: Vec<u64> = report.budget.iter()
+            .skip(report.budget.len().saturating_sub(width))
+            .map(|(ticks, busy)| busy * 100 / (*ticks).max(1))
+            .collect();
+        let title = format!("Frame budget {:.1}% avg {:.1}% peak", average * 100., peak * 100.);
+        let sparkline = Sparkline::default()
+            .block(Block::bordered().title(title))
+            .data(&data)
+            .max(100)
+            .style(Style::default().fg(Color::Green));
+        f.render_widget(sparkline, budget);
+    }
+}
@@ -0,0 +1,122 @@
+//! Suzy and Mikey register addresses and field decoding shared by the inspector panels.
+
+pub(crate) const SUZY_BASE: u16 = 0xfc00;
+pub(crate) const MIKEY_BASE: u16 = 0xfd00;
+pub(crate) const REGISTERS_LEN: usize = 0x100;
+
+pub(crate) const HOFF: u16 = 0xfc04;
+pub(crate) const VOFF: u16 = 0xfc06;
+pub(crate) const VIDBAS: u16 = 0xfc08;
+pub(crate) const COLLBAS: u16 = 0xfc0a;
+pub(crate) const SCBNEXT: u16 = 0xfc10;
+pub(crate) const SPRDLINE: u16 = 0xfc12;
+pub(crate) const HPOSSTRT: u16 = 0xfc14;
+pub(crate) const VPOSSTRT: u16 = 0xfc16;
+pub(crate) const SPRHSIZ: u16 = 0xfc18;
+pub(crate) const SPRVSIZ: u16 = 0xfc1a;
+pub(crate) const STRETCH: u16 = 0xfc1c;
+pub(crate) const TILT: u16 = 0xfc1e;
+pub(crate) const COLLOFF: u16 = 0xfc24;
+pub(crate) const SCBADR: u16 = 0xfc2c;
+pub(crate) const PROCADR: u16 = 0xfc2e;
+pub(crate) const MATHD: u16 = 0xfc52;
+pub(crate) const MATHP: u16 = 0xfc56;
+pub(crate) const MATHH: u16 = 0xfc60;
+pub(crate) const MATHM: u16 = 0xfc6c;
+pub(crate) const SPRCTL0: u16 = 0xfc80;
+pub(crate) const SPRCTL1: u16 = 0xfc81;
+pub(crate) const SPRCOLL: u16 = 0xfc82;
+pub(crate) const SUZYBUSEN: u16 = 0xfc90;
+pub(crate) const SPRGO: u16 = 0xfc91;
+pub(crate) const SPRSYS: u16 = 0xfc92;
+pub(crate) const JOYSTICK: u16 = 0xfcb0;
+pub(crate) const SWITCHES: u16 = 0xfcb1;
+
+pub(crate) const TIMERS: u16 = 0xfd00;
+pub(crate) const TIMER_LEN: u16 = 4;
+pub(crate) const TIMER_COUNT: u16 = 8;
+pub(crate) const AUDIO: u16 = 0xfd20;
+pub(crate) const AUDIO_LEN: u16 = 8;
+pub(crate) const AUDIO_CHANNELS: u16 = 4;
+pub(crate) const ATTEN_A: u16 = 0xfd40;
+pub(crate) const MPAN: u16 = 0xfd44;
+pub(crate) const MSTEREO: u16 = 0xfd50;
+pub(crate) const INTSET: u16 = 0xfd81;
+pub(crate) const SYSCTL1: u16 = 0xfd87;
+pub(crate) const IODIR: u16 = 0xfd8a;
+pub(crate) const IODAT: u16 = 0xfd8b;
+pub(crate) const SERCTL: u16 = 0xfd8c;
+pub(crate) const SERDAT: u16 = 0xfd8d;
+pub(crate) const DISPCTL: u16 = 0xfd92;
+pub(crate) const PBKUP: u16 = 0xfd93;
+pub(crate) const DISPADR: u16 = 0xfd94;
+pub(crate) const GREEN0: u16 = 0xfda0;
+pub(crate) const BLUERED0: u16 = 0xfdb0;
+pub(crate) const PALETTE_LEN: u16 = 16;
+
+/// Timer/audio channel register offsets.
+pub(crate) const TIM_BKUP: u16 = 0;
+pub(crate) const TIM_CTLA: u16 = 1;
+pub(crate) const TIM_CNT: u16 = 2;
+pub(crate) const TIM_CTLB: u16 = 3;
+pub(crate) const AUD_VOL: u16 = 0;
+pub(crate) const AUD_SHFTFB: u16 = 1;
+pub(crate) const AUD_OUTVAL: u16 = 2;
+pub(crate) const AUD_L8SHFT: u16 = 3;
+pub(crate) const AUD_TBACK: u16 = 4;
+pub(crate) const AUD_CTL: u16 = 5;
+pub(crate) const AUD_COUNT: u16 = 6;
+pub(crate) const AUD_MISC: u16 = 7;
+
+/// SERCTL bits, written.
+pub(crate) const SERCTL_PAREN: u8 = 0x10;
+pub(crate) const SERCTL_PAREVEN: u8 = 0x01;
+/// SERCTL bits, read.
+pub(crate) const SERCTL_PARERR: u8 = 0x10;
+pub(crate) const SERCTL_OVERRUN: u8 = 0x08;
+pub(crate) const SERCTL_FRAMERR: u8 = 0x04;
+pub(crate) const SERCTL_RXBRK: u8 = 0x02;
+pub(crate) const SERCTL_PARBIT: u8 = 0x01;
+
+pub(crate) const TIMER_NAMES: [&str; TIMER_COUNT as usize] = ["HCOUNT", "T1", "VCOUNT", "T3", "UART", "T5", "T6", "T7"];
+pub(crate) const SPRITE_TYPES: [&str; 8] = ["bg shadow", "bg no coll", "bound shadow", "boundary", "normal", "no coll", "xor shadow", "shadow"];
+
+/// Source clock period in µs of a timer or audio control register, `None` when linked.
+pub(crate) fn clock_period_us(ctl: u8) -> Option<u32> {
+    match ctl & 0x07 {
+        7 => None,
+        n => Some(1 << n),
+    }
+}
+
+pub(crate) fn clock_name(ctl: u8) -> String {
+    match clock_period_us(ctl) {
+        None => "linked".to_string(),
+        Some(us) => format!("{}us", us),
+    }
+}
+
+/// Output frequency of an audio channel, the waveform toggles on every borrow.
+pub(crate) fn audio_frequency(ctl: u8, backup: u8) -> Option<f64> {
+    clock_period_us(ctl).map(|us| 1_000_000f64 / (us as f64 * (backup as f64 + 1.)))
+}
+
+/// Bits per pixel from SPRCTL0.
+pub(crate) fn sprite_bpp(sprctl0: u8) -> u8 {
+    (sprctl0 >> 6) + 1
+}
+
+pub(crate) fn sprite_type(sprctl0: u8) -> &'static str {
+    SPRITE_TYPES[(sprctl0 & 0x07) as usize]
+}
+
+/// Palette entry as 8 bits RGB, from the 4 bits GREEN and BLUERED registers.
+pub(crate) fn palette_rgb(green: u8, bluered: u8) -> (u8, u8, u8) {
+    let expand = |v: u8| (v & 0x0f) * 0x11;
+    (expand(bluered), expand(green), expand(bluered >> 4))
+}
+
+/// Left and right 4 bits attenuation of an audio channel.
+pub(crate) fn attenuation(atten: u8) -> (u8, u8) {
+    (atten >> 4, atten & 0x0f)
+}
@@ -0,0 +1,183 @@
+use std::{fs::File, io::{self, Read, Seek, SeekFrom}, path::Path};
+use flate2::read::GzDecoder;
+use sevenz_rust::{Password, SevenZReader};
+use zip::ZipArchive;
+
+pub(crate) const ARCHIVE_EXTENSIONS: [&str; 3] = ["zip", "gz", "7z"];
+
+/// Archive extension, case insensitive.
+pub(crate) fn is_archive(path: &Path) -> bool {
+    archive_extension(path).is_some()
+}
+
+fn archive_extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase)
+        .filter(|ext| ARCHIVE_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// Largest image read, Lynx cartridges hold up to 1 MB.
+pub(crate) const MAX_IMAGE_LEN: u64 = 2 * 1024 * 1024;
+
+fn error(path: &Path, e: impl std::fmt::Display) -> String {
+    format!("{}: {}", path.display(), e)
+}
+
+/// The file of a gzip archive, named after it.
+fn gzip_name(path: &Path) -> String {
+    path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+fn open_zip(path: &Path) -> Result<ZipArchive<File>, String> {
+    ZipArchive::new(File::open(path).map_err(|e| error(path, e))?).map_err(|e| error(path, e))
+}
+
+/// Up to `limit` bytes of `reader`.
+fn read_to(reader: impl Read, limit: u64) -> io::Result<Vec<u8>> {
+    let mut data = vec![];
+    reader.take(limit).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Names of the files of a zip or 7z archive, from its index, the file named after a gzip one.
+fn names(path: &Path) -> Result<Vec<String>, String> {
+    match archive_extension(path).as_deref() {
+        Some("gz") => Ok(vec![gzip_name(path)]),
+        Some("zip") => Ok(open_zip(path)?.file_names().filter(|name| !name.ends_with('/')).map(str::to_string).collect()),
+        Some("7z") => {
+            let reader = SevenZReader::open(path, Password::empty()).map_err(|e| error(path, e))?;
+            Ok(reader.archive().files.iter().filter(|entry| !entry.is_directory()).map(|entry| entry.name().to_string()).collect())
+        }
+        _ => Err(format!("{}: not an archive.", path.display())),
+    }
+}
+
+/// Files of the archive `path` that `wanted` accepts, up to `limit` bytes each. The other files aren't
+/// decompressed, but the ones before a wanted file in a solid 7z block.
+fn extract(path: &Path, wanted: &dyn Fn(&str) -> bool, limit: u64) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut files = vec![];
+    match archive_extension(path).as_deref() {
+        Some("gz") => {
+            let name = gzip_name(path);
+            if wanted(&name) {
+                let data = read_to(GzDecoder::new(File::open(path).map_err(|e| error(path, e))?), limit).map_err(|e| error(path, e))?;
+                files.push((name, data));
+            }
+        }
+        Some("zip") => {
+            let mut zip = open_zip(path)?;
+            let names: Vec<String> = zip.file_names().filter(|name| !name.ends_with('/') && wanted(name)).map(str::to_string).collect();
+            for name in names {
+                let data = read_to(zip.by_name(&name).map_err(|e| error(path, e))?, limit).map_err(|e| error(path, e))?;
+                files.push((name, data));
+            }
+        }
+        Some("7z") => {
+            let mut reader = SevenZReader::open(path, Password::empty()).map_err(|e| error(path, e))?;
+            let mut left = reader.archive().files.iter().filter(|entry| !entry.is_directory() && wanted(entry.name())).count();
+            if left == 0 {
+                return Ok(files);
+            }
+            reader.for_each_entries(|entry, read| {
+                // The files of a block are decompressed in turn, a file has to be read through for the
+                // next one.
+                if entry.is_directory() || !wanted(entry.name()) {
+                    io::copy(read, &mut io::sink())?;
+                    return Ok(true);
+                }
+                files.push((entry.name().to_string(), read_to(&mut *read, limit)?));
+                left -= 1;
+                if left > 0 {
+                    io::copy(read, &mut io::sink())?;
+                }
+                Ok(left > 0)
+            }).map_err(|e| error(path, e))?;
+        }
+        _ => return Err(format!("{}: not an archive.", path.display())),
+    }
+    Ok(files)
+}
+
+/// Files `is_image` accepts in the archive `path`, with their content. A gzip file is always one, files
+/// larger than `MAX_IMAGE_LEN` are left out.
+pub(crate) fn extract_images(path: &Path, is_image: fn(&Path) -> bool) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let gzip = archive_extension(path).as_deref() == Some("gz");
+    Ok(extract(path, &|name| gzip || is_image(Path::new(name)), MAX_IMAGE_LEN + 1)?
+        .into_iter()
+        .filter(|(_, data)| data.len() as u64 <= MAX_IMAGE_LEN)
+        .collect())
+}
+
+/// Names of the images in the archive `path`, nothing is decompressed.
+pub(crate) fn images(path: &Path, is_image: fn(&Path) -> bool) -> Result<Vec<String>, String> {
+    let gzip = archive_extension(path).as_deref() == Some("gz");
+    Ok(names(path)?.into_iter().filter(|name| gzip || is_image(Path::new(name))).collect())
+}
+
+/// Name of the image `member` of the archive `path`, of the only image when `None`.
+fn image_name(path: &Path, member: Option<&str>, is_image: fn(&Path) -> bool) -> Result<String, String> {
+    let mut images = images(path, is_image)?;
+    match member {
+        Some(member) => images.into_iter().find(|name| name == member)
+            .ok_or_else(|| format!("{}: no '{}' in the archive.", path.display(), member)),
+        None if images.len() == 1 => Ok(images.swap_remove(0)),
+        None if images.is_empty() => Err(format!("{}: no Lynx image in the archive.", path.display())),
+        None => Err(format!("{}: several images in the archive, {}.", path.display(), images.join(", "))),
+    }
+}
+
+/// Uncompressed size of the file `name` of the archive `path`, from its index, or from the gzip trailer.
+fn size(path: &Path, name: &str) -> Result<u64, String> {
+    match archive_extension(path).as_deref() {
+        Some("gz") => {
+            let mut file = File::open(path).map_err(|e| error(path, e))?;
+            let mut trailer = [0; 4];
+            file.seek(SeekFrom::End(-4)).and_then(|_| file.read_exact(&mut trailer)).map_err(|e| error(path, e))?;
+            Ok(u32::from_le_bytes(trailer) as u64)
+        }
+        Some("zip") => Ok(open_zip(path)?.by_name(name).map_err(|e| error(path, e))?.size()),
+        Some("7z") => {
+            let reader = SevenZReader::open(path, Password::empty()).map_err(|e| error(path, e))?;
+            reader.archive().files.iter()
+                .find(|entry| entry.name() == name)
+                .map(|entry| entry.size())
+                .ok_or_else(|| format!("{}: no '{}' in the archive.", path.display(), name))
+        }
+        _ => Err(format!("{}: not an archive.", path.display())),
+    }
+}
+
+/// First `len` bytes of the file `path`, or of the image `member` of the archive `path`, the only image
+/// when `None`, with the size of the whole file.
+pub(crate) fn read_header(path: &Path, member: Option<&str>, is_image: fn(&Path) -> bool, len: u64) -> Result<(Vec<u8>, u64), String> {
+    if !is_archive(path) {
+        let file = File::open(path).map_err(|e| error(path, e))?;
+        let size = file.metadata().map_err(|e| error(path, e))?.len();
+        return Ok((read_to(file, len).map_err(|e| error(path, e))?, size));
+    }
+    let name = image_name(path, member, is_image)?;
+    let (_, header) = extract(path, &|entry| entry == name, len)?
+        .pop()
+        .ok_or_else(|| format!("{}: no '{}' in the archive.", path.display(), name))?;
+    Ok((header, size(path, &name)?))
+}
+
+/// Content of the file `path`, or of the image `member` of the archive `path`, the only image when
+/// `None`. Files larger than `MAX_IMAGE_LEN` are refused.
+pub(crate) fn read(path: &Path, member: Option<&str>, is_image: fn(&Path) -> bool) -> Result<Vec<u8>, String> {
+    let data = match is_archive(path) {
+        false => read_to(File::open(path).map_err(|e| error(path, e))?, MAX_IMAGE_LEN + 1).map_err(|e| error(path, e))?,
+        true => {
+            let name = image_name(path, member, is_image)?;
+            extract(path, &|entry| entry == name, MAX_IMAGE_LEN + 1)?
+                .pop()
+                .map(|(_, data)| data)
+                .ok_or_else(|| format!("{}: no '{}' in the archive.", path.display(), name))?
+        }
+    };
+    if data.len() as u64 > MAX_IMAGE_LEN {
+        return Err(format!("{}: larger than a Lynx image.", path.display()));
+    }
+    Ok(data)
+}
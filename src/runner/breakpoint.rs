@@ -0,0 +1,306 @@
+use std::fmt;
+
+use crate::disasm::Access;
+
+use super::lynx_ext::CpuRegisters;
+
+const SUZY_REGISTERS: (u16, u16) = (0xfc00, 0xfcff);
+const MIKEY_REGISTERS: (u16, u16) = (0xfd00, 0xfdff);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BreakKind {
+    Exec(u16),
+    /// Inclusive address ranges.
+    Read(u16, u16),
+    Write(u16, u16),
+    Access(u16, u16),
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum Operand {
+    A,
+    X,
+    Y,
+    S,
+    P,
+    PC,
+    Memory(u16),
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct Condition {
+    operand: Operand,
+    comparison: Comparison,
+    value: u16,
+}
+
+/// Execution breakpoint or watchpoint, with an optional condition and hit count.
+///
+/// Prompt syntax: `<b|wr|ww|wa> <target> [if <operand> <op> <value>] [hits <n>]`, `b` takes an
+/// address, watchpoints an address, a `<start>-<end>` range, `suzy` or `mikey`.
+/// Operands are `a`, `x`, `y`, `s`, `p`, `pc` or `[<addr>]`, ops `==`, `!=`, `<`, `<=`, `>`, `>=`.
+///
+/// Watchpoints match the operand accesses decoded at opcode fetch, stack, vector and Suzy accesses are
+/// not seen.
+#[derive(Clone)]
+pub(crate) struct Breakpoint {
+    pub id: u32,
+    pub kind: BreakKind,
+    pub condition: Option<Condition>,
+    /// Execution stops from the `hit_target`th hit on.
+    pub hit_target: u32,
+    pub hits: u32,
+}
+
+impl Breakpoint {
+    pub(crate) fn exec(addr: u16) -> Self {
+        Self {
+            id: 0,
+            kind: BreakKind::Exec(addr),
+            condition: None,
+            hit_target: 0,
+            hits: 0,
+        }
+    }
+
+    /// Parses a prompt command, `resolve` translates an address expression.
+    pub(crate) fn parse(cmd: &str, args: &[&str], resolve: &dyn Fn(&str) -> Option<u16>) -> Result<Self, String> {
+        let Some((target, mut args)) = args.split_first() else {
+            return Err(format!("'{}' expects a target.", cmd));
+        };
+
+        let range = || -> Result<(u16, u16), String> {
+            match *target {
+                "suzy" => Ok(SUZY_REGISTERS),
+                "mikey" => Ok(MIKEY_REGISTERS),
+                _ => {
+                    let (start, end) = target.split_once('-').unwrap_or((target, target));
+                    let start = resolve(start).ok_or(format!("Invalid address '{}'.", start))?;
+                    let end = resolve(end).ok_or(format!("Invalid address '{}'.", end))?;
+                    Ok((start.min(end), start.max(end)))
+                }
+            }
+        };
+
+        let kind = match cmd {
+            "b" => BreakKind::Exec(resolve(target).ok_or(format!("Invalid address '{}'.", target))?),
+            "wr" | "ww" | "wa" => {
+                let (start, end) = range()?;
+                match cmd {
+                    "wr" => BreakKind::Read(start, end),
+                    "ww" => BreakKind::Write(start, end),
+                    _ => BreakKind::Access(start, end),
+                }
+            }
+            _ => return Err(format!("Unknown breakpoint type '{}'.", cmd)),
+        };
+
+        let mut bp = Self::exec(0);
+        bp.kind = kind;
+
+        while let Some((keyword, rest)) = args.split_first() {
+            match (*keyword, rest) {
+                ("if", [operand, comparison, value, rest @ ..]) => {
+                    bp.condition = Some(Condition::parse(operand, comparison, value, resolve)?);
+                    args = rest;
+                }
+                ("hits", [count, rest @ ..]) => {
+                    bp.hit_target = count.parse().map_err(|_| format!("Invalid hit count '{}'.", count))?;
+                    args = rest;
+                }
+                _ => return Err(format!("Unexpected '{}'.", keyword)),
+            }
+        }
+
+        Ok(bp)
+    }
+
+    pub(crate) fn exec_address(&self) -> Option<u16> {
+        match self.kind {
+            BreakKind::Exec(addr) => Some(addr),
+            _ => None,
+        }
+    }
+
+    /// Called at the start of every instruction with the data access it's about to do,
+    /// counts the hit and returns `true` if execution should stop.
+    pub(crate) fn hit<F: Fn(u16) -> u8>(&mut self, registers: &CpuRegisters, access: Option<(u16, Access)>, peek: F) -> bool {
+        let in_range = |start: u16, end: u16, kinds: &[Access]| {
+            access.is_some_and(|(addr, kind)| addr >= start && addr <= end && kinds.contains(&kind))
+        };
+
+        let triggered = match self.kind {
+            BreakKind::Exec(addr) => registers.pc == addr,
+            BreakKind::Read(s, e) => in_range(s, e, &[Access::Read, Access::ReadWrite]),
+            BreakKind::Write(s, e) => in_range(s, e, &[Access::Write, Access::ReadWrite]),
+            BreakKind::Access(s, e) => in_range(s, e, &[Access::Read, Access::Write, Access::ReadWrite]),
+        };
+
+        if !triggered || !self.condition.as_ref().is_none_or(|c| c.eval(registers, &peek)) {
+            return false;
+        }
+
+        self.hits += 1;
+        self.hits >= self.hit_target
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.id)?;
+        match self.kind {
+            BreakKind::Exec(addr) => write!(f, "exec ${:04X}", addr)?,
+            BreakKind::Read(s, e) => write!(f, "read ${:04X}-${:04X}", s, e)?,
+            BreakKind::Write(s, e) => write!(f, "write ${:04X}-${:04X}", s, e)?,
+            BreakKind::Access(s, e) => write!(f, "access ${:04X}-${:04X}", s, e)?,
+        }
+        if let Some(condition) = &self.condition {
+            write!(f, " if {}", condition)?;
+        }
+        match self.hit_target {
+            0 | 1 => write!(f, " ({} hits)", self.hits),
+            target => write!(f, " ({}/{} hits)", self.hits, target),
+        }
+    }
+}
+
+impl Condition {
+    fn parse(operand: &str, comparison: &str, value: &str, resolve: &dyn Fn(&str) -> Option<u16>) -> Result<Self, String> {
+        let operand = match operand.to_lowercase().as_str() {
+            "a" => Operand::A,
+            "x" => Operand::X,
+            "y" => Operand::Y,
+            "s" => Operand::S,
+            "p" => Operand::P,
+            "pc" => Operand::PC,
+            _ => {
+                let addr = operand.strip_prefix('[').and_then(|o| o.strip_suffix(']')).and_then(resolve);
+                Operand::Memory(addr.ok_or(format!("Invalid operand '{}'.", operand))?)
+            }
+        };
+        let comparison = match comparison {
+            "==" => Comparison::Eq,
+            "!=" => Comparison::Ne,
+            "<" => Comparison::Lt,
+            "<=" => Comparison::Le,
+            ">" => Comparison::Gt,
+            ">=" => Comparison::Ge,
+            other => return Err(format!("Invalid comparison '{}'.", other)),
+        };
+        let value = resolve(value).ok_or(format!("Invalid value '{}'.", value))?;
+
+        Ok(Self {
+            operand,
+            comparison,
+            value,
+        })
+    }
+
+    fn eval<F: Fn(u16) -> u8>(&self, registers: &CpuRegisters, peek: &F) -> bool {
+        let lhs = match self.operand {
+            Operand::A => registers.a as u16,
+            Operand::X => registers.x as u16,
+            Operand::Y => registers.y as u16,
+            Operand::S => registers.s as u16,
+            Operand::P => registers.p as u16,
+            Operand::PC => registers.pc,
+            Operand::Memory(addr) => peek(addr) as u16,
+        };
+        match self.comparison {
+            Comparison::Eq => lhs == self.value,
+            Comparison::Ne => lhs != self.value,
+            Comparison::Lt => lhs < self.value,
+            Comparison::Le => lhs <= self.value,
+            Comparison::Gt => lhs > self.value,
+            Comparison::Ge => lhs >= self.value,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.operand {
+            Operand::A => write!(f, "a")?,
+            Operand::X => write!(f, "x")?,
+            Operand::Y => write!(f, "y")?,
+            Operand::S => write!(f, "s")?,
+            Operand::P => write!(f, "p")?,
+            Operand::PC => write!(f, "pc")?,
+            Operand::Memory(addr) => write!(f, "[${:04X}]", addr)?,
+        }
+        let comparison = match self.comparison {
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        };
+        write!(f, " {} ${:02X}", comparison, self.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Option<u16> {
+        u16::from_str_radix(s.trim_start_matches('$'), 16).ok()
+    }
+
+    fn parse(command: &str) -> Result<Breakpoint, String> {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        Breakpoint::parse(words[0], &words[1..], &hex)
+    }
+
+    #[test]
+    fn parses_targets() {
+        assert_eq!(parse("b 0240").unwrap().to_string(), "0: exec $0240 (0 hits)");
+        assert_eq!(parse("wr fd00-fc00").unwrap().to_string(), "0: read $FC00-$FD00 (0 hits)");
+        assert_eq!(parse("ww 1234").unwrap().to_string(), "0: write $1234-$1234 (0 hits)");
+        assert_eq!(parse("wa suzy").unwrap().to_string(), "0: access $FC00-$FCFF (0 hits)");
+        assert_eq!(parse("wa mikey").unwrap().to_string(), "0: access $FD00-$FDFF (0 hits)");
+    }
+
+    #[test]
+    fn parses_conditions_and_hits() {
+        let bp = parse("b 0240 if [00a0] >= 10 hits 3").unwrap();
+        assert_eq!(bp.to_string(), "0: exec $0240 if [$00A0] >= $10 (0/3 hits)");
+        let bp = parse("ww 2000 hits 2 if A != ff").unwrap();
+        assert_eq!(bp.to_string(), "0: write $2000-$2000 if a != $FF (0/2 hits)");
+    }
+
+    #[test]
+    fn rejects_bad_commands() {
+        assert_eq!(parse("b").err().unwrap(), "'b' expects a target.");
+        assert_eq!(parse("x 0240").err().unwrap(), "Unknown breakpoint type 'x'.");
+        assert_eq!(parse("b zz").err().unwrap(), "Invalid address 'zz'.");
+        assert_eq!(parse("wr 10-zz").err().unwrap(), "Invalid address 'zz'.");
+        assert_eq!(parse("b 0240 if q == 1").err().unwrap(), "Invalid operand 'q'.");
+        assert_eq!(parse("b 0240 if a =< 1").err().unwrap(), "Invalid comparison '=<'.");
+        assert_eq!(parse("b 0240 hits many").err().unwrap(), "Invalid hit count 'many'.");
+        assert_eq!(parse("b 0240 if a ==").err().unwrap(), "Unexpected 'if'.");
+    }
+
+    #[test]
+    fn stops_on_the_target_hit() {
+        let mut bp = parse("wr 00a0 if x == 2 hits 2").unwrap();
+        let registers = CpuRegisters { x: 2, ..Default::default() };
+        let peek = |_| 0;
+        assert!(!bp.hit(&registers, Some((0x00a0, Access::Write)), peek));
+        assert!(!bp.hit(&CpuRegisters::default(), Some((0x00a0, Access::Read)), peek));
+        assert!(!bp.hit(&registers, Some((0x00a0, Access::Read)), peek));
+        assert!(bp.hit(&registers, Some((0x00a0, Access::ReadWrite)), peek));
+        assert_eq!(bp.hits, 2);
+    }
+}
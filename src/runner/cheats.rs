@@ -0,0 +1,181 @@
+use std::{fmt::Write as _, path::Path};
+use holani::lynx::Lynx;
+
+use super::lynx_ext::LynxExt;
+
+/// Searched memory, below the Suzy and Mikey register pages.
+const RAM_END: u16 = 0xfc00;
+/// Search results reported to the panel.
+const MAX_RESULTS: usize = 64;
+
+/// A byte written every frame while enabled.
+#[derive(Clone)]
+pub(crate) struct Cheat {
+    pub id: u32,
+    pub address: u16,
+    pub value: u8,
+    pub enabled: bool,
+}
+
+impl Cheat {
+    pub(crate) fn new(address: u16, value: u8) -> Self {
+        Self {
+            id: 0,
+            address,
+            value,
+            enabled: true,
+        }
+    }
+}
+
+/// Cheats file, one cheat per line: `<address> <value> [off]`, hexadecimal, `#` starts a comment.
+pub(crate) fn load_cheats(path: &Path) -> Result<Vec<Cheat>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut cheats = vec![];
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (address, value, enabled) = match fields[..] {
+            [address, value] => (address, value, true),
+            [address, value, "off"] => (address, value, false),
+            _ => return Err(format!("line {}: expected '<address> <value> [off]'.", n + 1)),
+        };
+        let address = u16::from_str_radix(address, 16).map_err(|_| format!("line {}: invalid address '{}'.", n + 1, address))?;
+        let value = u8::from_str_radix(value, 16).map_err(|_| format!("line {}: invalid value '{}'.", n + 1, value))?;
+        cheats.push(Cheat { enabled, ..Cheat::new(address, value) });
+    }
+    Ok(cheats)
+}
+
+pub(crate) fn save_cheats(path: &Path, cheats: &[Cheat]) -> Result<(), String> {
+    let text = cheats.iter().fold(String::new(), |mut text, cheat| {
+        let _ = writeln!(text, "{:04x} {:02x}{}", cheat.address, cheat.value, if cheat.enabled { "" } else { " off" });
+        text
+    });
+    std::fs::write(path, text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum SearchFilter {
+    Exact(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+}
+
+impl SearchFilter {
+    fn keep(&self, previous: u8, current: u8) -> bool {
+        match self {
+            SearchFilter::Exact(value) => current == *value,
+            SearchFilter::Changed => current != previous,
+            SearchFilter::Unchanged => current == previous,
+            SearchFilter::Increased => current > previous,
+            SearchFilter::Decreased => current < previous,
+        }
+    }
+}
+
+/// RAM search narrowing down the candidate addresses, each filter compares the memory with the previous step.
+pub(crate) struct MemorySearch {
+    snapshot: Vec<u8>,
+    candidates: Vec<u16>,
+}
+
+impl MemorySearch {
+    pub(crate) fn new(lynx: &Lynx) -> Self {
+        Self::start(lynx.peek_range(0, RAM_END as usize))
+    }
+
+    /// Every address of `memory` is a candidate.
+    fn start(memory: Vec<u8>) -> Self {
+        Self {
+            candidates: (0..memory.len()).map(|addr| addr as u16).collect(),
+            snapshot: memory,
+        }
+    }
+
+    pub(crate) fn filter(&mut self, lynx: &Lynx, filter: SearchFilter) {
+        self.narrow(lynx.peek_range(0, RAM_END as usize), filter);
+    }
+
+    fn narrow(&mut self, current: Vec<u8>, filter: SearchFilter) {
+        self.candidates.retain(|addr| filter.keep(self.snapshot[*addr as usize], current[*addr as usize]));
+        self.snapshot = current;
+    }
+
+    pub(crate) fn candidates(&self) -> usize {
+        self.candidates.len()
+    }
+
+    /// First candidates as `(address, previous step value, current value)`.
+    pub(crate) fn results(&self, lynx: &Lynx) -> Vec<(u16, u8, u8)> {
+        self.candidates.iter()
+            .take(MAX_RESULTS)
+            .map(|addr| (*addr, self.snapshot[*addr as usize], lynx.peek(*addr)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn narrows_candidates() {
+        let mut search = MemorySearch::start(vec![5, 5, 5, 5, 9, 0]);
+        search.narrow(vec![5, 6, 4, 5, 9, 0], SearchFilter::Changed);
+        assert_eq!(search.candidates, vec![1, 2]);
+
+        let mut search = MemorySearch::start(vec![5, 5, 5, 5, 9, 0]);
+        search.narrow(vec![5, 6, 4, 5, 9, 0], SearchFilter::Unchanged);
+        assert_eq!(search.candidates, vec![0, 3, 4, 5]);
+        search.narrow(vec![5, 6, 4, 5, 9, 0], SearchFilter::Exact(5));
+        assert_eq!(search.candidates, vec![0, 3]);
+        assert_eq!(search.candidates(), 2);
+
+        let mut search = MemorySearch::start(vec![1, 1, 1, 0xff]);
+        search.narrow(vec![2, 1, 0, 0], SearchFilter::Increased);
+        assert_eq!(search.candidates, vec![0]);
+        let mut search = MemorySearch::start(vec![1, 1, 1, 0xff]);
+        search.narrow(vec![2, 1, 0, 0], SearchFilter::Decreased);
+        assert_eq!(search.candidates, vec![2, 3]);
+        // Compared with the previous step, not the start.
+        search.narrow(vec![2, 1, 1, 0], SearchFilter::Increased);
+        assert_eq!(search.candidates, vec![2]);
+        search.narrow(vec![2, 1, 1, 0], SearchFilter::Changed);
+        assert!(search.candidates.is_empty());
+    }
+
+    #[test]
+    fn loads_and_saves_cheats() {
+        let dir = std::env::temp_dir().join(format!("holani-cheats-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("game.cht");
+
+        std::fs::write(&path, "# Lives\n00a4 09\n\n  1F00 FF off  # Timer\n").unwrap();
+        let cheats = load_cheats(&path).unwrap();
+        let fields: Vec<(u16, u8, bool)> = cheats.iter().map(|c| (c.address, c.value, c.enabled)).collect();
+        assert_eq!(fields, vec![(0x00a4, 0x09, true), (0x1f00, 0xff, false)]);
+
+        save_cheats(&path, &cheats).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "00a4 09\n1f00 ff off\n");
+        let reloaded: Vec<(u16, u8, bool)> = load_cheats(&path).unwrap().iter().map(|c| (c.address, c.value, c.enabled)).collect();
+        assert_eq!(reloaded, fields);
+
+        for (text, error) in [
+            ("00a4\n", "line 1: expected '<address> <value> [off]'."),
+            ("00a4 09 on\n", "line 1: expected '<address> <value> [off]'."),
+            ("00a4 09\n10000 01\n", "line 2: invalid address '10000'."),
+            ("00a4 100\n", "line 1: invalid value '100'."),
+            ("zz 01\n", "line 1: invalid address 'zz'."),
+        ] {
+            std::fs::write(&path, text).unwrap();
+            assert_eq!(load_cheats(&path).err().as_deref(), Some(error), "{:?}", text);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
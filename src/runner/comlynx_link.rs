@@ -0,0 +1,243 @@
+use std::{collections::{HashMap, VecDeque}, io::{self, Read, Write}, net::{TcpListener, TcpStream}, sync::{atomic::{AtomicBool, Ordering}, Arc}};
+use log::{info, warn};
+
+/// Wire frame: sender tick, little endian, then the byte.
+const FRAME_LEN: usize = 9;
+/// One byte at the fastest Comlynx rate, 11 bits at 62500 bauds, in crystal ticks.
+const BYTE_TICKS: u64 = 2816;
+/// Margin added to the measured delay to absorb the network jitter, 1 ms.
+const JITTER_TICKS: i64 = 16_000;
+/// Lower delay measurements are followed by 1/DELAY_DECAY of the difference.
+const DELAY_DECAY: i64 = 64;
+
+/// `(sender, sender tick, byte)`, the sender is the instance index on an in-process cable.
+type Transfer = (usize, u64, u8);
+
+#[derive(Clone)]
+pub(crate) enum ComlynxEndpoint {
+    Listen(String),
+    Connect(String),
+    /// In-process cable, the instance index, bytes are sent to every other instance.
+    Cable(usize, Vec<kanal::Sender<Transfer>>, kanal::Receiver<Transfer>),
+}
+
+impl ComlynxEndpoint {
+    /// Cable linking `instances` cores of this process, none for a single one.
+    pub(crate) fn cable(instances: usize) -> Vec<ComlynxEndpoint> {
+        if instances < 2 {
+            return vec![];
+        }
+        let channels: Vec<_> = (0..instances).map(|_| kanal::unbounded::<Transfer>()).collect();
+        channels.iter().enumerate()
+            .map(|(n, (_, rx))| {
+                let peers = channels.iter().enumerate().filter(|(peer, _)| *peer != n).map(|(_, (tx, _))| tx.clone()).collect();
+                ComlynxEndpoint::Cable(n, peers, rx.clone())
+            })
+            .collect()
+    }
+}
+
+/// Comlynx cable to other instances, over TCP or in-process.
+///
+/// Bytes are sent with the sender tick. The receiver replays them with the sender timing, delayed by
+/// the transit time measured on the received bytes plus a jitter margin, per sender as each instance
+/// counts ticks from its own start. A late byte raises the delay, it goes down again slowly when the
+/// network gets faster.
+///
+/// A clone shares the channels, it saves the state of an in-process cable between two transfers.
+#[derive(Clone)]
+pub(crate) struct ComlynxLink {
+    id: usize,
+    tx: Vec<kanal::Sender<Transfer>>,
+    rx: kanal::Receiver<Transfer>,
+    pending: VecDeque<Transfer>,
+    delays: HashMap<usize, i64>,
+    next_delivery: u64,
+    connected: bool,
+    /// Set once the TCP peer is connected, bytes sent before are dropped. Always linked in-process.
+    online: Option<Arc<AtomicBool>>,
+}
+
+impl ComlynxLink {
+    /// A listening link accepts its peer in the background, the game runs unlinked until it connects.
+    pub(crate) fn open(endpoint: &ComlynxEndpoint) -> io::Result<Self> {
+        let (tx, writer_rx) = kanal::unbounded::<Transfer>();
+        let (reader_tx, rx) = kanal::unbounded::<Transfer>();
+        let online = Arc::new(AtomicBool::new(false));
+
+        match endpoint {
+            ComlynxEndpoint::Listen(addr) => {
+                let listener = TcpListener::bind(addr)?;
+                info!("Comlynx waiting for a peer on {}.", listener.local_addr()?);
+                let online = online.clone();
+                std::thread::Builder::new()
+                    .name("Comlynx accept".to_string())
+                    .spawn(move || {
+                        let started = listener.accept().and_then(|(stream, _)| Self::start(stream, writer_rx, reader_tx, &online));
+                        if let Err(err) = started {
+                            warn!("Comlynx accept: {}", err);
+                        }
+                    })?;
+            }
+            ComlynxEndpoint::Connect(addr) => Self::start(TcpStream::connect(addr)?, writer_rx, reader_tx, &online)?,
+            ComlynxEndpoint::Cable(id, peers, rx) => return Ok(Self::new(*id, peers.clone(), rx.clone(), None)),
+        }
+
+        Ok(Self::new(0, vec![tx], rx, Some(online)))
+    }
+
+    /// Carries the link channels over the connected `stream`.
+    fn start(stream: TcpStream, writer_rx: kanal::Receiver<Transfer>, reader_tx: kanal::Sender<Transfer>, online: &AtomicBool) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        info!("Comlynx connected to {}.", stream.peer_addr()?);
+
+        let mut writer = stream.try_clone()?;
+        std::thread::Builder::new()
+            .name("Comlynx TX".to_string())
+            .spawn(move || {
+                while let Ok((_, tick, data)) = writer_rx.recv() {
+                    let mut frame = [0; FRAME_LEN];
+                    frame[..8].copy_from_slice(&tick.to_le_bytes());
+                    frame[8] = data;
+                    if let Err(err) = writer.write_all(&frame) {
+                        warn!("Comlynx send: {}", err);
+                        return;
+                    }
+                }
+            })?;
+
+        let mut reader = stream;
+        std::thread::Builder::new()
+            .name("Comlynx RX".to_string())
+            .spawn(move || {
+                let mut frame = [0; FRAME_LEN];
+                while reader.read_exact(&mut frame).is_ok() {
+                    let tick = u64::from_le_bytes(frame[..8].try_into().unwrap());
+                    if reader_tx.send((0, tick, frame[8])).is_err() {
+                        return;
+                    }
+                }
+            })?;
+
+        online.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn new(id: usize, tx: Vec<kanal::Sender<Transfer>>, rx: kanal::Receiver<Transfer>, online: Option<Arc<AtomicBool>>) -> Self {
+        Self {
+            id,
+            tx,
+            rx,
+            pending: VecDeque::new(),
+            delays: HashMap::new(),
+            next_delivery: 0,
+            connected: true,
+            online,
+        }
+    }
+
+    pub(crate) fn send(&self, tick: u64, data: u8) {
+        if self.online.as_ref().is_some_and(|online| !online.load(Ordering::Relaxed)) {
+            return;
+        }
+        self.tx.iter().for_each(|tx| {
+            let _ = tx.send((self.id, tick, data));
+        });
+    }
+
+    /// Next received byte due at `tick`.
+    pub(crate) fn receive(&mut self, tick: u64) -> Option<u8> {
+        loop {
+            match self.rx.try_recv() {
+                Ok(Some((sender, remote_tick, data))) => {
+                    let measured = tick as i64 - remote_tick as i64;
+                    let delay = match self.delays.get(&sender) {
+                        Some(delay) if measured <= *delay => delay - (delay - measured) / DELAY_DECAY,
+                        _ => measured,
+                    };
+                    self.delays.insert(sender, delay);
+                    self.pending.push_back((sender, remote_tick, data));
+                }
+                Ok(None) => break,
+                Err(_) => {
+                    if self.connected {
+                        warn!("Comlynx peer disconnected.");
+                        self.connected = false;
+                    }
+                    break;
+                }
+            }
+        }
+
+        let (sender, remote_tick, data) = *self.pending.front()?;
+        let due = (remote_tick as i64 + self.delays[&sender] + JITTER_TICKS).max(self.next_delivery as i64);
+        if (tick as i64) < due {
+            return None;
+        }
+        self.pending.pop_front();
+        self.next_delivery = tick + BYTE_TICKS;
+        Some(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cable() -> (ComlynxLink, ComlynxLink) {
+        let endpoints = ComlynxEndpoint::cable(2);
+        (ComlynxLink::open(&endpoints[0]).unwrap(), ComlynxLink::open(&endpoints[1]).unwrap())
+    }
+
+    #[test]
+    fn delivers_after_measured_delay() {
+        let (a, mut b) = cable();
+        a.send(1_000, 0x42);
+        // Seen 500 ticks after it was sent, due after the jitter margin.
+        assert_eq!(b.receive(1_500), None);
+        assert_eq!(b.delays[&0], 500);
+        assert_eq!(b.receive(1_000 + 500 + JITTER_TICKS as u64 - 1), None);
+        assert_eq!(b.receive(1_000 + 500 + JITTER_TICKS as u64), Some(0x42));
+        assert_eq!(b.receive(u64::MAX / 2), None);
+    }
+
+    #[test]
+    fn spaces_bursts() {
+        let (a, mut b) = cable();
+        a.send(1_000, 1);
+        a.send(1_000, 2);
+        let due = 1_000 + JITTER_TICKS as u64;
+        assert_eq!(b.receive(1_000), None);
+        assert_eq!(b.receive(due), Some(1));
+        assert_eq!(b.receive(due + 1), None);
+        assert_eq!(b.receive(due + BYTE_TICKS - 1), None);
+        assert_eq!(b.receive(due + BYTE_TICKS), Some(2));
+    }
+
+    #[test]
+    fn raises_delay_on_late_bytes_and_decays() {
+        let (a, mut b) = cable();
+        a.send(1_000, 1);
+        assert_eq!(b.receive(1_500), None);
+        assert_eq!(b.receive(20_000), Some(1));
+
+        // Late: the delay follows right away.
+        a.send(100_000, 2);
+        assert_eq!(b.receive(110_000), None);
+        assert_eq!(b.delays[&0], 10_000);
+        assert_eq!(b.receive(100_000 + 10_000 + JITTER_TICKS as u64 - 1), None);
+        assert_eq!(b.receive(100_000 + 10_000 + JITTER_TICKS as u64), Some(2));
+
+        // Faster again: the delay goes down by 1/DELAY_DECAY of the difference per byte.
+        let mut delay = 10_000;
+        for n in 0..200u64 {
+            let sent = 200_000 + n * 100_000;
+            a.send(sent, 3);
+            assert_eq!(b.receive(sent + 100), None);
+            delay -= (delay - 100) / DELAY_DECAY;
+            assert_eq!(b.delays[&0], delay);
+            assert_eq!(b.receive(sent + delay as u64 + JITTER_TICKS as u64), Some(3));
+        }
+        assert!(delay < 1_000, "{}", delay);
+    }
+}
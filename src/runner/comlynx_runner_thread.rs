@@ -1,11 +1,11 @@
 use std::{collections::VecDeque, time::{Duration, Instant}};
 use holani::{cartridge::lnx_header::LNXRotation, lynx::Lynx};
-use log::trace;
+use log::{error, trace};
 use rodio::{OutputStream, Sink};
 
-use crate::sound_source::SoundSource;
+use crate::{cartridge_browser::is_cartridge, sound_source::SoundSource};
 
-use super::{RunnerConfig, RunnerThread, CRYSTAL_FREQUENCY, SAMPLE_TICKS};
+use super::{archive, comlynx_link::ComlynxLink, debugger::Debugger, input_script::InputScript, movie::{load_movie, MovieRecorder}, spectator::SpectatorServer, RunnerConfig, RunnerThread, CRYSTAL_FREQUENCY, SAMPLE_TICKS};
 
 const TICK_GROUP: u32 = 8;
 const TICK_LENGTH: Duration = Duration::from_nanos((1_000_000_000f32 / CRYSTAL_FREQUENCY as f32 * TICK_GROUP as f32) as u64);
@@ -20,6 +20,17 @@ pub(crate) struct ComlynxRunnerThread {
     input_rx: kanal::Receiver<(u8, u8)>,
     update_display_tx: kanal::Sender<Vec<u8>>,
     rotation_tx: kanal::Sender<LNXRotation>,
+    frame: u64,
+    input_script: Option<InputScript>,
+    movie_recorder: Option<MovieRecorder>,
+    /// Last input read from the UI, not applied yet.
+    live_input: Option<(u8, u8)>,
+    /// Frame of the last inputs poll.
+    input_frame: Option<u64>,
+    debugger: Debugger,
+    spectators: Option<SpectatorServer>,
+    link: Option<ComlynxLink>,
+    ticks: u64,
     sink: Option<Sink>,
     stream: Option<OutputStream>,
 }
@@ -30,6 +41,7 @@ impl ComlynxRunnerThread {
         input_rx: kanal::Receiver<(u8, u8)>, 
         update_display_tx: kanal::Sender<Vec<u8>>, 
         rotation_tx: kanal::Sender<LNXRotation>,
+        debugger: Debugger,
     ) -> Self {
         Self {
             lynx: Lynx::new(),
@@ -38,6 +50,15 @@ impl ComlynxRunnerThread {
             input_rx,
             update_display_tx,
             rotation_tx,
+            frame: 0,
+            input_script: None,
+            movie_recorder: None,
+            live_input: None,
+            input_frame: None,
+            debugger,
+            spectators: None,
+            link: None,
+            ticks: 0,
             sound_tick: 0,
             sound_sample: VecDeque::new(),
             sample_ticks: SAMPLE_TICKS,
@@ -47,7 +68,7 @@ impl ComlynxRunnerThread {
     }
 
     fn sound(&mut self) {
-        if self.config.mute() {
+        if self.config.mute() && self.spectators.is_none() {
             return;
         }
 
@@ -57,7 +78,28 @@ impl ComlynxRunnerThread {
         }
 
         self.sound_tick = 0;
-        self.sound_sample.push_back(self.lynx.audio_sample());
+        let sample = self.lynx.audio_sample();
+        if let Some(spectators) = self.spectators.as_mut() {
+            spectators.sample(sample);
+        }
+        if !self.config.mute() {
+            self.sound_sample.push_back(sample);
+        }
+    }
+
+    /// Carries the serial bytes over the TCP link, the core echoes its own bytes.
+    fn comlynx(&mut self) {
+        let Some(link) = self.link.as_mut() else {
+            return;
+        };
+        if let Some(data) = self.lynx.comlynx_take_tx() {
+            link.send(self.ticks, data);
+            self.debugger.comlynx_transfer(&self.lynx, true, data);
+        }
+        if let Some(data) = link.receive(self.ticks) {
+            self.lynx.comlynx_receive(data);
+            self.debugger.comlynx_transfer(&self.lynx, false, data);
+        }
     }
 
     fn display(&mut self) {
@@ -66,15 +108,43 @@ impl ComlynxRunnerThread {
         }
         trace!("Display updated.");
         let screen = self.lynx.screen_rgb().clone();
+        if let Some(spectators) = self.spectators.as_mut() {
+            spectators.frame(&screen);
+        }
         let _ = self.update_display_tx.try_send(screen).is_ok();
+        self.frame += 1;
+        self.debugger.frame_done(&mut self.lynx);
+    }
+
+    fn record(&mut self, joy: u8, sw: u8) {
+        if let Some(Err(err)) = self.movie_recorder.as_mut().map(|recorder| recorder.record(self.frame, joy, sw)) {
+            error!("Movie recording stopped: {}", err);
+            self.movie_recorder = None;
+        }
     }
 
     fn inputs(&mut self) -> bool {
         if self.input_rx.is_disconnected() {
             return true;
-        } else if let Ok(Some((joy, sw))) = self.input_rx.try_recv() {
+        } else if let Ok(Some(input)) = self.input_rx.try_recv() {
+            if self.config.play_movie().is_none() {
+                self.live_input = Some(input);
+            }
+        }
+        // Movies are played back at frame starts, live inputs wait for the next one while recording.
+        let frame_start = self.input_frame != Some(self.frame);
+        self.input_frame = Some(self.frame);
+        if frame_start || self.movie_recorder.is_none() {
+            if let Some((joy, sw)) = self.live_input.take() {
+                self.lynx.set_joystick_u8(joy);
+                self.lynx.set_switches_u8(sw);
+                self.record(joy, sw);
+            }
+        }
+        if let Some((joy, sw)) = self.input_script.as_mut().and_then(|script| script.next(self.frame)) {
             self.lynx.set_joystick_u8(joy);
             self.lynx.set_switches_u8(sw);
+            self.record(joy, sw);
         }
         false
     }   
@@ -83,24 +153,30 @@ impl ComlynxRunnerThread {
 impl RunnerThread for ComlynxRunnerThread {
     fn initialize(&mut self) -> Result<(), &str> {
         if let Some(rom) = self.config.rom() {
-            let data = std::fs::read(rom);            
-            if data.is_err() {
-                return Err("Couldn't not load ROM file.");
-            }
-            if self.lynx.load_rom_from_slice(&data.unwrap()).is_err() {
+            let data = match archive::read(rom, None, |_| true) {
+                Ok(data) => data,
+                Err(err) => {
+                    error!("ROM: {}", err);
+                    return Err("Couldn't not load ROM file.");
+                }
+            };
+            if self.lynx.load_rom_from_slice(&data).is_err() {
                 return Err("Couldn't not load ROM file.");
             }
             trace!("ROM loaded.");
         }
 
         match self.config.cartridge() {
-            None => panic!("A cartridge is required."),
+            None => return Err("A cartridge is required."),
             Some(cart) => {
-                let data = std::fs::read(cart);            
-                if data.is_err() {
-                    return Err("Couldn't not load Cartridge file.");
-                }
-                if self.lynx.load_cart_from_slice(&data.unwrap()).is_err() {
+                let data = match archive::read(cart, self.config.cartridge_member().as_deref(), is_cartridge) {
+                    Ok(data) => data,
+                    Err(err) => {
+                        error!("Cartridge: {}", err);
+                        return Err("Couldn't not load Cartridge file.");
+                    }
+                };
+                if self.lynx.load_cart_from_slice(&data).is_err() {
                     return Err("Couldn't not load Cartridge file.");
                 }
                 trace!("ROM loaded.");
@@ -108,6 +184,63 @@ impl RunnerThread for ComlynxRunnerThread {
         }
 
         trace!("Cart loaded.");
+
+        if let Some(script) = self.config.input_script() {
+            match InputScript::load(script) {
+                Err(err) => {
+                    error!("Input script: {}", err);
+                    return Err("Couldn't load input script file.");
+                }
+                Ok(script) => self.input_script = Some(script),
+            }
+            trace!("Input script loaded.");
+        }
+
+        if let Some(movie) = self.config.play_movie() {
+            match load_movie(movie) {
+                Err(err) => {
+                    error!("Movie: {}", err);
+                    return Err("Couldn't load movie file.");
+                }
+                Ok(script) => self.input_script = Some(script),
+            }
+            trace!("Movie loaded.");
+        }
+
+        if let Some(movie) = self.config.record_movie() {
+            match MovieRecorder::create(movie) {
+                Err(err) => {
+                    error!("Movie: {}", err);
+                    return Err("Couldn't create movie file.");
+                }
+                Ok(recorder) => self.movie_recorder = Some(recorder),
+            }
+            trace!("Movie recording.");
+        }
+
+        if let Some(endpoint) = self.config.comlynx_link() {
+            match ComlynxLink::open(endpoint) {
+                Err(err) => {
+                    error!("Comlynx link: {}", err);
+                    return Err("Couldn't open the Comlynx link.");
+                }
+                Ok(link) => {
+                    self.link = Some(link);
+                    self.debugger.set_comlynx_linked();
+                }
+            }
+        }
+
+        if let Some(addr) = self.config.spectator_listen() {
+            match SpectatorServer::listen(addr) {
+                Err(err) => {
+                    error!("Spectators: {}", err);
+                    return Err("Couldn't open the spectator socket.");
+                }
+                Ok(server) => self.spectators = Some(server),
+            }
+        }
+
         self.rotation_tx.send(self.lynx.rotation()).unwrap();
 
         Ok(())
@@ -128,22 +261,37 @@ impl RunnerThread for ComlynxRunnerThread {
         }
 
         loop {
-            while Instant::now() < self.next_ticks_trigger {
-                if let Ok(Some(())) = sample_req_rx.try_recv() {
-                    sample_rec_tx.send(self.sound_sample.pop_front().unwrap_or((0, 0))).unwrap();
+            // Headless runs as fast as possible, muted.
+            if !self.config.headless() {
+                while Instant::now() < self.next_ticks_trigger {
+                    if let Ok(Some(())) = sample_req_rx.try_recv() {
+                        sample_rec_tx.send(self.sound_sample.pop_front().unwrap_or((0, 0))).unwrap();
+                    }
                 }
+                self.next_ticks_trigger = Instant::now() + TICK_LENGTH;
             }
-            self.next_ticks_trigger = Instant::now() + TICK_LENGTH;
 
             if self.inputs() {
                 return;
             }
 
+            self.debugger.update(&mut self.lynx, self.frame);
+
+            if self.debugger.paused() {
+                self.debugger.wait(&mut self.lynx);
+                continue;
+            }
+
             for _ in 0..TICK_GROUP {
-                self.lynx.tick();
+                if !self.debugger.tick(&mut self.lynx) {
+                    break;
+                }
+                self.ticks += 1;
                 self.sound();
             }
 
+            self.comlynx();
+
             self.display();
         }
     }
@@ -0,0 +1,248 @@
+use std::{collections::VecDeque, fs::File, io::{BufWriter, Write}, path::Path};
+use holani::lynx::Lynx;
+
+use crate::{disasm::{decode, Access}, registers::*};
+
+use super::lynx_ext::{CpuRegisters, LynxExt};
+
+/// Transfers kept for the panel.
+const RECENT_LEN: usize = 256;
+
+/// Serial frame format, from the last `SERCTL` write.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Parity {
+    Even,
+    Odd,
+    /// The 9th bit is the `PAREVEN` value.
+    Mark(bool),
+}
+
+impl Parity {
+    pub(crate) fn new(serctl: u8) -> Self {
+        let even = serctl & SERCTL_PAREVEN != 0;
+        match (serctl & SERCTL_PAREN != 0, even) {
+            (true, true) => Parity::Even,
+            (true, false) => Parity::Odd,
+            (false, bit) => Parity::Mark(bit),
+        }
+    }
+
+    /// 9th bit sent with `data`.
+    fn bit(&self, data: u8) -> bool {
+        let odd_ones = data.count_ones() % 2 == 1;
+        match self {
+            Parity::Even => odd_ones,
+            Parity::Odd => !odd_ones,
+            Parity::Mark(bit) => *bit,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Parity::Even => "even",
+            Parity::Odd => "odd",
+            Parity::Mark(true) => "mark",
+            Parity::Mark(false) => "space",
+        }
+    }
+}
+
+/// A byte sent or received, over the link or seen at `SERDAT`.
+#[derive(Clone)]
+pub(crate) struct SerialTransfer {
+    /// Crystal ticks since power-on.
+    pub tick: u64,
+    pub instance: usize,
+    pub transmit: bool,
+    pub data: u8,
+    pub parity: Parity,
+    /// 9th bit, sent or received.
+    pub bit9: bool,
+    /// `SERCTL` read value when receiving, error bits.
+    pub status: u8,
+}
+
+impl SerialTransfer {
+    pub(crate) fn direction(&self) -> &'static str {
+        match self.transmit {
+            true => "TX",
+            false => "RX",
+        }
+    }
+
+    pub(crate) fn ascii(&self) -> char {
+        match self.data {
+            0x20..=0x7e => self.data as char,
+            _ => '.',
+        }
+    }
+
+    /// Receive errors, `-` when none.
+    pub(crate) fn errors(&self) -> String {
+        let errors: Vec<&str> = [(SERCTL_PARERR, "parity"), (SERCTL_FRAMERR, "framing"), (SERCTL_OVERRUN, "overrun"), (SERCTL_RXBRK, "break")]
+            .iter()
+            .filter(|(bit, _)| !self.transmit && self.status & bit != 0)
+            .map(|(_, name)| *name)
+            .collect();
+        match errors.is_empty() {
+            true => "-".to_string(),
+            false => errors.join(","),
+        }
+    }
+
+    fn log_line(&self) -> String {
+        format!(
+            "{:>12} #{} {} {:02X} '{}' 9th:{} {:<5} {}",
+            self.tick, self.instance, self.direction(), self.data, self.ascii(), self.bit9 as u8, self.parity.name(), self.errors(),
+        )
+    }
+}
+
+/// Shared Comlynx log file, each instance sends its lines to the writer thread.
+#[derive(Clone)]
+pub(crate) struct ComlynxLog {
+    tx: kanal::Sender<String>,
+}
+
+impl ComlynxLog {
+    pub(crate) fn create(path: &Path) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let (tx, rx) = kanal::unbounded::<String>();
+        std::thread::Builder::new()
+            .name("Comlynx log".to_string())
+            .spawn(move || {
+                let mut writer = BufWriter::new(file);
+                let _ = writeln!(writer, "{:>12} inst dir data ascii 9th parity errors", "tick");
+                while let Ok(line) = rx.recv() {
+                    let _ = writeln!(writer, "{}", line);
+                    if rx.is_empty() {
+                        let _ = writer.flush();
+                    }
+                }
+            })
+            .map_err(|e| e.to_string())?;
+        Ok(Self { tx })
+    }
+}
+
+/// Comlynx traffic, the bytes carried by the link when there is one.
+///
+/// Unlinked, or linked through shared memory, the bytes are inferred from the instructions accessing
+/// `SERDAT`: stores only, from `STA`, `STX`, `STY` and `STZ`, and every read including the ones that
+/// don't take a byte off the receiver.
+pub(crate) struct ComlynxSniffer {
+    instance: usize,
+    log: Option<ComlynxLog>,
+    serctl: u8,
+    /// Bytes are taken at the link transfer points.
+    linked: bool,
+    recent: VecDeque<SerialTransfer>,
+}
+
+impl ComlynxSniffer {
+    pub(crate) fn new(instance: usize, log: Option<ComlynxLog>) -> Self {
+        Self {
+            instance,
+            log,
+            serctl: 0,
+            linked: false,
+            recent: VecDeque::with_capacity(RECENT_LEN),
+        }
+    }
+
+    /// Sniffing from power-on into the log file.
+    pub(crate) fn always_on(&self) -> bool {
+        self.log.is_some()
+    }
+
+    /// Last `SERCTL` write.
+    pub(crate) fn serctl(&self) -> u8 {
+        self.serctl
+    }
+
+    pub(crate) fn set_linked(&mut self) {
+        self.linked = true;
+    }
+
+    pub(crate) fn linked(&self) -> bool {
+        self.linked
+    }
+
+    pub(crate) fn recent(&self) -> Vec<SerialTransfer> {
+        self.recent.iter().cloned().collect()
+    }
+
+    /// Called before the instruction at PC executes, with its memory access.
+    pub(crate) fn instruction(&mut self, tick: u64, lynx: &Lynx, registers: &CpuRegisters, access: (u16, Access)) {
+        let stored = match decode(lynx.peek(registers.pc)).mnemonic {
+            "STA" => Some(registers.a),
+            "STX" => Some(registers.x),
+            "STY" => Some(registers.y),
+            "STZ" => Some(0),
+            _ => None,
+        };
+        let parity = Parity::new(self.serctl);
+        let transfer = match (access, stored) {
+            ((SERCTL, Access::Write), Some(value)) => {
+                self.serctl = value;
+                return;
+            }
+            _ if self.linked => return,
+            ((SERDAT, Access::Write), Some(data)) => SerialTransfer {
+                tick,
+                instance: self.instance,
+                transmit: true,
+                data,
+                parity,
+                bit9: parity.bit(data),
+                status: 0,
+            },
+            ((SERDAT, Access::Read), _) => {
+                let status = lynx.peek(SERCTL);
+                SerialTransfer {
+                    tick,
+                    instance: self.instance,
+                    transmit: false,
+                    data: lynx.peek(SERDAT),
+                    parity,
+                    bit9: status & SERCTL_PARBIT != 0,
+                    status,
+                }
+            }
+            _ => return,
+        };
+        self.push(transfer);
+    }
+
+    /// Byte taken from the core by the link, or given to it, `lynx` is the core after the transfer.
+    pub(crate) fn transfer(&mut self, tick: u64, lynx: &Lynx, transmit: bool, data: u8) {
+        let parity = Parity::new(self.serctl);
+        let status = match transmit {
+            true => 0,
+            false => lynx.peek(SERCTL),
+        };
+        let bit9 = match transmit {
+            true => parity.bit(data),
+            false => status & SERCTL_PARBIT != 0,
+        };
+        self.push(SerialTransfer {
+            tick,
+            instance: self.instance,
+            transmit,
+            data,
+            parity,
+            bit9,
+            status,
+        });
+    }
+
+    fn push(&mut self, transfer: SerialTransfer) {
+        if let Some(log) = &self.log {
+            let _ = log.tx.send(transfer.log_line());
+        }
+        if self.recent.len() == RECENT_LEN {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(transfer);
+    }
+}
@@ -0,0 +1,600 @@
+use std::{collections::VecDeque, time::Duration};
+use holani::{lynx::Lynx, mikey::video::{LYNX_SCREEN_HEIGHT, LYNX_SCREEN_WIDTH}};
+
+use crate::{disasm::{decode, Access}, registers::{AUDIO, AUDIO_CHANNELS, AUDIO_LEN, AUD_OUTVAL, BLUERED0, DISPADR, GREEN0, MIKEY_BASE, PALETTE_LEN, REGISTERS_LEN, SERCTL, SPRGO, SUZY_BASE}};
+
+use super::{breakpoint::{BreakKind, Breakpoint}, cheats::{Cheat, MemorySearch, SearchFilter}, comlynx_sniffer::{ComlynxSniffer, SerialTransfer}, lynx_ext::{CpuRegisters, LynxExt}, profiler::{ProfileReport, Profiler}, scb::{walk_chain, Scb}, trace::Tracer, SAMPLE_TICKS};
+
+/// Wait for a command while paused.
+pub(crate) const DEBUGGER_POLL: Duration = Duration::from_millis(2);
+const JSR: u8 = 0x20;
+const CODE_BEFORE_PC: u16 = 16;
+const CODE_LEN: usize = 64;
+const MEMORY_LEN: usize = 128;
+/// Audio samples kept for the oscilloscope, about two frames.
+const SCOPE_LEN: usize = 512;
+/// 4 bits per pixel.
+pub(crate) const FRAMEBUFFER_LEN: usize = (LYNX_SCREEN_WIDTH * LYNX_SCREEN_HEIGHT / 2) as usize;
+
+/// Inspector panel shown, its snapshot is reported every frame.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Inspector {
+    Hardware,
+    Sprites,
+    Video,
+    Audio,
+    Cheats,
+    Profiler,
+    Comlynx,
+}
+
+pub(crate) enum DebugCommand {
+    /// The debugger view is shown, a state is reported every frame.
+    Attach(bool),
+    Inspect(Option<Inspector>),
+    Pause,
+    Run,
+    Step,
+    StepOver,
+    AddBreakpoint(Breakpoint),
+    /// Removes by id.
+    RemoveBreakpoint(u32),
+    /// Removes every breakpoint of that kind and address.
+    RemoveBreakpointAt(BreakKind),
+    MemoryView(u16),
+    /// Framebuffer shown by the video inspector, `None` follows `DISPADR`.
+    FramebufferView(Option<u16>),
+    ReadRegisters(kanal::Sender<CpuRegisters>),
+    WriteRegisters(CpuRegisters),
+    ReadMemory(u16, usize, kanal::Sender<Vec<u8>>),
+    WriteMemory(u16, Vec<u8>),
+    /// Notified once, on the next pause.
+    NotifyStop(kanal::Sender<()>),
+    ToggleTrace,
+    /// Starts a new memory search with `None`, narrows it down otherwise.
+    Search(Option<SearchFilter>),
+    AddCheat(Cheat),
+    RemoveCheat(u32),
+    ToggleCheat(u32),
+    ResetProfile,
+}
+
+#[derive(Clone)]
+pub(crate) struct DebugState {
+    pub paused: bool,
+    pub frame: u64,
+    pub registers: CpuRegisters,
+    /// Memory from `code_address`, for disassembly around PC.
+    pub code_address: u16,
+    pub code: Vec<u8>,
+    pub memory_address: u16,
+    pub memory: Vec<u8>,
+    pub breakpoints: Vec<Breakpoint>,
+    /// Set when stopped by a breakpoint.
+    pub break_reason: Option<String>,
+}
+
+/// Raw Suzy and Mikey register pages.
+pub(crate) struct HardwareState {
+    pub suzy: Vec<u8>,
+    pub mikey: Vec<u8>,
+}
+
+impl HardwareState {
+    fn new(lynx: &Lynx) -> Self {
+        Self {
+            suzy: lynx.peek_range(SUZY_BASE, REGISTERS_LEN),
+            mikey: lynx.peek_range(MIKEY_BASE, REGISTERS_LEN),
+        }
+    }
+
+    pub(crate) fn byte(&self, addr: u16) -> u8 {
+        match addr & 0xff00 {
+            SUZY_BASE => self.suzy[(addr - SUZY_BASE) as usize],
+            MIKEY_BASE => self.mikey[(addr - MIKEY_BASE) as usize],
+            _ => 0,
+        }
+    }
+
+    /// Little endian register pair.
+    pub(crate) fn word(&self, addr: u16) -> u16 {
+        u16::from_le_bytes([self.byte(addr), self.byte(addr + 1)])
+    }
+}
+
+/// Palette registers and a framebuffer read from RAM.
+pub(crate) struct VideoState {
+    pub green: Vec<u8>,
+    pub bluered: Vec<u8>,
+    pub framebuffer_address: u16,
+    /// Follows `DISPADR`.
+    pub displayed: bool,
+    pub framebuffer: Vec<u8>,
+}
+
+impl VideoState {
+    fn new(lynx: &Lynx, address: Option<u16>) -> Self {
+        let framebuffer_address = address.unwrap_or_else(|| lynx.peek_word(DISPADR));
+        Self {
+            green: lynx.peek_range(GREEN0, PALETTE_LEN as usize),
+            bluered: lynx.peek_range(BLUERED0, PALETTE_LEN as usize),
+            framebuffer_address,
+            displayed: address.is_none(),
+            framebuffer: lynx.peek_range(framebuffer_address, FRAMEBUFFER_LEN),
+        }
+    }
+}
+
+/// Last audio samples, oldest first, and the Mikey registers.
+pub(crate) struct AudioState {
+    /// Channels output values.
+    pub channels: Vec<[i8; AUDIO_CHANNELS as usize]>,
+    /// Stereo mix, as sent to the audio output.
+    pub output: Vec<(i16, i16)>,
+    pub hardware: HardwareState,
+}
+
+pub(crate) struct CheatsState {
+    /// `None` until a search is started.
+    pub candidates: Option<usize>,
+    /// First candidates as `(address, previous step value, current value)`.
+    pub results: Vec<(u16, u8, u8)>,
+    pub cheats: Vec<Cheat>,
+}
+
+pub(crate) struct ComlynxState {
+    /// Last transfers, oldest first.
+    pub transfers: Vec<SerialTransfer>,
+    /// Last `SERCTL` write.
+    pub control: u8,
+    /// `SERCTL` read.
+    pub status: u8,
+    /// Bytes taken at the link transfer points, inferred from `SERDAT` accesses otherwise.
+    pub linked: bool,
+}
+
+pub(crate) enum DebugEvent {
+    State(Box<DebugState>),
+    Hardware(Box<HardwareState>),
+    /// Sprites drawn during the last frame that drew any, in chain order.
+    Sprites(Vec<Scb>),
+    Video(Box<VideoState>),
+    Audio(Box<AudioState>),
+    Cheats(Box<CheatsState>),
+    Profile(Box<ProfileReport>),
+    Comlynx(Box<ComlynxState>),
+    /// Why the core was halted, for the status line.
+    Halted(String),
+}
+
+#[derive(PartialEq, Eq)]
+enum Mode {
+    Run,
+    /// Pause at the next instruction.
+    Step,
+    /// Run until the temporary breakpoint.
+    RunTo(u16),
+}
+
+/// Core side of the debugger, owned by the runner thread and wrapping `lynx.tick()`.
+pub(crate) struct Debugger {
+    command_rx: kanal::Receiver<DebugCommand>,
+    event_tx: kanal::Sender<DebugEvent>,
+    attached: bool,
+    inspector: Option<Inspector>,
+    paused: bool,
+    mode: Mode,
+    fetching: bool,
+    breakpoints: Vec<Breakpoint>,
+    next_breakpoint_id: u32,
+    break_reason: Option<String>,
+    memory_address: u16,
+    framebuffer_address: Option<u16>,
+    stop_listeners: Vec<kanal::Sender<()>>,
+    report: bool,
+    inspect: bool,
+    /// SCB chains started in the current frame.
+    drawing: Vec<Scb>,
+    sprites: Vec<Scb>,
+    sample_tick: u32,
+    samples: VecDeque<([i8; AUDIO_CHANNELS as usize], (i16, i16))>,
+    tracer: Option<Tracer>,
+    ticks: u64,
+    search: Option<MemorySearch>,
+    cheats: Vec<Cheat>,
+    next_cheat_id: u32,
+    profiler: Option<Profiler>,
+    sniffer: Option<ComlynxSniffer>,
+}
+
+impl Debugger {
+    pub(crate) fn new(command_rx: kanal::Receiver<DebugCommand>, event_tx: kanal::Sender<DebugEvent>) -> Self {
+        Self {
+            command_rx,
+            event_tx,
+            attached: false,
+            inspector: None,
+            paused: false,
+            mode: Mode::Run,
+            fetching: false,
+            breakpoints: vec![],
+            next_breakpoint_id: 1,
+            break_reason: None,
+            memory_address: 0,
+            framebuffer_address: None,
+            stop_listeners: vec![],
+            report: false,
+            inspect: false,
+            drawing: vec![],
+            sprites: vec![],
+            sample_tick: 0,
+            samples: VecDeque::with_capacity(SCOPE_LEN),
+            tracer: None,
+            ticks: 0,
+            search: None,
+            cheats: vec![],
+            next_cheat_id: 1,
+            profiler: None,
+            sniffer: None,
+        }
+    }
+
+    pub(crate) fn set_profiler(&mut self, profiler: Profiler) {
+        self.profiler = Some(profiler);
+    }
+
+    /// Profiling while the panel is shown, or from power-on.
+    fn profiler(&mut self) -> Option<&mut Profiler> {
+        let inspected = self.inspector == Some(Inspector::Profiler);
+        self.profiler.as_mut().filter(|profiler| inspected || profiler.always_on())
+    }
+
+    pub(crate) fn set_sniffer(&mut self, sniffer: ComlynxSniffer) {
+        self.sniffer = Some(sniffer);
+    }
+
+    /// Sniffing while the panel is shown, or from power-on into the log.
+    fn sniffer(&mut self) -> Option<&mut ComlynxSniffer> {
+        let inspected = self.inspector == Some(Inspector::Comlynx);
+        self.sniffer.as_mut().filter(|sniffer| inspected || sniffer.always_on())
+    }
+
+    /// Sniffs the link transfer points instead of the `SERDAT` accesses.
+    pub(crate) fn set_comlynx_linked(&mut self) {
+        if let Some(sniffer) = self.sniffer.as_mut() {
+            sniffer.set_linked();
+        }
+    }
+
+    /// Byte carried by the Comlynx link, `lynx` is the core after the transfer.
+    pub(crate) fn comlynx_transfer(&mut self, lynx: &Lynx, transmit: bool, data: u8) {
+        let ticks = self.ticks;
+        if let Some(sniffer) = self.sniffer() {
+            sniffer.transfer(ticks, lynx, transmit, data);
+        }
+    }
+
+    pub(crate) fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    pub(crate) fn add_cheat(&mut self, mut cheat: Cheat) {
+        cheat.id = self.next_cheat_id;
+        self.next_cheat_id += 1;
+        self.cheats.push(cheat);
+    }
+
+    pub(crate) fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Processes the pending commands and reports the state if anything changed.
+    pub(crate) fn update(&mut self, lynx: &mut Lynx, frame: u64) {
+        while let Ok(Some(command)) = self.command_rx.try_recv() {
+            self.command(lynx, command);
+            self.report = true;
+            self.inspect = true;
+        }
+
+        if self.report {
+            self.report = false;
+            if self.attached || self.paused {
+                self.send_state(lynx, frame);
+            }
+        }
+
+        if self.inspect {
+            self.inspect = false;
+            if let Some(inspector) = self.inspector {
+                self.send_inspector(lynx, inspector);
+            }
+        }
+    }
+
+    /// Pauses the core on a failure the game can't go on from, `message` is shown in the status line.
+    pub(crate) fn halt(&mut self, message: String) {
+        self.paused = true;
+        self.mode = Mode::Run;
+        self.report = true;
+        self.notify_stop();
+        let _ = self.event_tx.send(DebugEvent::Halted(message));
+    }
+
+    /// While paused, blocks up to `DEBUGGER_POLL` for a command instead of spinning, the state is reported on
+    /// next `update()`.
+    pub(crate) fn wait(&mut self, lynx: &mut Lynx) {
+        if let Ok(command) = self.command_rx.recv_timeout(DEBUGGER_POLL) {
+            self.command(lynx, command);
+            self.report = true;
+            self.inspect = true;
+        }
+    }
+
+    /// A frame was displayed, the cheats are applied, the state and inspector snapshot are reported on next `update()`.
+    pub(crate) fn frame_done(&mut self, lynx: &mut Lynx) {
+        for cheat in self.cheats.iter().filter(|cheat| cheat.enabled) {
+            lynx.poke(cheat.address, cheat.value);
+        }
+        self.report |= self.attached;
+        self.inspect |= self.inspector.is_some();
+        if !self.drawing.is_empty() {
+            self.sprites = std::mem::take(&mut self.drawing);
+        }
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.flush();
+        }
+        if let Some(profiler) = self.profiler() {
+            profiler.frame_done();
+        }
+    }
+
+    /// Ticks the core unless the debugger is paused or a break condition is met, returns `false` in that case.
+    pub(crate) fn tick(&mut self, lynx: &mut Lynx) -> bool {
+        if self.paused {
+            return false;
+        }
+
+        let fetching = lynx.instruction_start();
+        let new_instruction = fetching && !self.fetching;
+        self.fetching = fetching;
+
+        if new_instruction && self.inspector == Some(Inspector::Sprites) {
+            self.capture_sprites(lynx);
+        }
+
+        if new_instruction && self.should_break(lynx) {
+            if let (Some(tracer), Some(reason)) = (self.tracer.as_mut(), &self.break_reason) {
+                tracer.dump(reason);
+            }
+            self.paused = true;
+            self.mode = Mode::Run;
+            self.report = true;
+            self.notify_stop();
+            return false;
+        }
+
+        if new_instruction {
+            if let Some(tracer) = self.tracer.as_mut() {
+                let registers = lynx.registers();
+                tracer.instruction(self.ticks, &registers, &lynx.peek_range(registers.pc, 3));
+            }
+            if let Some(profiler) = self.profiler() {
+                profiler.instruction(lynx.registers().pc);
+            }
+            let ticks = self.ticks;
+            if let Some(sniffer) = self.sniffer() {
+                let registers = lynx.registers();
+                if let Some(access) = memory_access(lynx, &registers) {
+                    sniffer.instruction(ticks, lynx, &registers, access);
+                }
+            }
+        }
+
+        lynx.tick();
+        self.ticks += 1;
+        if let Some(profiler) = self.profiler() {
+            profiler.tick();
+        }
+
+        if self.inspector == Some(Inspector::Audio) {
+            self.capture_sample(lynx);
+        }
+        true
+    }
+
+    fn should_break(&mut self, lynx: &Lynx) -> bool {
+        let registers = lynx.registers();
+        match self.mode {
+            Mode::Step => return true,
+            Mode::RunTo(addr) if addr == registers.pc => return true,
+            _ => (),
+        }
+
+        if self.breakpoints.is_empty() {
+            return false;
+        }
+
+        let access = memory_access(lynx, &registers);
+        let mut stop = false;
+        for bp in self.breakpoints.iter_mut() {
+            if bp.hit(&registers, access, |addr| lynx.peek(addr)) && !stop {
+                stop = true;
+                self.break_reason = Some(format!("Breakpoint {} at ${:04X}.", bp, registers.pc));
+            }
+        }
+        stop
+    }
+
+    /// Suzy walks the SCB chain as soon as `SPRGO` is written, the chain is read before that write.
+    fn capture_sprites(&mut self, lynx: &Lynx) {
+        let registers = lynx.registers();
+        if let Some((SPRGO, Access::Write | Access::ReadWrite)) = memory_access(lynx, &registers) {
+            self.drawing.extend(walk_chain(lynx));
+        }
+    }
+
+    /// Samples the channels outputs and the mix at the audio output rate.
+    fn capture_sample(&mut self, lynx: &mut Lynx) {
+        self.sample_tick += 1;
+        if self.sample_tick < SAMPLE_TICKS {
+            return;
+        }
+        self.sample_tick = 0;
+
+        let channels = std::array::from_fn(|n| lynx.peek(AUDIO + n as u16 * AUDIO_LEN + AUD_OUTVAL) as i8);
+        if self.samples.len() == SCOPE_LEN {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((channels, lynx.audio_sample()));
+    }
+
+    fn command(&mut self, lynx: &mut Lynx, command: DebugCommand) {
+        match command {
+            DebugCommand::Attach(attached) => self.attached = attached,
+            DebugCommand::Inspect(inspector) => {
+                self.inspector = inspector;
+                self.drawing.clear();
+                self.sprites.clear();
+                self.samples.clear();
+            }
+            DebugCommand::Pause => {
+                match self.paused {
+                    true => self.notify_stop(),
+                    false => self.mode = Mode::Step,
+                }
+            }
+            DebugCommand::Run => {
+                self.mode = Mode::Run;
+                self.resume();
+            }
+            DebugCommand::Step => {
+                self.mode = Mode::Step;
+                self.resume();
+            }
+            DebugCommand::StepOver => {
+                let pc = lynx.registers().pc;
+                self.mode = match lynx.peek(pc) {
+                    JSR => Mode::RunTo(pc.wrapping_add(3)),
+                    _ => Mode::Step,
+                };
+                self.resume();
+            }
+            DebugCommand::AddBreakpoint(mut bp) => {
+                bp.id = self.next_breakpoint_id;
+                self.next_breakpoint_id += 1;
+                self.breakpoints.push(bp);
+            }
+            DebugCommand::RemoveBreakpoint(id) => {
+                self.breakpoints.retain(|bp| bp.id != id);
+            }
+            DebugCommand::RemoveBreakpointAt(kind) => {
+                self.breakpoints.retain(|bp| bp.kind != kind);
+            }
+            DebugCommand::MemoryView(addr) => self.memory_address = addr,
+            DebugCommand::FramebufferView(addr) => self.framebuffer_address = addr,
+            DebugCommand::ReadRegisters(reply_tx) => {
+                let _ = reply_tx.send(lynx.registers());
+            }
+            DebugCommand::WriteRegisters(registers) => lynx.set_registers(&registers),
+            DebugCommand::ReadMemory(addr, len, reply_tx) => {
+                let _ = reply_tx.send(lynx.peek_range(addr, len));
+            }
+            DebugCommand::WriteMemory(addr, data) => {
+                for (i, b) in data.iter().enumerate() {
+                    lynx.poke(addr.wrapping_add(i as u16), *b);
+                }
+            }
+            DebugCommand::NotifyStop(listener_tx) => self.stop_listeners.push(listener_tx),
+            DebugCommand::Search(None) => self.search = Some(MemorySearch::new(lynx)),
+            DebugCommand::Search(Some(filter)) => {
+                if let Some(search) = self.search.as_mut() {
+                    search.filter(lynx, filter);
+                }
+            }
+            DebugCommand::AddCheat(cheat) => self.add_cheat(cheat),
+            DebugCommand::RemoveCheat(id) => self.cheats.retain(|cheat| cheat.id != id),
+            DebugCommand::ToggleCheat(id) => {
+                if let Some(cheat) = self.cheats.iter_mut().find(|cheat| cheat.id == id) {
+                    cheat.enabled = !cheat.enabled;
+                }
+            }
+            DebugCommand::ResetProfile => {
+                if let Some(profiler) = self.profiler.as_mut() {
+                    profiler.reset();
+                }
+            }
+            DebugCommand::ToggleTrace => {
+                if let Some(tracer) = self.tracer.as_mut() {
+                    tracer.toggle();
+                }
+            }
+        }
+    }
+
+    fn notify_stop(&mut self) {
+        for listener_tx in self.stop_listeners.drain(..) {
+            let _ = listener_tx.send(());
+        }
+    }
+
+    fn resume(&mut self) {
+        self.paused = false;
+        self.break_reason = None;
+    }
+
+    fn send_inspector(&self, lynx: &Lynx, inspector: Inspector) {
+        let event = match inspector {
+            Inspector::Hardware => DebugEvent::Hardware(Box::new(HardwareState::new(lynx))),
+            Inspector::Sprites => DebugEvent::Sprites(self.sprites.clone()),
+            Inspector::Video => DebugEvent::Video(Box::new(VideoState::new(lynx, self.framebuffer_address))),
+            Inspector::Audio => DebugEvent::Audio(Box::new(AudioState {
+                channels: self.samples.iter().map(|(channels, _)| *channels).collect(),
+                output: self.samples.iter().map(|(_, output)| *output).collect(),
+                hardware: HardwareState::new(lynx),
+            })),
+            Inspector::Cheats => DebugEvent::Cheats(Box::new(CheatsState {
+                candidates: self.search.as_ref().map(|search| search.candidates()),
+                results: self.search.as_ref().map(|search| search.results(lynx)).unwrap_or_default(),
+                cheats: self.cheats.clone(),
+            })),
+            Inspector::Profiler => match &self.profiler {
+                Some(profiler) => DebugEvent::Profile(Box::new(profiler.panel_report())),
+                None => return,
+            },
+            Inspector::Comlynx => match &self.sniffer {
+                Some(sniffer) => DebugEvent::Comlynx(Box::new(ComlynxState {
+                    transfers: sniffer.recent(),
+                    control: sniffer.serctl(),
+                    status: lynx.peek(SERCTL),
+                    linked: sniffer.linked(),
+                })),
+                None => return,
+            },
+        };
+        let _ = self.event_tx.try_send(event);
+    }
+
+    fn send_state(&self, lynx: &Lynx, frame: u64) {
+        let registers = lynx.registers();
+        let code_address = registers.pc.wrapping_sub(CODE_BEFORE_PC);
+        let state = DebugState {
+            paused: self.paused,
+            frame,
+            registers,
+            code_address,
+            code: lynx.peek_range(code_address, CODE_LEN),
+            memory_address: self.memory_address,
+            memory: lynx.peek_range(self.memory_address, MEMORY_LEN),
+            breakpoints: self.breakpoints.clone(),
+            break_reason: self.break_reason.clone(),
+        };
+        let _ = self.event_tx.try_send(DebugEvent::State(Box::new(state)));
+    }
+}
+
+/// Address and kind of the memory access of the instruction at PC.
+fn memory_access(lynx: &Lynx, registers: &CpuRegisters) -> Option<(u16, Access)> {
+    let bytes = lynx.peek_range(registers.pc, 3);
+    let ins = decode(bytes[0]);
+    ins.effective_address(&bytes, registers.x, registers.y, |addr| lynx.peek(addr)).zip(ins.access())
+}
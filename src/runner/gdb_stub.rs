@@ -0,0 +1,434 @@
+use std::{fmt::Write as _, io::{self, ErrorKind, Read, Write}, net::{Ipv4Addr, TcpListener, TcpStream}, thread::JoinHandle, time::Duration};
+use log::{info, trace, warn};
+
+use super::{breakpoint::{BreakKind, Breakpoint}, debugger::DebugCommand, lynx_ext::CpuRegisters};
+
+const INTERRUPT: u8 = 0x03;
+const STOP_POLL: Duration = Duration::from_millis(10);
+const SIGINT: &str = "S02";
+const SIGTRAP: &str = "S05";
+/// Largest packet accepted, advertised to the debugger. Memory is read and written as 2 hex digits per byte.
+const PACKET_SIZE: usize = 0x1000;
+const MAX_MEMORY_LEN: usize = PACKET_SIZE / 2;
+
+/// GDB remote serial protocol stub, listening on localhost.
+///
+/// Registers, in `g`/`G` order and `p`/`P` numbers: 0 `a`, 1 `x`, 2 `y`, 3 `p`, 4 `s` as 1 byte,
+/// 5 `pc` as 2 bytes little endian.
+/// Breakpoints: `Z0`/`Z1` execution, `Z2` write, `Z3` read, `Z4` access watchpoints.
+pub(crate) struct GdbStub {
+    port: u16,
+    debug_tx: kanal::Sender<DebugCommand>,
+}
+
+impl GdbStub {
+    pub(crate) fn new(port: u16, debug_tx: kanal::Sender<DebugCommand>) -> Self {
+        Self {
+            port,
+            debug_tx,
+        }
+    }
+
+    pub(crate) fn spawn(self) -> io::Result<JoinHandle<()>> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, self.port))?;
+        info!("GDB stub listening on {}.", listener.local_addr()?);
+
+        std::thread::Builder::new()
+            .name("GDB".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    let Ok(stream) = stream else {
+                        continue;
+                    };
+                    info!("GDB connected from {:?}.", stream.peer_addr());
+                    let mut connection = Connection { stream, debug_tx: self.debug_tx.clone() };
+                    if let Err(err) = connection.serve() {
+                        warn!("GDB connection: {}", err);
+                    }
+                    connection.detach();
+                    info!("GDB disconnected.");
+                }
+            })
+    }
+}
+
+enum Packet {
+    Data(String),
+    Interrupt,
+}
+
+struct Connection {
+    stream: TcpStream,
+    debug_tx: kanal::Sender<DebugCommand>,
+}
+
+impl Connection {
+    fn serve(&mut self) -> io::Result<()> {
+        self.stop(DebugCommand::Pause)?;
+
+        loop {
+            let reply = match self.read_packet()? {
+                None => return Ok(()),
+                Some(Packet::Interrupt) => self.stop(DebugCommand::Pause)?.then_some(SIGINT.to_string()),
+                Some(Packet::Data(data)) => {
+                    trace!("GDB <- {}", data);
+                    match data.as_bytes().first() {
+                        Some(b'k') | Some(b'D') => {
+                            self.send_packet("OK")?;
+                            return Ok(());
+                        }
+                        _ => self.handle(&data)?,
+                    }
+                }
+            };
+            if let Some(reply) = reply {
+                trace!("GDB -> {}", reply);
+                self.send_packet(&reply)?;
+            }
+        }
+    }
+
+    fn detach(&self) {
+        let _ = self.debug_tx.send(DebugCommand::Run);
+    }
+
+    fn command(&self, command: DebugCommand) -> io::Result<()> {
+        self.debug_tx.send(command).map_err(|_| core_stopped())
+    }
+
+    fn request<T>(&self, command: impl FnOnce(kanal::Sender<T>) -> DebugCommand) -> io::Result<T> {
+        let (reply_tx, reply_rx) = kanal::bounded::<T>(1);
+        self.command(command(reply_tx))?;
+        reply_rx.recv().map_err(|_| core_stopped())
+    }
+
+    /// Sends `command` and waits for the core to pause, a GDB interrupt pauses it.
+    /// Returns `false` if the connection was closed meanwhile.
+    fn stop(&mut self, command: DebugCommand) -> io::Result<bool> {
+        let (stop_tx, stop_rx) = kanal::bounded::<()>(1);
+        self.command(DebugCommand::NotifyStop(stop_tx))?;
+        self.command(command)?;
+
+        self.stream.set_read_timeout(Some(STOP_POLL))?;
+        let mut byte = [0u8];
+        let stopped = loop {
+            match stop_rx.try_recv() {
+                Ok(Some(())) => break true,
+                Ok(None) => (),
+                Err(_) => return Err(core_stopped()),
+            }
+            match self.stream.read(&mut byte) {
+                Ok(0) => break false,
+                Ok(_) if byte[0] == INTERRUPT => self.command(DebugCommand::Pause)?,
+                Ok(_) => (),
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => (),
+                Err(err) => return Err(err),
+            }
+        };
+        self.stream.set_read_timeout(None)?;
+        Ok(stopped)
+    }
+
+    fn handle(&mut self, data: &str) -> io::Result<Option<String>> {
+        let Some(cmd) = data.get(..1) else {
+            return Ok(Some(String::new()));
+        };
+        let args = &data[1..];
+        let reply = match cmd {
+            "?" => SIGTRAP.to_string(),
+            "g" => encode_registers(&self.request(DebugCommand::ReadRegisters)?),
+            "G" => match decode_hex(args).and_then(|bytes| decode_registers(&bytes)) {
+                Some(registers) => {
+                    self.command(DebugCommand::WriteRegisters(registers))?;
+                    "OK".to_string()
+                }
+                None => "E01".to_string(),
+            },
+            "p" => {
+                let registers = self.request(DebugCommand::ReadRegisters)?;
+                let bytes = encode_registers(&registers);
+                match usize::from_str_radix(args, 16) {
+                    Ok(n @ 0..=4) => bytes[n * 2..n * 2 + 2].to_string(),
+                    Ok(5) => bytes[10..14].to_string(),
+                    _ => "E01".to_string(),
+                }
+            }
+            "P" => {
+                let mut registers = self.request(DebugCommand::ReadRegisters)?;
+                let parsed = args.split_once('=').and_then(|(n, v)| Some((usize::from_str_radix(n, 16).ok()?, decode_hex(v)?)));
+                match parsed {
+                    Some((n, value)) if set_register(&mut registers, n, &value) => {
+                        self.command(DebugCommand::WriteRegisters(registers))?;
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            "m" => match parse_read(args) {
+                Some((addr, len)) => {
+                    let data = self.request(|reply_tx| DebugCommand::ReadMemory(addr, len, reply_tx))?;
+                    encode_hex(&data)
+                }
+                None => "E01".to_string(),
+            },
+            "M" => match parse_write(args) {
+                Some((addr, data)) => {
+                    self.command(DebugCommand::WriteMemory(addr, data))?;
+                    "OK".to_string()
+                }
+                None => "E01".to_string(),
+            },
+            "s" => match self.stop(DebugCommand::Step)? {
+                true => SIGTRAP.to_string(),
+                false => return Ok(None),
+            },
+            "c" => match self.stop(DebugCommand::Run)? {
+                true => SIGTRAP.to_string(),
+                false => return Ok(None),
+            },
+            "Z" | "z" => match parse_breakpoint(args) {
+                Some(kind) if cmd == "Z" => {
+                    let mut bp = Breakpoint::exec(0);
+                    bp.kind = kind;
+                    self.command(DebugCommand::AddBreakpoint(bp))?;
+                    "OK".to_string()
+                }
+                Some(kind) => {
+                    self.command(DebugCommand::RemoveBreakpointAt(kind))?;
+                    "OK".to_string()
+                }
+                None => String::new(),
+            },
+            "H" => "OK".to_string(),
+            "q" if args.starts_with("Supported") => format!("PacketSize={:x}", PACKET_SIZE),
+            "q" if args.starts_with("Attached") => "1".to_string(),
+            "q" if args == "C" => "QC1".to_string(),
+            "q" if args == "fThreadInfo" => "m1".to_string(),
+            "q" if args == "sThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        };
+        Ok(Some(reply))
+    }
+
+    /// Returns `None` on disconnection. Packets with a bad checksum are refused and skipped.
+    fn read_packet(&mut self) -> io::Result<Option<Packet>> {
+        let mut byte = [0u8];
+        loop {
+            loop {
+                if self.stream.read(&mut byte)? == 0 {
+                    return Ok(None);
+                }
+                match byte[0] {
+                    INTERRUPT => return Ok(Some(Packet::Interrupt)),
+                    b'$' => break,
+                    _ => (),
+                }
+            }
+
+            let mut data = vec![];
+            loop {
+                if self.stream.read(&mut byte)? == 0 {
+                    return Ok(None);
+                }
+                if byte[0] == b'#' {
+                    break;
+                }
+                if data.len() == PACKET_SIZE {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "packet too long"));
+                }
+                data.push(byte[0]);
+            }
+
+            let mut checksum = [0u8; 2];
+            self.stream.read_exact(&mut checksum)?;
+            let expected = std::str::from_utf8(&checksum).ok().and_then(|c| u8::from_str_radix(c, 16).ok());
+            if expected != Some(checksum_of(&data)) {
+                self.stream.write_all(b"-")?;
+                continue;
+            }
+
+            self.stream.write_all(b"+")?;
+            return Ok(Some(Packet::Data(String::from_utf8_lossy(&data).into_owned())));
+        }
+    }
+
+    fn send_packet(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.stream.write_all(packet.as_bytes())?;
+        self.stream.flush()
+    }
+}
+
+fn core_stopped() -> io::Error {
+    io::Error::new(ErrorKind::BrokenPipe, "Core runner stopped.")
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+fn encode_hex(data: &[u8]) -> String {
+    data.iter().fold(String::new(), |mut s, b| {
+        let _ = write!(s, "{:02x}", b);
+        s
+    })
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok()).collect()
+}
+
+fn encode_registers(r: &CpuRegisters) -> String {
+    let [pc_lo, pc_hi] = r.pc.to_le_bytes();
+    encode_hex(&[r.a, r.x, r.y, r.p, r.s, pc_lo, pc_hi])
+}
+
+fn decode_registers(bytes: &[u8]) -> Option<CpuRegisters> {
+    let [a, x, y, p, s, pc_lo, pc_hi] = bytes[..] else {
+        return None;
+    };
+    Some(CpuRegisters { pc: u16::from_le_bytes([pc_lo, pc_hi]), a, x, y, s, p })
+}
+
+fn set_register(r: &mut CpuRegisters, n: usize, value: &[u8]) -> bool {
+    match (n, value) {
+        (0, [v]) => r.a = *v,
+        (1, [v]) => r.x = *v,
+        (2, [v]) => r.y = *v,
+        (3, [v]) => r.p = *v,
+        (4, [v]) => r.s = *v,
+        (5, [lo, hi]) => r.pc = u16::from_le_bytes([*lo, *hi]),
+        _ => return false,
+    }
+    true
+}
+
+/// `<addr>,<len>`
+fn parse_addr_len(s: &str) -> Option<(u16, usize)> {
+    let (addr, len) = s.split_once(',')?;
+    Some((u16::from_str_radix(addr, 16).ok()?, usize::from_str_radix(len, 16).ok()?))
+}
+
+/// `m` arguments, `<addr>,<len>` up to `MAX_MEMORY_LEN` bytes.
+fn parse_read(s: &str) -> Option<(u16, usize)> {
+    parse_addr_len(s).filter(|(_, len)| *len <= MAX_MEMORY_LEN)
+}
+
+/// `M` arguments, `<addr>,<len>:<data>` up to `MAX_MEMORY_LEN` bytes.
+fn parse_write(s: &str) -> Option<(u16, Vec<u8>)> {
+    let (range, data) = s.split_once(':')?;
+    let (addr, len) = parse_read(range)?;
+    let data = decode_hex(data).filter(|data| data.len() == len)?;
+    Some((addr, data))
+}
+
+/// `<type>,<addr>,<kind>`
+fn parse_breakpoint(s: &str) -> Option<BreakKind> {
+    let mut fields = s.split(',');
+    let kind = fields.next()?;
+    let addr = u16::from_str_radix(fields.next()?, 16).ok()?;
+    let len = fields.next().and_then(|l| u16::from_str_radix(l, 16).ok()).unwrap_or(1).max(1);
+    let end = addr.saturating_add(len - 1);
+    match kind {
+        "0" | "1" => Some(BreakKind::Exec(addr)),
+        "2" => Some(BreakKind::Write(addr, end)),
+        "3" => Some(BreakKind::Read(addr, end)),
+        "4" => Some(BreakKind::Access(addr, end)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_hex() {
+        assert_eq!(decode_hex("00a9FF"), Some(vec![0x00, 0xa9, 0xff]));
+        assert_eq!(decode_hex(""), Some(vec![]));
+        assert_eq!(decode_hex("abc"), None);
+        assert_eq!(decode_hex("zz"), None);
+        assert_eq!(decode_hex("é1"), None);
+        assert_eq!(encode_hex(&[0x00, 0xa9, 0xff]), "00a9ff");
+    }
+
+    #[test]
+    fn parses_memory_packets() {
+        assert_eq!(parse_read("fd00,10"), Some((0xfd00, 0x10)));
+        assert_eq!(parse_read(&format!("0,{:x}", MAX_MEMORY_LEN)), Some((0, MAX_MEMORY_LEN)));
+        assert_eq!(parse_read(&format!("0,{:x}", MAX_MEMORY_LEN + 1)), None);
+        assert_eq!(parse_read("0,ffffffffffffffffff"), None);
+        assert_eq!(parse_read("10000,1"), None);
+        assert_eq!(parse_read("fd00"), None);
+        assert_eq!(parse_read("x,1"), None);
+
+        assert_eq!(parse_write("200,2:a9ff"), Some((0x200, vec![0xa9, 0xff])));
+        assert_eq!(parse_write("200,3:a9ff"), None);
+        assert_eq!(parse_write("200,2"), None);
+        assert_eq!(parse_write("200,2:a9f"), None);
+        let oversized = "00".repeat(MAX_MEMORY_LEN + 1);
+        assert_eq!(parse_write(&format!("0,{:x}:{}", MAX_MEMORY_LEN + 1, oversized)), None);
+    }
+
+    #[test]
+    fn parses_breakpoint_packets() {
+        assert!(parse_breakpoint("0,400,1") == Some(BreakKind::Exec(0x400)));
+        assert!(parse_breakpoint("1,400") == Some(BreakKind::Exec(0x400)));
+        assert!(parse_breakpoint("2,fd80,2") == Some(BreakKind::Write(0xfd80, 0xfd81)));
+        assert!(parse_breakpoint("3,fd80,0") == Some(BreakKind::Read(0xfd80, 0xfd80)));
+        assert!(parse_breakpoint("4,fffe,10") == Some(BreakKind::Access(0xfffe, 0xffff)));
+        assert!(parse_breakpoint("5,400,1").is_none());
+        assert!(parse_breakpoint("0,10000,1").is_none());
+        assert!(parse_breakpoint("0").is_none());
+        assert!(parse_breakpoint("").is_none());
+    }
+
+    #[test]
+    fn orders_registers() {
+        let registers = CpuRegisters { pc: 0x1234, a: 1, x: 2, y: 3, s: 0xfd, p: 0x24 };
+        let encoded = encode_registers(&registers);
+        assert_eq!(encoded, "01020324fd3412");
+        assert!(decode_registers(&decode_hex(&encoded).unwrap()) == Some(registers));
+        assert!(decode_registers(&[0; 6]).is_none());
+        assert!(decode_registers(&[0; 8]).is_none());
+
+        let mut r = CpuRegisters::default();
+        assert!(set_register(&mut r, 0, &[1]) && set_register(&mut r, 1, &[2]) && set_register(&mut r, 2, &[3]));
+        assert!(set_register(&mut r, 3, &[0x24]) && set_register(&mut r, 4, &[0xfd]) && set_register(&mut r, 5, &[0x34, 0x12]));
+        assert!(r == registers);
+        assert!(!set_register(&mut r, 5, &[0x34]));
+        assert!(!set_register(&mut r, 0, &[1, 2]));
+        assert!(!set_register(&mut r, 6, &[1]));
+    }
+
+    #[test]
+    fn skips_bad_checksums() {
+        assert_eq!(checksum_of(b"g"), 0x67);
+        assert_eq!(checksum_of(b"m0,ff"), 0x95);
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let mut connection = Connection { stream, debug_tx: kanal::unbounded().0 };
+
+        let writer = std::thread::spawn(move || {
+            for _ in 0..10_000 {
+                client.write_all(b"$g#00").unwrap();
+            }
+            client.write_all(b"$g#67").unwrap();
+            client.write_all(&[INTERRUPT]).unwrap();
+            let mut acks = vec![];
+            client.read_to_end(&mut acks).unwrap();
+            acks
+        });
+        assert!(matches!(connection.read_packet().unwrap(), Some(Packet::Data(data)) if data == "g"));
+        assert!(matches!(connection.read_packet().unwrap(), Some(Packet::Interrupt)));
+        drop(connection);
+        let acks = writer.join().unwrap();
+        assert_eq!(acks.len(), 10_001);
+        assert!(acks[..10_000].iter().all(|ack| *ack == b'-') && acks[10_000] == b'+');
+    }
+}
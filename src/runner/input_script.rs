@@ -0,0 +1,98 @@
+use std::{collections::VecDeque, path::Path};
+use holani::suzy::registers::{Joystick, Switches};
+
+use super::runner_config::Input;
+
+/// Frame stamped inputs applied by the runner thread.
+///
+/// Text format, one event per line, `#` starts a comment:
+/// ```text
+/// <frame> [<input>,<input>,...]
+/// ```
+/// The listed inputs are held from `<frame>` until the next event, a line with
+/// only a frame number releases everything.
+pub(crate) struct InputScript {
+    events: VecDeque<(u64, u8, u8)>,
+}
+
+impl InputScript {
+    pub(crate) fn new(mut events: Vec<(u64, u8, u8)>) -> Self {
+        events.sort_by_key(|(frame, _, _)| *frame);
+        Self {
+            events: events.into(),
+        }
+    }
+
+    pub(crate) fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(&text)
+    }
+
+    pub(crate) fn parse(text: &str) -> Result<Self, String> {
+        let mut events = vec![];
+
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (frame, inputs) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let frame = frame.parse::<u64>().map_err(|_| format!("line {}: invalid frame '{}'.", n + 1, frame))?;
+
+            let mut joystick = Joystick::empty();
+            let mut switches = Switches::empty();
+            for input in inputs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+                let input = input.parse::<Input>().map_err(|e| format!("line {}: {}", n + 1, e))?;
+                input.apply(&mut joystick, &mut switches, true);
+            }
+
+            events.push((frame, joystick.bits(), switches.bits()));
+        }
+
+        Ok(Self::new(events))
+    }
+
+    /// Returns the latest `(joystick, switches)` state scheduled at or before `frame`, if any is pending.
+    pub(crate) fn next(&mut self, frame: u64) -> Option<(u8, u8)> {
+        let mut state = None;
+        while let Some(&(f, joy, sw)) = self.events.front() {
+            if f > frame {
+                break;
+            }
+            state = Some((joy, sw));
+            self.events.pop_front();
+        }
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_events_in_frame_order() {
+        let mut script = InputScript::parse("# intro\n120 a\n\n60 up, right ,pause # hold\n180\n").unwrap();
+        let up_right = (Joystick::up.bits() | Joystick::right.bits(), Switches::pause.bits());
+        assert_eq!(script.next(59), None);
+        assert_eq!(script.next(60), Some(up_right));
+        assert_eq!(script.next(119), None);
+        assert_eq!(script.next(120), Some((Joystick::outside.bits(), 0)));
+        assert_eq!(script.next(180), Some((0, 0)));
+        assert_eq!(script.next(1000), None);
+    }
+
+    #[test]
+    fn skipped_frames_keep_the_latest_event() {
+        let mut script = InputScript::parse("0 b\n10 o1\n20 o2").unwrap();
+        assert_eq!(script.next(15), Some((Joystick::option_1.bits(), 0)));
+        assert_eq!(script.next(20), Some((Joystick::option_2.bits(), 0)));
+    }
+
+    #[test]
+    fn reports_the_bad_line() {
+        assert_eq!(InputScript::parse("0 up\nten a").err().unwrap(), "line 2: invalid frame 'ten'.");
+        assert_eq!(InputScript::parse("0 up\n\n5 jump").err().unwrap(), "line 3: Unknown input 'jump'.");
+    }
+}
@@ -0,0 +1,79 @@
+use holani::lynx::Lynx;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct CpuRegisters {
+    pub pc: u16,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub s: u8,
+    pub p: u8,
+}
+
+const FLAGS: &str = "NV-BDIZC";
+
+impl CpuRegisters {
+    /// Status flags as `NV-BDIZC`, cleared ones as `.`.
+    pub(crate) fn flags(&self) -> String {
+        FLAGS.chars().enumerate()
+            .map(|(i, c)| if self.p & (0x80 >> i) != 0 { c } else { '.' })
+            .collect()
+    }
+}
+
+/// Debugger access to the core, side effect free reads and writes, CPU registers and instruction boundaries.
+pub(crate) trait LynxExt {
+    fn registers(&self) -> CpuRegisters;
+    fn set_registers(&mut self, registers: &CpuRegisters);
+    fn peek(&self, addr: u16) -> u8;
+    fn peek_range(&self, addr: u16, len: usize) -> Vec<u8>;
+    /// Little endian.
+    fn peek_word(&self, addr: u16) -> u16;
+    fn poke(&mut self, addr: u16, data: u8);
+    /// `true` while the CPU is fetching the opcode of the next instruction, PC points to it.
+    fn instruction_start(&self) -> bool;
+}
+
+impl LynxExt for Lynx {
+    fn registers(&self) -> CpuRegisters {
+        let cpu = self.cpu();
+        CpuRegisters {
+            pc: cpu.pc(),
+            a: cpu.a(),
+            x: cpu.x(),
+            y: cpu.y(),
+            s: cpu.s(),
+            p: cpu.flags().bits(),
+        }
+    }
+
+    fn set_registers(&mut self, registers: &CpuRegisters) {
+        let cpu = self.cpu_mut();
+        cpu.set_pc(registers.pc);
+        cpu.set_a(registers.a);
+        cpu.set_x(registers.x);
+        cpu.set_y(registers.y);
+        cpu.set_s(registers.s);
+        cpu.set_flags_u8(registers.p);
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.debug_peek(addr)
+    }
+
+    fn peek_range(&self, addr: u16, len: usize) -> Vec<u8> {
+        (0..len).map(|i| self.debug_peek(addr.wrapping_add(i as u16))).collect()
+    }
+
+    fn peek_word(&self, addr: u16) -> u16 {
+        u16::from_le_bytes([self.debug_peek(addr), self.debug_peek(addr.wrapping_add(1))])
+    }
+
+    fn poke(&mut self, addr: u16, data: u8) {
+        self.debug_poke(addr, data);
+    }
+
+    fn instruction_start(&self) -> bool {
+        self.cpu().is_fetching_opcode()
+    }
+}
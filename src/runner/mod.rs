@@ -1,12 +1,33 @@
 use std::thread::JoinHandle;
+use cheats::load_cheats;
 use comlynx_runner_thread::ComlynxRunnerThread;
+use comlynx_sniffer::ComlynxSniffer;
+use debugger::{DebugCommand, DebugEvent, Debugger};
+use gdb_stub::GdbStub;
 use holani::cartridge::lnx_header::LNXRotation;
-use log::trace;
+use log::{error, trace};
 use perframe_runner_thread::PerFrameRunnerThread;
+use profiler::Profiler;
 use runner_config::RunnerConfig;
 use thread_priority::*;
+use trace::Tracer;
 
 pub(crate) mod runner_config;
+pub(crate) mod archive;
+pub(crate) mod input_script;
+pub(crate) mod movie;
+pub(crate) mod lynx_ext;
+pub(crate) mod debugger;
+pub(crate) mod breakpoint;
+pub(crate) mod scb;
+pub(crate) mod trace;
+pub(crate) mod cheats;
+pub(crate) mod profiler;
+pub(crate) mod netplay;
+pub(crate) mod gdb_stub;
+pub(crate) mod spectator;
+pub(crate) mod comlynx_link;
+pub(crate) mod comlynx_sniffer;
 pub(crate) mod comlynx_runner_thread;
 pub(crate) mod perframe_runner_thread;
 
@@ -19,18 +40,26 @@ pub(crate) trait RunnerThread {
     fn run(&mut self);
 }
 
+/// UI ends of the core thread channels.
+pub(crate) struct RunnerChannels {
+    pub input_tx: kanal::Sender<(u8, u8)>,
+    pub update_display_rx: kanal::Receiver<Vec<u8>>,
+    pub debug_tx: kanal::Sender<DebugCommand>,
+    pub debug_rx: kanal::Receiver<DebugEvent>,
+}
+
 pub(crate) struct Runner {
     runner_thread: Option<JoinHandle<()>>,
     config: RunnerConfig,
-    input_tx: Option<kanal::Sender<u8>>,
+    input_tx: Option<kanal::Sender<(u8, u8)>>,
 }
 
 impl Drop for Runner {
     fn drop(&mut self) {
         if let Some(tx) = self.input_tx.take() {
-            tx.close().unwrap();
+            let _ = tx.close();
             if let Some(handle) = self.runner_thread.take() {
-                handle.join().unwrap();
+                let _ = handle.join();
             }
         }
     }
@@ -46,33 +75,71 @@ impl Runner {
         }
     }
 
-    pub fn initialize_thread(&mut self) -> (kanal::Sender<(u8, u8)>, kanal::Receiver<Vec<u8>>, LNXRotation) {
+    /// Starts the core thread, returns the error it stopped on when it couldn't load the game.
+    pub fn initialize_thread(&mut self) -> Result<RunnerChannels, String> {
         let (input_tx, input_rx) = kanal::unbounded::<(u8, u8)>();
+        // Closed on drop to stop the core thread and join it, reports and traces are written on exit.
+        self.input_tx = Some(input_tx.clone());
         let (update_display_tx, update_display_rx) = kanal::unbounded::<Vec<u8>>();
+        let (debug_tx, debug_rx) = kanal::unbounded::<DebugCommand>();
+        let (debug_event_tx, debug_event_rx) = kanal::bounded::<DebugEvent>(4);
         let (rotation_tx, rotation_rx) = kanal::unbounded::<LNXRotation>();
+        let (error_tx, error_rx) = kanal::bounded::<String>(1);
 
         let conf = self.config.clone();
 
+        if let Some(port) = conf.gdb_port() {
+            if let Err(err) = GdbStub::new(port, debug_tx.clone()).spawn() {
+                error!("GDB stub on port {}: {}", port, err);
+            }
+        }
+
         self.runner_thread = Some(
             std::thread::Builder::new()
             .name("Core".to_string())
             .spawn_with_priority(ThreadPriority::Max, move |_| {
+                let mut debugger = Debugger::new(debug_rx, debug_event_tx);
+                debugger.set_profiler(Profiler::new(conf.profile_idle().clone(), conf.symbols().clone(), conf.profile().clone()));
+                debugger.set_sniffer(ComlynxSniffer::new(conf.instance(), conf.comlynx_log().clone()));
+                if let Some(path) = conf.cheats().as_ref().filter(|path| path.exists()) {
+                    match load_cheats(path) {
+                        Ok(cheats) => cheats.into_iter().for_each(|cheat| debugger.add_cheat(cheat)),
+                        Err(err) => error!("Cheats: {}", err),
+                    }
+                }
+                if let Some(trace) = conf.trace() {
+                    match Tracer::create(trace, conf.trace_ranges().clone(), conf.trace_ring(), conf.symbols().clone()) {
+                        Ok(tracer) => debugger.set_tracer(tracer),
+                        Err(err) => {
+                            let _ = error_tx.send(format!("Couldn't create trace file. {}", err));
+                            return;
+                        }
+                    }
+                }
                 let mut thread: Box<dyn RunnerThread> = match conf.comlynx() {
-                    true => Box::new(ComlynxRunnerThread::new(conf, input_rx, update_display_tx, rotation_tx)),
-                    false => Box::new(PerFrameRunnerThread::new(conf, input_rx, update_display_tx, rotation_tx)),
+                    true => Box::new(ComlynxRunnerThread::new(conf, input_rx, update_display_tx, rotation_tx, debugger)),
+                    false => Box::new(PerFrameRunnerThread::new(conf, input_rx, update_display_tx, rotation_tx, debugger)),
                 };
                 trace!("Runner started.");
-                thread.initialize().unwrap_or_else(|err| {
-                    println!("Error: {}", err);
-                    std::process::exit(1);
-                });
+                if let Err(err) = thread.initialize() {
+                    let _ = error_tx.send(err.to_string());
+                    return;
+                }
                 thread.run();
             })
             .expect("Could not create the main core runner thread.")
         );
 
-        let rotation = rotation_rx.recv().unwrap();
-       
-        (input_tx, update_display_rx, rotation)
+        // Sent once the cartridge is loaded, the screen isn't rotated. The error is sent before the
+        // thread stops.
+        if rotation_rx.recv().is_err() {
+            self.input_tx = None;
+            if let Some(handle) = self.runner_thread.take() {
+                let _ = handle.join();
+            }
+            return Err(error_rx.try_recv().ok().flatten().unwrap_or_else(|| "Core runner stopped unexpectedly.".to_string()));
+        }
+
+        Ok(RunnerChannels { input_tx, update_display_rx, debug_tx, debug_rx: debug_event_rx })
     }
 }
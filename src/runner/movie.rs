@@ -0,0 +1,67 @@
+use std::{fs::File, io::{BufWriter, Write}, path::Path};
+
+use super::input_script::InputScript;
+
+const MOVIE_HEADER: &str = "holani-movie 1";
+
+/// Records the `(joystick, switches)` values received by the runner thread.
+///
+/// Text format, a header line followed by one event per line:
+/// ```text
+/// holani-movie 1
+/// <frame> <joystick> <switches>
+/// ```
+/// joystick and switches as 2 hex digits.
+pub(crate) struct MovieRecorder {
+    writer: BufWriter<File>,
+}
+
+impl MovieRecorder {
+    pub(crate) fn create(path: &Path) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut recorder = Self {
+            writer: BufWriter::new(file),
+        };
+        recorder.write_line(MOVIE_HEADER)?;
+        Ok(recorder)
+    }
+
+    pub(crate) fn record(&mut self, frame: u64, joystick: u8, switches: u8) -> Result<(), String> {
+        self.write_line(&format!("{} {:02x} {:02x}", frame, joystick, switches))
+    }
+
+    /// Events are flushed as they come, the core thread isn't guaranteed to be joined on exit.
+    fn write_line(&mut self, line: &str) -> Result<(), String> {
+        writeln!(self.writer, "{}", line)
+            .and_then(|_| self.writer.flush())
+            .map_err(|e| e.to_string())
+    }
+}
+
+pub(crate) fn load_movie(path: &Path) -> Result<InputScript, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut lines = text.lines().enumerate();
+
+    match lines.next() {
+        Some((_, header)) if header.trim() == MOVIE_HEADER => (),
+        _ => return Err(format!("{}: not a movie file.", path.display())),
+    }
+
+    let mut events = vec![];
+    for (n, line) in lines {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [frame, joystick, switches] = fields[..] else {
+            return Err(format!("line {}: expected '<frame> <joystick> <switches>'.", n + 1));
+        };
+        let frame = frame.parse::<u64>().map_err(|_| format!("line {}: invalid frame '{}'.", n + 1, frame))?;
+        let joystick = u8::from_str_radix(joystick, 16).map_err(|_| format!("line {}: invalid joystick '{}'.", n + 1, joystick))?;
+        let switches = u8::from_str_radix(switches, 16).map_err(|_| format!("line {}: invalid switches '{}'.", n + 1, switches))?;
+        events.push((frame, joystick, switches));
+    }
+
+    Ok(InputScript::new(events))
+}
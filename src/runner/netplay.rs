@@ -0,0 +1,356 @@
+use std::{collections::BTreeMap, io::{self, ErrorKind}, net::{SocketAddr, ToSocketAddrs, UdpSocket}, time::{Duration, Instant}};
+use log::{error, info, warn};
+
+const INPUTS: u8 = b'I';
+const HASH: u8 = b'H';
+pub(crate) const MAX_DELAY: u8 = 30;
+pub(crate) const MAX_ROLLBACK: u8 = 15;
+/// Largest packet, inputs of `2 * MAX_DELAY + 2 * MAX_ROLLBACK + 1` frames.
+const PACKET_LEN: usize = 1 + 8 + 1 + 2 * (2 * MAX_DELAY as usize + 2 * MAX_ROLLBACK as usize + 1);
+/// Frames between two sync checks.
+pub(crate) const HASH_INTERVAL: u64 = 60;
+const WAIT_POLL: Duration = Duration::from_millis(1);
+const RESEND: Duration = Duration::from_millis(10);
+const WAIT_WARNING: Duration = Duration::from_secs(5);
+
+#[derive(Clone)]
+pub(crate) struct NetplaySettings {
+    pub bind: String,
+    pub peer: String,
+    /// Local player, 1 or 2.
+    pub player: u8,
+    /// Frames between a local input and the frame it's applied on.
+    pub delay: u8,
+    /// Frames run ahead of the peer inputs on predicted inputs, 0 for lockstep.
+    pub rollback: u8,
+}
+
+/// Per frame `(joystick, switches)` exchange with a peer over UDP.
+///
+/// Both instances run the same pair of Lynx from power-on, linked by an in-process Comlynx cable, and
+/// apply on every frame each player's inputs to that player's console. A local input is applied `delay` frames later, giving the peer's input that much time
+/// to arrive. Packets carry the local inputs of all the frames the peer may still be missing, a lost
+/// packet is covered by the next one.
+///
+/// Without rollback a frame runs once the peer input for it arrived. With rollback, up to `rollback`
+/// frames run ahead on predicted peer inputs, the last one received repeated. When a prediction turns
+/// out wrong the runner restores the state of that frame and runs the following frames again.
+///
+/// Frame hashes are compared every `HASH_INTERVAL` frames, once no prediction is involved. The runner
+/// ends the session on the first mismatch.
+pub(crate) struct Netplay {
+    socket: UdpSocket,
+    peer: SocketAddr,
+    /// Local player index, 0 or 1.
+    player: usize,
+    delay: u64,
+    rollback: u64,
+    local: BTreeMap<u64, (u8, u8)>,
+    remote: BTreeMap<u64, (u8, u8)>,
+    /// First frame without the peer input.
+    confirmed: u64,
+    /// Predicted peer inputs of the unconfirmed frames run.
+    predicted: BTreeMap<u64, (u8, u8)>,
+    /// Hashes of frames not confirmed yet.
+    pending_hashes: BTreeMap<u64, u64>,
+    hashes: BTreeMap<u64, u64>,
+    remote_hashes: BTreeMap<u64, u64>,
+    /// First frame found out of sync.
+    desync: Option<u64>,
+    frame: u64,
+    wait_start: Option<Instant>,
+    last_send: Instant,
+}
+
+impl Netplay {
+    pub(crate) fn open(settings: &NetplaySettings) -> io::Result<Self> {
+        let socket = UdpSocket::bind(&settings.bind)?;
+        let peer = settings.peer.to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, format!("no address for {}", settings.peer)))?;
+        socket.set_nonblocking(true)?;
+        info!(
+            "Netplay on {} with {}, {} frames of input delay, {} frames of rollback.",
+            socket.local_addr()?, peer, settings.delay, settings.rollback,
+        );
+        let delay = settings.delay.min(MAX_DELAY) as u64;
+        Ok(Self {
+            socket,
+            peer,
+            player: settings.player.clamp(1, 2) as usize - 1,
+            delay,
+            rollback: settings.rollback.min(MAX_ROLLBACK) as u64,
+            local: BTreeMap::new(),
+            // The first `delay` frames have no input.
+            remote: (0..delay).map(|frame| (frame, (0, 0))).collect(),
+            confirmed: delay,
+            predicted: BTreeMap::new(),
+            pending_hashes: BTreeMap::new(),
+            hashes: BTreeMap::new(),
+            remote_hashes: BTreeMap::new(),
+            desync: None,
+            frame: 0,
+            wait_start: None,
+            last_send: Instant::now(),
+        })
+    }
+
+    /// Schedules the local input read at `frame`, applied on `frame + delay`. Called again for the same
+    /// frame the first input stays.
+    pub(crate) fn add_local(&mut self, frame: u64, input: (u8, u8)) {
+        self.frame = frame;
+        self.local.entry(frame + self.delay).or_insert(input);
+        let kept = frame.saturating_sub(2 * (self.delay + self.rollback) + 1);
+        self.local.retain(|f, _| *f >= kept);
+        self.remote.retain(|f, _| *f >= kept);
+        self.send_inputs();
+    }
+
+    /// Local player index, 0 or 1.
+    pub(crate) fn player(&self) -> usize {
+        self.player
+    }
+
+    /// Frames run ahead of the peer inputs.
+    pub(crate) fn rollback(&self) -> u64 {
+        self.rollback
+    }
+
+    /// `frame` can run, its peer input arrived or it's within the rollback window.
+    pub(crate) fn ready(&self, frame: u64) -> bool {
+        frame < self.confirmed + self.rollback
+    }
+
+    /// Both players inputs applied on `frame`, in player order, the peer's predicted when it hasn't arrived.
+    pub(crate) fn inputs(&mut self, frame: u64) -> [(u8, u8); 2] {
+        let remote = match self.remote.get(&frame) {
+            Some(remote) if frame < self.confirmed => *remote,
+            _ => {
+                let last = self.confirmed.checked_sub(1).and_then(|last| self.remote.get(&last));
+                let predicted = last.copied().unwrap_or_default();
+                self.predicted.insert(frame, predicted);
+                predicted
+            }
+        };
+        let local = self.local.get(&frame).copied().unwrap_or_default();
+        match self.player {
+            0 => [local, remote],
+            _ => [remote, local],
+        }
+    }
+
+    /// First frame run on a wrong prediction, the runner has to run it again from its start.
+    pub(crate) fn misprediction(&mut self) -> Option<u64> {
+        let wrong = self.predicted.range(..self.confirmed)
+            .find(|(frame, predicted)| self.remote.get(frame) != Some(predicted))
+            .map(|(frame, _)| *frame);
+        self.predicted.retain(|frame, _| *frame >= self.confirmed);
+        wrong
+    }
+
+    /// Hash of `frame` after it ran, sent and compared every `HASH_INTERVAL` frames.
+    pub(crate) fn frame_hash(&mut self, frame: u64, hash: u64) {
+        if frame.is_multiple_of(HASH_INTERVAL) {
+            self.pending_hashes.insert(frame, hash);
+        }
+    }
+
+    /// Sends the hashes of the confirmed frames, to be called once the mispredicted frames ran again.
+    pub(crate) fn send_hashes(&mut self) {
+        let confirmed: Vec<u64> = self.pending_hashes.range(..self.confirmed).map(|(frame, _)| *frame).collect();
+        for frame in confirmed {
+            let hash = self.pending_hashes.remove(&frame).unwrap();
+            let mut packet = vec![HASH];
+            packet.extend(frame.to_le_bytes());
+            packet.extend(hash.to_le_bytes());
+            self.send(&packet);
+            self.hashes.insert(frame, hash);
+        }
+        self.check_sync();
+    }
+
+    /// First frame found out of sync with the peer.
+    pub(crate) fn desync(&self) -> Option<u64> {
+        self.desync
+    }
+
+    /// Waiting for the peer, resends the local inputs in case they were lost.
+    pub(crate) fn idle(&mut self) {
+        let wait_start = *self.wait_start.get_or_insert_with(Instant::now);
+        if wait_start.elapsed() > WAIT_WARNING {
+            warn!("Netplay: waiting for the peer input of frame {}.", self.confirmed);
+            self.wait_start = Some(Instant::now());
+        }
+        if self.last_send.elapsed() > RESEND {
+            self.send_inputs();
+        }
+        std::thread::sleep(WAIT_POLL);
+    }
+
+    pub(crate) fn receive(&mut self) {
+        let mut packet = [0; PACKET_LEN];
+        while let Ok((len, from)) = self.socket.recv_from(&mut packet) {
+            if from != self.peer || len < 9 {
+                continue;
+            }
+            let frame = u64::from_le_bytes(packet[1..9].try_into().unwrap());
+            match (packet[0], &packet[9..len]) {
+                (INPUTS, [count, inputs @ ..]) if inputs.len() == *count as usize * 2 => {
+                    let first = (frame + 1).saturating_sub(*count as u64);
+                    for (n, input) in inputs.chunks_exact(2).enumerate() {
+                        if first + n as u64 >= self.confirmed {
+                            self.remote.insert(first + n as u64, (input[0], input[1]));
+                        }
+                    }
+                    while self.remote.contains_key(&self.confirmed) {
+                        self.confirmed += 1;
+                        self.wait_start = None;
+                    }
+                }
+                (HASH, hash) if hash.len() == 8 => {
+                    self.remote_hashes.insert(frame, u64::from_le_bytes(hash.try_into().unwrap()));
+                    self.check_sync();
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn check_sync(&mut self) {
+        let matched: Vec<u64> = self.hashes.keys().filter(|f| self.remote_hashes.contains_key(f)).copied().collect();
+        for frame in matched {
+            let (local, remote) = (self.hashes[&frame], self.remote_hashes[&frame]);
+            if local != remote && self.desync.is_none() {
+                error!("Netplay desync at frame {}: local hash {:016x}, peer {:016x}.", frame, local, remote);
+                self.desync = Some(frame);
+            }
+            self.hashes.remove(&frame);
+            self.remote_hashes.remove(&frame);
+        }
+    }
+
+    /// Local inputs from the oldest frame the peer may still be waiting for.
+    fn send_inputs(&mut self) {
+        let Some(last) = self.local.keys().next_back().copied() else {
+            return;
+        };
+        let first = self.frame.saturating_sub(self.delay + 2 * self.rollback);
+        let inputs: Vec<(u8, u8)> = self.local.range(first..=last).map(|(_, input)| *input).collect();
+        let mut packet = vec![INPUTS];
+        packet.extend(last.to_le_bytes());
+        packet.push(inputs.len() as u8);
+        inputs.iter().for_each(|(joy, sw)| packet.extend([*joy, *sw]));
+        self.send(&packet);
+        self.last_send = Instant::now();
+    }
+
+    fn send(&self, packet: &[u8]) {
+        if let Err(err) = self.socket.send_to(packet, self.peer) {
+            if err.kind() != ErrorKind::WouldBlock {
+                warn!("Netplay send: {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Netplay on localhost with its peer's socket.
+    pub(crate) fn open(player: u8, delay: u8, rollback: u8) -> (Netplay, UdpSocket) {
+        let peer = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let settings = NetplaySettings {
+            bind: "127.0.0.1:0".to_string(),
+            peer: peer.local_addr().unwrap().to_string(),
+            player,
+            delay,
+            rollback,
+        };
+        (Netplay::open(&settings).unwrap(), peer)
+    }
+
+    /// Sends `packet` from `from` and waits for the local socket to have it.
+    pub(crate) fn deliver(netplay: &mut Netplay, from: &UdpSocket, packet: &[u8]) {
+        from.send_to(packet, netplay.socket.local_addr().unwrap()).unwrap();
+        let mut buf = [0; PACKET_LEN];
+        let start = Instant::now();
+        while netplay.socket.peek_from(&mut buf).is_err() {
+            assert!(start.elapsed() < Duration::from_secs(1), "packet not delivered");
+            std::thread::sleep(WAIT_POLL);
+        }
+        netplay.receive();
+    }
+
+    pub(crate) fn inputs_packet(last: u64, inputs: &[(u8, u8)]) -> Vec<u8> {
+        let mut packet = vec![INPUTS];
+        packet.extend(last.to_le_bytes());
+        packet.push(inputs.len() as u8);
+        inputs.iter().for_each(|(joy, sw)| packet.extend([*joy, *sw]));
+        packet
+    }
+
+    pub(crate) fn hash_packet(frame: u64, hash: u64) -> Vec<u8> {
+        let mut packet = vec![HASH];
+        packet.extend(frame.to_le_bytes());
+        packet.extend(hash.to_le_bytes());
+        packet
+    }
+
+    #[test]
+    fn confirms_the_received_frames() {
+        let (mut netplay, peer) = open(1, 2, 0);
+        assert!(netplay.ready(1));
+        assert!(!netplay.ready(2));
+        deliver(&mut netplay, &peer, &inputs_packet(3, &[(1, 0), (2, 0)]));
+        assert_eq!(netplay.confirmed, 4);
+        assert!(netplay.ready(3));
+        netplay.add_local(1, (0x10, 1));
+        assert_eq!(netplay.inputs(3), [(0x10, 1), (2, 0)]);
+    }
+
+    #[test]
+    fn inputs_are_in_player_order() {
+        let (mut netplay, peer) = open(2, 0, 0);
+        netplay.add_local(0, (0x80, 0));
+        deliver(&mut netplay, &peer, &inputs_packet(0, &[(0x40, 1)]));
+        assert_eq!(netplay.inputs(0), [(0x40, 1), (0x80, 0)]);
+    }
+
+    #[test]
+    fn a_gap_waits_for_the_missing_frame() {
+        let (mut netplay, peer) = open(1, 0, 0);
+        deliver(&mut netplay, &peer, &inputs_packet(5, &[(5, 0)]));
+        assert_eq!(netplay.confirmed, 0);
+        deliver(&mut netplay, &peer, &inputs_packet(5, &[(0, 0), (1, 0), (2, 0), (3, 0), (4, 0), (5, 0)]));
+        assert_eq!(netplay.confirmed, 6);
+        assert_eq!(netplay.remote[&5], (5, 0));
+    }
+
+    #[test]
+    fn ignores_malformed_and_foreign_packets() {
+        let (mut netplay, peer) = open(1, 0, 0);
+        let mut wrong_count = inputs_packet(1, &[(1, 0), (2, 0)]);
+        wrong_count[9] = 3;
+        deliver(&mut netplay, &peer, &wrong_count);
+        deliver(&mut netplay, &peer, &[INPUTS, 0, 0]);
+        deliver(&mut netplay, &peer, &[b'X', 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let stranger = UdpSocket::bind("127.0.0.1:0").unwrap();
+        deliver(&mut netplay, &stranger, &inputs_packet(0, &[(1, 0)]));
+        assert_eq!(netplay.confirmed, 0);
+        assert!(netplay.remote.is_empty());
+    }
+
+    #[test]
+    fn a_different_hash_is_a_desync() {
+        let (mut netplay, peer) = open(1, 0, 0);
+        deliver(&mut netplay, &peer, &inputs_packet(HASH_INTERVAL, &vec![(0, 0); HASH_INTERVAL as usize + 1]));
+        netplay.frame_hash(0, 1);
+        netplay.frame_hash(HASH_INTERVAL, 2);
+        netplay.send_hashes();
+        deliver(&mut netplay, &peer, &hash_packet(0, 1));
+        assert_eq!(netplay.desync(), None);
+        deliver(&mut netplay, &peer, &hash_packet(HASH_INTERVAL, 3));
+        assert_eq!(netplay.desync(), Some(HASH_INTERVAL));
+    }
+}
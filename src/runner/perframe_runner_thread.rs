@@ -1,11 +1,93 @@
-use std::time::{Duration, Instant};
+use std::{collections::VecDeque, time::{Duration, Instant}};
 use holani::{cartridge::lnx_header::LNXRotation, lynx::Lynx};
-use log::trace;
+use log::{error, trace};
 use rodio::{buffer::SamplesBuffer, OutputStream, Sink};
 
-use super::{RunnerConfig, RunnerThread, CRYSTAL_FREQUENCY, SAMPLE_RATE};
+use crate::{cartridge_browser::is_cartridge, frame_test::frame_hash};
+
+use super::{archive, comlynx_link::{ComlynxEndpoint, ComlynxLink}, debugger::Debugger, input_script::InputScript, movie::{load_movie, MovieRecorder}, netplay::Netplay, spectator::SpectatorServer, RunnerConfig, RunnerThread, CRYSTAL_FREQUENCY, SAMPLE_RATE};
 const TICKS_PER_AUDIO_SAMPLE: u64 = CRYSTAL_FREQUENCY as u64 / SAMPLE_RATE as u64;
 
+/// Netplay console pair, the runner's console is the local player's.
+#[derive(Clone)]
+struct LinkedConsoles {
+    /// Local player index, 0 or 1.
+    player: usize,
+    /// The peer player's console.
+    remote: Lynx,
+    /// In-process cable ends, in player order.
+    links: [ComlynxLink; 2],
+    /// Ticks since power-on, both consoles tick together.
+    ticks: u64,
+}
+
+impl LinkedConsoles {
+    fn new(player: usize, local: &Lynx) -> Self {
+        let endpoints = ComlynxEndpoint::cable(2);
+        let open = |n: usize| ComlynxLink::open(&endpoints[n]).expect("Couldn't open the in-process Comlynx cable.");
+        Self {
+            player,
+            remote: local.clone(),
+            links: [open(0), open(1)],
+            ticks: 0,
+        }
+    }
+
+    fn set_inputs(&mut self, local: &mut Lynx, inputs: [(u8, u8); 2]) {
+        let (joy, sw) = inputs[self.player];
+        local.set_joystick_u8(joy);
+        local.set_switches_u8(sw);
+        let (joy, sw) = inputs[1 - self.player];
+        self.remote.set_joystick_u8(joy);
+        self.remote.set_switches_u8(sw);
+    }
+
+    /// Ticks the peer player's console, after the local one ticked, and carries the Comlynx bytes between
+    /// them. Returns the bytes the local console sent and received.
+    fn tick(&mut self, local: &mut Lynx) -> (Option<u8>, Option<u8>) {
+        self.remote.tick();
+        self.ticks += 1;
+        let ticks = self.ticks;
+        let [first, second] = &mut self.links;
+        let (local_link, remote_link) = match self.player {
+            0 => (first, second),
+            _ => (second, first),
+        };
+        let sent = local.comlynx_take_tx();
+        if let Some(data) = sent {
+            local_link.send(ticks, data);
+        }
+        if let Some(data) = self.remote.comlynx_take_tx() {
+            remote_link.send(ticks, data);
+        }
+        let received = local_link.receive(ticks);
+        if let Some(data) = received {
+            local.comlynx_receive(data);
+        }
+        if let Some(data) = remote_link.receive(ticks) {
+            self.remote.comlynx_receive(data);
+        }
+        (sent, received)
+    }
+
+    /// Frame end, on the first player's console so that both instances agree.
+    fn redraw_requested(&mut self, local: &mut Lynx) -> bool {
+        match self.player {
+            0 => local.redraw_requested(),
+            _ => self.remote.redraw_requested(),
+        }
+    }
+
+    /// Both screens hashed, in player order.
+    fn hash(&self, local: &Lynx) -> u64 {
+        let (first, second) = match self.player {
+            0 => (local, &self.remote),
+            _ => (&self.remote, local),
+        };
+        frame_hash(first.screen_rgb()) ^ frame_hash(second.screen_rgb()).rotate_left(32)
+    }
+}
+
 pub(crate) struct PerFrameRunnerThread {
     lynx: Lynx,
     sound_tick: u64,
@@ -14,6 +96,17 @@ pub(crate) struct PerFrameRunnerThread {
     input_rx: kanal::Receiver<(u8, u8)>,
     update_display_tx: kanal::Sender<Vec<u8>>,
     rotation_tx: kanal::Sender<LNXRotation>,
+    frame: u64,
+    input_script: Option<InputScript>,
+    movie_recorder: Option<MovieRecorder>,
+    debugger: Debugger,
+    spectators: Option<SpectatorServer>,
+    netplay: Option<Netplay>,
+    linked: Option<LinkedConsoles>,
+    /// Last input read from the UI, sent to the netplay peer.
+    local_input: (u8, u8),
+    /// Netplay console states at the start of the last frames, oldest first.
+    snapshots: VecDeque<(u64, Lynx, LinkedConsoles)>,
     frame_time: Duration,
     next_lcd_refresh: Instant,
     last_refresh_rate: f64,
@@ -27,6 +120,7 @@ impl PerFrameRunnerThread {
         input_rx: kanal::Receiver<(u8, u8)>, 
         update_display_tx: kanal::Sender<Vec<u8>>, 
         rotation_tx: kanal::Sender<LNXRotation>,
+        debugger: Debugger,
     ) -> Self {
         Self {
             lynx: Lynx::new(),
@@ -34,6 +128,15 @@ impl PerFrameRunnerThread {
             input_rx,
             update_display_tx,
             rotation_tx,
+            frame: 0,
+            input_script: None,
+            movie_recorder: None,
+            debugger,
+            spectators: None,
+            netplay: None,
+            linked: None,
+            local_input: (0, 0),
+            snapshots: VecDeque::new(),
             sound_tick: 0,
             sound_sample: vec![],
             frame_time: Duration::from_millis(16),
@@ -45,7 +148,7 @@ impl PerFrameRunnerThread {
     }
 
     fn sound(&mut self) {
-        if self.config.mute() {
+        if self.config.mute() && self.spectators.is_none() {
             return;
         }
 
@@ -57,48 +160,192 @@ impl PerFrameRunnerThread {
 
         self.sound_tick = 0;
         let (l, r) = self.lynx.audio_sample();
-        self.sound_sample.push(l);
-        self.sound_sample.push(r);        
+        if let Some(spectators) = self.spectators.as_mut() {
+            spectators.sample((l, r));
+        }
+        if !self.config.mute() {
+            self.sound_sample.push(l);
+            self.sound_sample.push(r);
+        }
     }
 
     fn display(&mut self) {
         trace!("Display updated.");
         let screen = self.lynx.screen_rgb().clone();
+        if let Some(spectators) = self.spectators.as_mut() {
+            spectators.frame(&screen);
+        }
+        if let (Some(netplay), Some(linked)) = (self.netplay.as_mut(), self.linked.as_ref()) {
+            netplay.frame_hash(self.frame, linked.hash(&self.lynx));
+        }
         let _ = self.update_display_tx.try_send(screen).is_ok();
+        self.frame += 1;
+        self.debugger.frame_done(&mut self.lynx);
+    }
+
+    fn record(&mut self, joy: u8, sw: u8) {
+        if let Some(Err(err)) = self.movie_recorder.as_mut().map(|recorder| recorder.record(self.frame, joy, sw)) {
+            error!("Movie recording stopped: {}", err);
+            self.movie_recorder = None;
+        }
     }
 
     fn inputs(&mut self) -> bool {
         if self.input_rx.is_disconnected() {
             return true;
         } else if let Ok(Some((joy, sw))) = self.input_rx.try_recv() {
+            if self.netplay.is_some() {
+                self.local_input = (joy, sw);
+            } else if self.config.play_movie().is_none() {
+                self.lynx.set_joystick_u8(joy);
+                self.lynx.set_switches_u8(sw);
+                self.record(joy, sw);
+            }
+        }
+        if let Some((joy, sw)) = self.input_script.as_mut().and_then(|script| script.next(self.frame)) {
             self.lynx.set_joystick_u8(joy);
             self.lynx.set_switches_u8(sw);
+            self.record(joy, sw);
+        }
+        if self.netplay.is_some() {
+            return self.netplay_inputs();
         }
         false
-    }   
+    }
+
+    /// Applies both players inputs, runs the mispredicted frames again. Returns `true` if the UI exited
+    /// while waiting for the peer. Out of sync, the session ends and the game is paused.
+    fn netplay_inputs(&mut self) -> bool {
+        let (Some(mut netplay), Some(mut linked)) = (self.netplay.take(), self.linked.take()) else {
+            return false;
+        };
+        match self.netplay_frame(&mut netplay, &mut linked) {
+            Ok(exited) => {
+                self.netplay = Some(netplay);
+                self.linked = Some(linked);
+                exited
+            }
+            Err(err) => {
+                error!("{}", err);
+                self.snapshots.clear();
+                self.debugger.halt(format!("{} Session stopped, game paused.", err));
+                false
+            }
+        }
+    }
+
+    fn netplay_frame(&mut self, netplay: &mut Netplay, linked: &mut LinkedConsoles) -> Result<bool, String> {
+        netplay.add_local(self.frame, self.local_input);
+        loop {
+            netplay.receive();
+            if let Some(frame) = netplay.misprediction() {
+                self.rollback(netplay, linked, frame)?;
+            }
+            netplay.send_hashes();
+            if let Some(frame) = netplay.desync() {
+                return Err(format!("Netplay out of sync with the peer at frame {}.", frame));
+            }
+            if netplay.ready(self.frame) {
+                break;
+            }
+            if self.input_rx.is_disconnected() {
+                return Ok(true);
+            }
+            netplay.idle();
+        }
+
+        // Frame start state, unless resuming from a debugger pause.
+        if netplay.rollback() > 0 && self.snapshots.back().is_none_or(|(frame, _, _)| *frame != self.frame) {
+            if self.snapshots.len() > netplay.rollback() as usize {
+                self.snapshots.pop_front();
+            }
+            self.snapshots.push_back((self.frame, self.lynx.clone(), linked.clone()));
+        }
+
+        linked.set_inputs(&mut self.lynx, netplay.inputs(self.frame));
+        Ok(false)
+    }
+
+    /// Restores the state at the start of `from` and runs the frames up to the current one again, with
+    /// the peer inputs received since, without sound nor display.
+    ///
+    /// `ready()` runs a frame on predictions only within `rollback()` frames of the last confirmed one,
+    /// whose start is among the `rollback() + 1` snapshots kept.
+    fn rollback(&mut self, netplay: &mut Netplay, linked: &mut LinkedConsoles, from: u64) -> Result<(), String> {
+        let Some(index) = self.snapshots.iter().position(|(frame, _, _)| *frame == from) else {
+            debug_assert!(false, "no snapshot of frame {}", from);
+            return Err(format!("Netplay: no snapshot of frame {} to roll back to.", from));
+        };
+        trace!("Rolling back {} frames.", self.frame - from);
+        (_, self.lynx, *linked) = self.snapshots[index].clone();
+        self.snapshots.truncate(index);
+        for frame in from..self.frame {
+            self.snapshots.push_back((frame, self.lynx.clone(), linked.clone()));
+            linked.set_inputs(&mut self.lynx, netplay.inputs(frame));
+            while !linked.redraw_requested(&mut self.lynx) {
+                self.lynx.tick();
+                linked.tick(&mut self.lynx);
+            }
+            netplay.frame_hash(frame, linked.hash(&self.lynx));
+        }
+        Ok(())
+    }
+
+    /// Runs the frame until its end or a debugger stop, the netplay peer player's console along.
+    fn run_frame(&mut self) {
+        let Some(mut linked) = self.linked.take() else {
+            while !self.lynx.redraw_requested() {
+                if !self.debugger.tick(&mut self.lynx) {
+                    break;
+                }
+                self.sound();
+            }
+            return;
+        };
+        while !linked.redraw_requested(&mut self.lynx) {
+            if !self.debugger.tick(&mut self.lynx) {
+                break;
+            }
+            let (sent, received) = linked.tick(&mut self.lynx);
+            if let Some(data) = sent {
+                self.debugger.comlynx_transfer(&self.lynx, true, data);
+            }
+            if let Some(data) = received {
+                self.debugger.comlynx_transfer(&self.lynx, false, data);
+            }
+            self.sound();
+        }
+        self.linked = Some(linked);
+    }
 }
 
 impl RunnerThread for PerFrameRunnerThread {
     fn initialize(&mut self) -> Result<(), &str> {
         if let Some(rom) = self.config.rom() {
-            let data = std::fs::read(rom);            
-            if data.is_err() {
-                return Err("Couldn't not load ROM file.");
-            }
-            if self.lynx.load_rom_from_slice(&data.unwrap()).is_err() {
+            let data = match archive::read(rom, None, |_| true) {
+                Ok(data) => data,
+                Err(err) => {
+                    error!("ROM: {}", err);
+                    return Err("Couldn't not load ROM file.");
+                }
+            };
+            if self.lynx.load_rom_from_slice(&data).is_err() {
                 return Err("Couldn't not load ROM file.");
             }
             trace!("ROM loaded.");
         }
 
         match self.config.cartridge() {
-            None => panic!("A cartridge is required."),
+            None => return Err("A cartridge is required."),
             Some(cart) => {
-                let data = std::fs::read(cart);            
-                if data.is_err() {
-                    return Err("Couldn't not load Cartridge file.");
-                }
-                if self.lynx.load_cart_from_slice(&data.unwrap()).is_err() {
+                let data = match archive::read(cart, self.config.cartridge_member().as_deref(), is_cartridge) {
+                    Ok(data) => data,
+                    Err(err) => {
+                        error!("Cartridge: {}", err);
+                        return Err("Couldn't not load Cartridge file.");
+                    }
+                };
+                if self.lynx.load_cart_from_slice(&data).is_err() {
                     return Err("Couldn't not load Cartridge file.");
                 }
                 trace!("ROM loaded.");
@@ -106,6 +353,64 @@ impl RunnerThread for PerFrameRunnerThread {
         }
 
         trace!("Cart loaded.");
+
+        if let Some(script) = self.config.input_script() {
+            match InputScript::load(script) {
+                Err(err) => {
+                    error!("Input script: {}", err);
+                    return Err("Couldn't load input script file.");
+                }
+                Ok(script) => self.input_script = Some(script),
+            }
+            trace!("Input script loaded.");
+        }
+
+        if let Some(movie) = self.config.play_movie() {
+            match load_movie(movie) {
+                Err(err) => {
+                    error!("Movie: {}", err);
+                    return Err("Couldn't load movie file.");
+                }
+                Ok(script) => self.input_script = Some(script),
+            }
+            trace!("Movie loaded.");
+        }
+
+        if let Some(movie) = self.config.record_movie() {
+            match MovieRecorder::create(movie) {
+                Err(err) => {
+                    error!("Movie: {}", err);
+                    return Err("Couldn't create movie file.");
+                }
+                Ok(recorder) => self.movie_recorder = Some(recorder),
+            }
+            trace!("Movie recording.");
+        }
+
+        if let Some(settings) = self.config.netplay() {
+            match Netplay::open(settings) {
+                Err(err) => {
+                    error!("Netplay: {}", err);
+                    return Err("Couldn't open the netplay socket.");
+                }
+                Ok(netplay) => {
+                    self.linked = Some(LinkedConsoles::new(netplay.player(), &self.lynx));
+                    self.debugger.set_comlynx_linked();
+                    self.netplay = Some(netplay);
+                }
+            }
+        }
+
+        if let Some(addr) = self.config.spectator_listen() {
+            match SpectatorServer::listen(addr) {
+                Err(err) => {
+                    error!("Spectators: {}", err);
+                    return Err("Couldn't open the spectator socket.");
+                }
+                Ok(server) => self.spectators = Some(server),
+            }
+        }
+
         self.rotation_tx.send(self.lynx.rotation()).unwrap();
 
         Ok(())
@@ -126,9 +431,13 @@ impl RunnerThread for PerFrameRunnerThread {
                 return;
             }
 
-            while !self.lynx.redraw_requested() {
-                self.lynx.tick();
-                self.sound();
+            self.debugger.update(&mut self.lynx, self.frame);
+
+            self.run_frame();
+
+            if self.debugger.paused() {
+                self.debugger.wait(&mut self.lynx);
+                continue;
             }
 
             if !self.sound_sample.is_empty() {
@@ -144,8 +453,85 @@ impl RunnerThread for PerFrameRunnerThread {
             } 
             self.display();
 
+            if self.config.headless() {
+                continue;
+            }
+
             while self.next_lcd_refresh > Instant::now() {}
             self.next_lcd_refresh = Instant::now() + self.frame_time;
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::runner::{debugger::DebugEvent, netplay::tests::{deliver, hash_packet, inputs_packet, open}};
+
+    use super::*;
+
+    /// Runner without a game, with the UI ends of its input and debugger event channels.
+    fn runner() -> (PerFrameRunnerThread, kanal::Sender<(u8, u8)>, kanal::Receiver<DebugEvent>) {
+        let (input_tx, input_rx) = kanal::unbounded();
+        let (update_display_tx, _) = kanal::unbounded();
+        let (rotation_tx, _) = kanal::unbounded();
+        let (_, command_rx) = kanal::unbounded();
+        let (event_tx, event_rx) = kanal::unbounded();
+        let debugger = Debugger::new(command_rx, event_tx);
+        (PerFrameRunnerThread::new(RunnerConfig::new(), input_rx, update_display_tx, rotation_tx, debugger), input_tx, event_rx)
+    }
+
+    fn start(runner: &mut PerFrameRunnerThread, netplay: Netplay) {
+        runner.linked = Some(LinkedConsoles::new(netplay.player(), &runner.lynx));
+        runner.netplay = Some(netplay);
+    }
+
+    #[test]
+    fn desync_stops_the_session() {
+        let (mut runner, _input_tx, events) = runner();
+        let (mut netplay, peer) = open(1, 0, 0);
+        netplay.frame_hash(0, 1);
+        deliver(&mut netplay, &peer, &hash_packet(0, 2));
+        deliver(&mut netplay, &peer, &inputs_packet(1, &[(0, 0), (0, 0)]));
+        start(&mut runner, netplay);
+        runner.frame = 1;
+
+        assert!(!runner.netplay_inputs());
+        assert!(runner.netplay.is_none() && runner.linked.is_none());
+        assert!(runner.debugger.paused());
+        let halted = match events.try_recv() {
+            Ok(Some(DebugEvent::Halted(message))) => message,
+            _ => panic!("no halt event"),
+        };
+        assert_eq!(halted, "Netplay out of sync with the peer at frame 0. Session stopped, game paused.");
+    }
+
+    fn snapshot_frames(runner: &PerFrameRunnerThread) -> Vec<u64> {
+        runner.snapshots.iter().map(|(frame, _, _)| *frame).collect()
+    }
+
+    #[test]
+    fn keeps_the_rollback_window_snapshots() {
+        let (mut runner, _input_tx, _events) = runner();
+        let (netplay, peer) = open(1, 0, 2);
+        start(&mut runner, netplay);
+
+        // Frames 0 and 1 run ahead of the peer, resuming from a debugger pause doesn't save the start again.
+        assert!(!runner.netplay_inputs());
+        assert!(!runner.netplay_inputs());
+        assert_eq!(snapshot_frames(&runner), [0]);
+        runner.frame = 1;
+        assert!(!runner.netplay_inputs());
+        assert_eq!(snapshot_frames(&runner), [0, 1]);
+        assert!(!runner.netplay.as_ref().unwrap().ready(2));
+
+        // Each confirmed frame lets one more run, the oldest start is dropped past `rollback() + 1`.
+        for frame in 2..10u64 {
+            deliver(runner.netplay.as_mut().unwrap(), &peer, &inputs_packet(frame - 2, &[(0, 0)]));
+            assert!(!runner.netplay.as_ref().unwrap().ready(frame + 1));
+            runner.frame = frame;
+            assert!(!runner.netplay_inputs());
+            let first = frame.saturating_sub(2);
+            assert_eq!(snapshot_frames(&runner), (first..=frame).collect::<Vec<u64>>());
+        }
+        assert!(runner.netplay.is_some());
+    }
+}
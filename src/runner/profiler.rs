@@ -0,0 +1,192 @@
+use std::{collections::{HashMap, VecDeque}, fmt::Write as _, path::PathBuf, sync::Arc};
+use log::error;
+
+use crate::symbols::Symbols;
+
+/// Frames kept for the budget history.
+const BUDGET_FRAMES: usize = 256;
+/// Routines listed in the panel report.
+const PANEL_ROWS: usize = 32;
+/// Routines listed in the exit-time report.
+const FILE_ROWS: usize = 200;
+
+/// Ticks spent in a routine, or at an address without symbols.
+#[derive(Clone)]
+pub(crate) struct ProfileRow {
+    pub address: u16,
+    pub name: Option<String>,
+    pub ticks: u64,
+}
+
+#[derive(Clone)]
+pub(crate) struct ProfileReport {
+    pub rows: Vec<ProfileRow>,
+    pub total_ticks: u64,
+    pub frames: u64,
+    /// Last frames `(ticks, busy ticks)`, oldest first. Busy excludes the idle ranges.
+    pub budget: Vec<(u64, u64)>,
+    pub idle_ranges: bool,
+}
+
+/// Crystal ticks counted per instruction address while the game runs, including the ticks
+/// the CPU is held by the hardware.
+pub(crate) struct Profiler {
+    /// Per address, allocated on the first tick.
+    ticks: Vec<u64>,
+    pc: u16,
+    idle: bool,
+    idle_ranges: Vec<(u16, u16)>,
+    frame_ticks: u64,
+    frame_busy: u64,
+    frames: u64,
+    budget: VecDeque<(u64, u64)>,
+    symbols: Option<Arc<Symbols>>,
+    report: Option<PathBuf>,
+}
+
+impl Profiler {
+    pub(crate) fn new(idle_ranges: Vec<(u16, u16)>, symbols: Option<Arc<Symbols>>, report: Option<PathBuf>) -> Self {
+        Self {
+            ticks: vec![],
+            pc: 0,
+            idle: false,
+            idle_ranges,
+            frame_ticks: 0,
+            frame_busy: 0,
+            frames: 0,
+            budget: VecDeque::with_capacity(BUDGET_FRAMES),
+            symbols,
+            report,
+        }
+    }
+
+    /// Profiling from power-on, with a report written on exit.
+    pub(crate) fn always_on(&self) -> bool {
+        self.report.is_some()
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.ticks.iter_mut().for_each(|t| *t = 0);
+        self.frame_ticks = 0;
+        self.frame_busy = 0;
+        self.frames = 0;
+        self.budget.clear();
+    }
+
+    pub(crate) fn instruction(&mut self, pc: u16) {
+        self.pc = pc;
+        self.idle = self.idle_ranges.iter().any(|(start, end)| (*start..=*end).contains(&pc));
+    }
+
+    pub(crate) fn tick(&mut self) {
+        if self.ticks.is_empty() {
+            self.ticks = vec![0; 0x10000];
+        }
+        self.ticks[self.pc as usize] += 1;
+        self.frame_ticks += 1;
+        if !self.idle {
+            self.frame_busy += 1;
+        }
+    }
+
+    pub(crate) fn frame_done(&mut self) {
+        if self.budget.len() == BUDGET_FRAMES {
+            self.budget.pop_front();
+        }
+        self.budget.push_back((self.frame_ticks, self.frame_busy));
+        self.frame_ticks = 0;
+        self.frame_busy = 0;
+        self.frames += 1;
+    }
+
+    /// Hottest routines first, grouped by symbol when symbols are loaded.
+    fn report(&self, max_rows: usize) -> ProfileReport {
+        let mut grouped: HashMap<u16, (Option<&str>, u64)> = HashMap::new();
+        for (pc, ticks) in self.ticks.iter().enumerate().filter(|(_, ticks)| **ticks > 0) {
+            let pc = pc as u16;
+            let (address, name) = match self.symbols.as_ref().and_then(|symbols| symbols.routine(pc)) {
+                Some((address, name)) => (address, Some(name)),
+                None => (pc, None),
+            };
+            grouped.entry(address).or_insert((name, 0)).1 += ticks;
+        }
+
+        let mut rows: Vec<ProfileRow> = grouped.into_iter()
+            .map(|(address, (name, ticks))| ProfileRow { address, name: name.map(str::to_string), ticks })
+            .collect();
+        rows.sort_by(|a, b| b.ticks.cmp(&a.ticks).then(a.address.cmp(&b.address)));
+        rows.truncate(max_rows);
+
+        ProfileReport {
+            rows,
+            total_ticks: self.ticks.iter().sum(),
+            frames: self.frames,
+            budget: self.budget.iter().copied().collect(),
+            idle_ranges: !self.idle_ranges.is_empty(),
+        }
+    }
+
+    pub(crate) fn panel_report(&self) -> ProfileReport {
+        self.report(PANEL_ROWS)
+    }
+
+    fn write_report(&self) {
+        let Some(path) = &self.report else {
+            return;
+        };
+        let report = self.report(FILE_ROWS);
+        let mut text = String::new();
+        let _ = writeln!(text, "frames: {}", report.frames);
+        let _ = writeln!(text, "ticks: {}", report.total_ticks);
+        if let Some((average, peak)) = report.budget_usage() {
+            let _ = writeln!(text, "frame budget used: {:.1}% average, {:.1}% peak (last {} frames)", average * 100., peak * 100., report.budget.len());
+        }
+        let _ = writeln!(text);
+        let _ = writeln!(text, "{:>12} {:>6}  address  routine", "ticks", "%");
+        for row in &report.rows {
+            let _ = writeln!(
+                text,
+                "{:>12} {:>6.2}  ${:04X}    {}",
+                row.ticks,
+                report.percent(row.ticks),
+                row.address,
+                row.name.as_deref().unwrap_or_default(),
+            );
+        }
+        if let Err(err) = std::fs::write(path, text) {
+            error!("Profile report {}: {}", path.display(), err);
+        }
+    }
+}
+
+impl ProfileReport {
+    pub(crate) fn percent(&self, ticks: u64) -> f64 {
+        match self.total_ticks {
+            0 => 0.,
+            total => ticks as f64 * 100. / total as f64,
+        }
+    }
+
+    /// Average and peak busy ratio over the last frames, `None` without idle ranges.
+    pub(crate) fn budget_usage(&self) -> Option<(f64, f64)> {
+        if !self.idle_ranges {
+            return None;
+        }
+        let ratios: Vec<f64> = self.budget.iter()
+            .filter(|(ticks, _)| *ticks > 0)
+            .map(|(ticks, busy)| *busy as f64 / *ticks as f64)
+            .collect();
+        if ratios.is_empty() {
+            return None;
+        }
+        let average = ratios.iter().sum::<f64>() / ratios.len() as f64;
+        let peak = ratios.iter().copied().fold(0., f64::max);
+        Some((average, peak))
+    }
+}
+
+impl Drop for Profiler {
+    fn drop(&mut self) {
+        self.write_report();
+    }
+}
@@ -1,7 +1,12 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{collections::HashMap, path::PathBuf, str::FromStr, sync::Arc};
 
+use holani::suzy::registers::{Joystick, Switches};
 use ratatui::crossterm::event::KeyCode;
 
+use crate::symbols::Symbols;
+
+use super::{comlynx_link::ComlynxEndpoint, comlynx_sniffer::ComlynxLog, netplay::NetplaySettings};
+
 #[derive(Clone, PartialEq, Eq, Hash, Copy)]
 pub(crate) enum Input {
     Up,
@@ -15,13 +20,70 @@ pub(crate) enum Input {
     Pause,
 }
 
+impl Input {
+    pub(crate) fn apply(&self, joystick: &mut Joystick, switches: &mut Switches, value: bool) {
+        match self {
+            Input::Pause => switches.set(Switches::pause, value),
+            Input::Up => joystick.set(Joystick::up, value),
+            Input::Down => joystick.set(Joystick::down, value),
+            Input::Left => joystick.set(Joystick::left, value),
+            Input::Right => joystick.set(Joystick::right, value),
+            Input::Outside => joystick.set(Joystick::outside, value),
+            Input::Inside => joystick.set(Joystick::inside, value),
+            Input::Option1 => joystick.set(Joystick::option_1, value),
+            Input::Option2 => joystick.set(Joystick::option_2, value),
+        }
+    }
+}
+
+impl FromStr for Input {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "up" => Ok(Input::Up),
+            "down" => Ok(Input::Down),
+            "left" => Ok(Input::Left),
+            "right" => Ok(Input::Right),
+            "outside" | "a" => Ok(Input::Outside),
+            "inside" | "b" => Ok(Input::Inside),
+            "option1" | "o1" => Ok(Input::Option1),
+            "option2" | "o2" => Ok(Input::Option2),
+            "pause" => Ok(Input::Pause),
+            other => Err(format!("Unknown input '{}'.", other)),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct RunnerConfig {
     rom: Option<PathBuf>,
     cartridge: Option<PathBuf>,
+    /// Image to load when the cartridge is an archive of several.
+    cartridge_member: Option<String>,
     button_mapping: HashMap<KeyCode, Input>,
     mute: bool,
     comlynx: bool,
+    comlynx_link: Option<ComlynxEndpoint>,
+    player_mappings: Vec<HashMap<KeyCode, Input>>,
+    instance: usize,
+    comlynx_log: Option<ComlynxLog>,
+    netplay: Option<NetplaySettings>,
+    spectator_listen: Option<String>,
+    headless: bool,
+    input_script: Option<PathBuf>,
+    record_movie: Option<PathBuf>,
+    play_movie: Option<PathBuf>,
+    autofire: HashMap<Input, u8>,
+    autofire_enabled: bool,
+    symbols: Option<Arc<Symbols>>,
+    gdb_port: Option<u16>,
+    trace: Option<PathBuf>,
+    trace_ranges: Vec<(u16, u16)>,
+    trace_ring: Option<usize>,
+    cheats: Option<PathBuf>,
+    profile: Option<PathBuf>,
+    profile_idle: Vec<(u16, u16)>,
 }
 
 impl RunnerConfig {
@@ -29,8 +91,29 @@ impl RunnerConfig {
         Self {
             rom: None,
             cartridge: None,
+            cartridge_member: None,
             mute: false,
             comlynx: false,
+            comlynx_link: None,
+            player_mappings: vec![],
+            instance: 0,
+            comlynx_log: None,
+            netplay: None,
+            spectator_listen: None,
+            headless: false,
+            input_script: None,
+            record_movie: None,
+            play_movie: None,
+            autofire: HashMap::new(),
+            autofire_enabled: false,
+            symbols: None,
+            gdb_port: None,
+            trace: None,
+            trace_ranges: vec![],
+            trace_ring: None,
+            cheats: None,
+            profile: None,
+            profile_idle: vec![],
             button_mapping: HashMap::new()
         }
     }
@@ -49,6 +132,15 @@ impl RunnerConfig {
 
     pub(crate) fn set_cartridge(&mut self, cartridge: PathBuf) {
         self.cartridge = Some(cartridge);
+        self.cartridge_member = None;
+    }
+
+    pub(crate) fn cartridge_member(&self) -> &Option<String> {
+        &self.cartridge_member
+    }
+
+    pub(crate) fn set_cartridge_member(&mut self, member: String) {
+        self.cartridge_member = Some(member);
     }
 
     pub(crate) fn button_mapping(&self) -> &HashMap<KeyCode, Input> {
@@ -78,4 +170,190 @@ impl RunnerConfig {
     pub(crate) fn set_comlynx(&mut self, comlynx: bool) {
         self.comlynx = comlynx;
     }
+
+    pub(crate) fn comlynx_link(&self) -> &Option<ComlynxEndpoint> {
+        &self.comlynx_link
+    }
+
+    pub(crate) fn set_comlynx_link(&mut self, endpoint: ComlynxEndpoint) {
+        self.comlynx_link = Some(endpoint);
+    }
+
+    /// Instances run in this process, linked by Comlynx.
+    pub(crate) fn players(&self) -> usize {
+        1 + self.player_mappings.len()
+    }
+
+    /// Adds a player, after the first one using `button_mapping`.
+    pub(crate) fn add_player(&mut self, mapping: HashMap<KeyCode, Input>) {
+        self.player_mappings.push(mapping);
+    }
+
+    /// Configuration of the `player`th instance. The first one keeps the sound, debugging and recording outputs,
+    /// and the scripted inputs.
+    pub(crate) fn player_config(&self, player: usize, link: Option<ComlynxEndpoint>) -> RunnerConfig {
+        let mut config = self.clone();
+        config.player_mappings.clear();
+        config.instance = player;
+        if let Some(link) = link {
+            config.comlynx = true;
+            config.comlynx_link = Some(link);
+        }
+        if player > 0 {
+            config.button_mapping = self.player_mappings[player - 1].clone();
+            config.mute = true;
+            config.gdb_port = None;
+            config.trace = None;
+            config.profile = None;
+            config.record_movie = None;
+            config.input_script = None;
+            config.play_movie = None;
+            config.cheats = None;
+            config.spectator_listen = None;
+        }
+        config
+    }
+
+    /// Player index, 0 unless several instances run in this process.
+    pub(crate) fn instance(&self) -> usize {
+        self.instance
+    }
+
+    pub(crate) fn comlynx_log(&self) -> &Option<ComlynxLog> {
+        &self.comlynx_log
+    }
+
+    pub(crate) fn set_comlynx_log(&mut self, log: ComlynxLog) {
+        self.comlynx_log = Some(log);
+    }
+
+    pub(crate) fn netplay(&self) -> &Option<NetplaySettings> {
+        &self.netplay
+    }
+
+    pub(crate) fn set_netplay(&mut self, netplay: NetplaySettings) {
+        self.netplay = Some(netplay);
+    }
+
+    pub(crate) fn spectator_listen(&self) -> &Option<String> {
+        &self.spectator_listen
+    }
+
+    pub(crate) fn set_spectator_listen(&mut self, addr: String) {
+        self.spectator_listen = Some(addr);
+    }
+
+    pub(crate) fn headless(&self) -> bool {
+        self.headless
+    }
+    
+    pub(crate) fn set_headless(&mut self, headless: bool) {
+        self.headless = headless;
+    }
+
+    pub(crate) fn input_script(&self) -> &Option<PathBuf> {
+        &self.input_script
+    }
+
+    pub(crate) fn set_input_script(&mut self, input_script: PathBuf) {
+        self.input_script = Some(input_script);
+    }
+
+    pub(crate) fn record_movie(&self) -> &Option<PathBuf> {
+        &self.record_movie
+    }
+
+    pub(crate) fn set_record_movie(&mut self, record_movie: PathBuf) {
+        self.record_movie = Some(record_movie);
+    }
+
+    pub(crate) fn play_movie(&self) -> &Option<PathBuf> {
+        &self.play_movie
+    }
+
+    pub(crate) fn set_play_movie(&mut self, play_movie: PathBuf) {
+        self.play_movie = Some(play_movie);
+    }
+
+    pub(crate) fn autofire(&self) -> &HashMap<Input, u8> {
+        &self.autofire
+    }
+
+    pub(crate) fn set_autofire(&mut self, btn: Input, rate: u8) {
+        self.autofire.insert(btn, rate.max(1));
+    }
+
+    pub(crate) fn autofire_enabled(&self) -> bool {
+        self.autofire_enabled
+    }
+
+    pub(crate) fn set_autofire_enabled(&mut self, autofire_enabled: bool) {
+        self.autofire_enabled = autofire_enabled;
+    }
+
+    pub(crate) fn symbols(&self) -> &Option<Arc<Symbols>> {
+        &self.symbols
+    }
+
+    pub(crate) fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = Some(Arc::new(symbols));
+    }
+
+    pub(crate) fn gdb_port(&self) -> Option<u16> {
+        self.gdb_port
+    }
+
+    pub(crate) fn set_gdb_port(&mut self, gdb_port: u16) {
+        self.gdb_port = Some(gdb_port);
+    }
+
+    pub(crate) fn trace(&self) -> &Option<PathBuf> {
+        &self.trace
+    }
+
+    pub(crate) fn set_trace(&mut self, trace: PathBuf) {
+        self.trace = Some(trace);
+    }
+
+    pub(crate) fn trace_ranges(&self) -> &Vec<(u16, u16)> {
+        &self.trace_ranges
+    }
+
+    /// Only instructions with PC in one of the inclusive ranges are traced, all if none.
+    pub(crate) fn add_trace_range(&mut self, start: u16, end: u16) {
+        self.trace_ranges.push((start.min(end), start.max(end)));
+    }
+
+    pub(crate) fn trace_ring(&self) -> Option<usize> {
+        self.trace_ring
+    }
+
+    pub(crate) fn set_trace_ring(&mut self, size: usize) {
+        self.trace_ring = Some(size);
+    }
+
+    pub(crate) fn cheats(&self) -> &Option<PathBuf> {
+        &self.cheats
+    }
+
+    pub(crate) fn set_cheats(&mut self, cheats: PathBuf) {
+        self.cheats = Some(cheats);
+    }
+
+    pub(crate) fn profile(&self) -> &Option<PathBuf> {
+        &self.profile
+    }
+
+    pub(crate) fn set_profile(&mut self, profile: PathBuf) {
+        self.profile = Some(profile);
+    }
+
+    pub(crate) fn profile_idle(&self) -> &Vec<(u16, u16)> {
+        &self.profile_idle
+    }
+
+    /// Ticks spent with PC in the inclusive range don't count in the frame budget.
+    pub(crate) fn add_profile_idle(&mut self, start: u16, end: u16) {
+        self.profile_idle.push((start.min(end), start.max(end)));
+    }
 }
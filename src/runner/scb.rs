@@ -0,0 +1,291 @@
+use holani::lynx::Lynx;
+
+use crate::registers::{sprite_bpp, HOFF, SCBNEXT, SPRHSIZ, SPRVSIZ, VOFF};
+
+use super::lynx_ext::LynxExt;
+
+/// Guards against SCB chains looping on themselves and unterminated sprite data.
+const MAX_CHAIN_LEN: usize = 256;
+const MAX_LINES: u32 = 1024;
+const PALETTE_LEN: usize = 8;
+
+const SPRCTL0_HFLIP: u8 = 0x20;
+const SPRCTL0_VFLIP: u8 = 0x10;
+const SPRCTL1_LITERAL: u8 = 0x80;
+const SPRCTL1_RELOAD_PALETTE_OFF: u8 = 0x08;
+const SPRCTL1_SKIP: u8 = 0x04;
+const SPRCTL1_START_UP: u8 = 0x02;
+const SPRCTL1_START_LEFT: u8 = 0x01;
+
+/// Screen area covered by a sprite, in screen pixels.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct SpriteBounds {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+/// A sprite control block as Suzy reads it.
+#[derive(Clone)]
+pub(crate) struct Scb {
+    pub address: u16,
+    pub sprctl0: u8,
+    pub sprctl1: u8,
+    pub sprcoll: u8,
+    pub next: u16,
+    pub data: u16,
+    pub hpos: i16,
+    pub vpos: i16,
+    /// 8.8 fixed point, `None` when kept from the previous sprite.
+    pub hsize: Option<u16>,
+    pub vsize: Option<u16>,
+    pub stretch: Option<u16>,
+    pub tilt: Option<u16>,
+    /// Pen index map, `None` when kept from the previous sprite.
+    pub palette: Option<[u8; PALETTE_LEN]>,
+    /// `None` for skipped and empty sprites.
+    pub bounds: Option<SpriteBounds>,
+}
+
+impl Scb {
+    pub(crate) fn skipped(&self) -> bool {
+        self.sprctl1 & SPRCTL1_SKIP != 0
+    }
+
+    fn read(lynx: &Lynx, address: u16, scale: &mut (u16, u16)) -> Self {
+        let mut offset = 0u16;
+        let mut byte = || {
+            let b = lynx.peek(address.wrapping_add(offset));
+            offset += 1;
+            b
+        };
+        let sprctl0 = byte();
+        let sprctl1 = byte();
+        let sprcoll = byte();
+        let mut word = || u16::from_le_bytes([byte(), byte()]);
+        let next = word();
+        let data = word();
+        let hpos = word() as i16;
+        let vpos = word() as i16;
+
+        let reload = (sprctl1 >> 4) & 0x03;
+        let hsize = (reload >= 1).then(&mut word);
+        let vsize = (reload >= 1).then(&mut word);
+        let stretch = (reload >= 2).then(&mut word);
+        let tilt = (reload >= 3).then(&mut word);
+        let palette = (sprctl1 & SPRCTL1_RELOAD_PALETTE_OFF == 0).then(|| {
+            let mut palette = [0; PALETTE_LEN];
+            palette.iter_mut().for_each(|p| *p = byte());
+            palette
+        });
+
+        if let (Some(h), Some(v)) = (hsize, vsize) {
+            *scale = (h, v);
+        }
+
+        Self { address, sprctl0, sprctl1, sprcoll, next, data, hpos, vpos, hsize, vsize, stretch, tilt, palette, bounds: None }
+    }
+
+    /// Decodes the sprite data line headers and packets to measure each quadrant,
+    /// stretch and tilt are ignored.
+    fn measure<F: Fn(u16) -> u8>(&self, peek: F, (hsize, vsize): (u16, u16), (hoff, voff): (i16, i16)) -> Option<SpriteBounds> {
+        let bpp = sprite_bpp(self.sprctl0) as u32;
+        let literal = self.sprctl1 & SPRCTL1_LITERAL != 0;
+        let hflip = self.sprctl0 & SPRCTL0_HFLIP != 0;
+        let vflip = self.sprctl0 & SPRCTL0_VFLIP != 0;
+
+        let start = start_quadrant(self.sprctl1);
+        let mut quadrant = start;
+
+        let x = self.hpos as i32 - hoff as i32;
+        let y = self.vpos as i32 - voff as i32;
+        let mut bounds: Option<SpriteBounds> = None;
+        let mut addr = self.data;
+        let (mut lines, mut width, mut total_lines) = (0u32, 0u32, 0u32);
+        while total_lines < MAX_LINES {
+            total_lines += 1;
+            let offset = peek(addr);
+            if offset <= 1 {
+                if lines > 0 && width > 0 {
+                    let w = (width * hsize as u32 / 256) as i32;
+                    let h = (lines * vsize as u32 / 256) as i32;
+                    let left_dir = matches!(quadrant, 2 | 3) != hflip;
+                    let up_dir = matches!(quadrant, 1 | 2) != vflip;
+                    let (left, right) = if left_dir { (x - w + 1, x) } else { (x, x + w - 1) };
+                    let (top, bottom) = if up_dir { (y - h + 1, y) } else { (y, y + h - 1) };
+                    bounds = Some(match bounds {
+                        None => SpriteBounds { left, top, right, bottom },
+                        Some(b) => SpriteBounds {
+                            left: b.left.min(left),
+                            top: b.top.min(top),
+                            right: b.right.max(right),
+                            bottom: b.bottom.max(bottom),
+                        },
+                    });
+                }
+                quadrant = (quadrant + 1) % 4;
+                if offset == 0 || quadrant == start {
+                    break;
+                }
+                addr = addr.wrapping_add(1);
+                lines = 0;
+                width = 0;
+                continue;
+            }
+
+            let line: Vec<u8> = (1..offset as u16).map(|i| peek(addr.wrapping_add(i))).collect();
+            width = width.max(line_width(&line, bpp, literal));
+            lines += 1;
+            addr = addr.wrapping_add(offset as u16);
+        }
+        bounds
+    }
+}
+
+/// Quadrants in drawing order: 0 down right, 1 up right, 2 up left, 3 down left.
+fn start_quadrant(sprctl1: u8) -> u8 {
+    match (sprctl1 & SPRCTL1_START_LEFT != 0, sprctl1 & SPRCTL1_START_UP != 0) {
+        (false, false) => 0,
+        (false, true) => 1,
+        (true, true) => 2,
+        (true, false) => 3,
+    }
+}
+
+/// Pixels in a line of sprite data.
+fn line_width(line: &[u8], bpp: u32, literal: bool) -> u32 {
+    let total_bits = line.len() as u32 * 8;
+    if literal {
+        return total_bits / bpp;
+    }
+
+    let mut bit = 0u32;
+    let mut read = |n: u32| -> Option<u32> {
+        if bit + n > total_bits {
+            return None;
+        }
+        let mut value = 0;
+        for _ in 0..n {
+            let b = (line[(bit / 8) as usize] >> (7 - bit % 8)) & 1;
+            value = (value << 1) | b as u32;
+            bit += 1;
+        }
+        Some(value)
+    };
+
+    let mut width = 0;
+    while let (Some(literal), Some(count)) = (read(1), read(4)) {
+        if literal == 0 && count == 0 {
+            break;
+        }
+        let pixels = count + 1;
+        let data_bits = if literal == 1 { pixels * bpp } else { bpp };
+        if read(data_bits).is_none() {
+            break;
+        }
+        width += pixels;
+    }
+    width
+}
+
+/// Walks the SCB chain starting at the current `SCBNEXT`, as `SPRGO` is written.
+pub(crate) fn walk_chain(lynx: &Lynx) -> Vec<Scb> {
+    let offsets = (lynx.peek_word(HOFF) as i16, lynx.peek_word(VOFF) as i16);
+    let mut scale = (lynx.peek_word(SPRHSIZ), lynx.peek_word(SPRVSIZ));
+    let mut chain = vec![];
+    let mut address = lynx.peek_word(SCBNEXT);
+    while address & 0xff00 != 0 && chain.len() < MAX_CHAIN_LEN {
+        let mut scb = Scb::read(lynx, address, &mut scale);
+        if !scb.skipped() {
+            scb.bounds = scb.measure(|addr| lynx.peek(addr), scale, offsets);
+        }
+        address = scb.next;
+        chain.push(scb);
+    }
+    chain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Packs `(value, bits)` fields most significant bit first.
+    fn pack(fields: &[(u32, u32)]) -> Vec<u8> {
+        let mut bytes = vec![];
+        let mut bit = 0;
+        for (value, bits) in fields {
+            for i in (0..*bits).rev() {
+                if bit % 8 == 0 {
+                    bytes.push(0);
+                }
+                *bytes.last_mut().unwrap() |= (((value >> i) & 1) as u8) << (7 - bit % 8);
+                bit += 1;
+            }
+        }
+        bytes
+    }
+
+    fn scb(sprctl0: u8, sprctl1: u8) -> Scb {
+        Scb {
+            address: 0x200, sprctl0, sprctl1, sprcoll: 0, next: 0, data: 0x1000, hpos: 50, vpos: 40,
+            hsize: None, vsize: None, stretch: None, tilt: None, palette: None, bounds: None,
+        }
+    }
+
+    fn measure(scb: &Scb, data: &[u8], scale: (u16, u16)) -> (i32, i32, i32, i32) {
+        let peek = |addr: u16| data.get(addr.wrapping_sub(0x1000) as usize).copied().unwrap_or(0);
+        let b = scb.measure(peek, scale, (0, 0)).unwrap();
+        (b.left, b.top, b.right, b.bottom)
+    }
+
+    #[test]
+    fn measures_literal_lines() {
+        for (bpp, width) in [(1, 24), (2, 12), (3, 8), (4, 6)] {
+            assert_eq!(line_width(&[0xff; 3], bpp, true), width, "{} bpp", bpp);
+        }
+    }
+
+    #[test]
+    fn measures_packed_lines() {
+        for bpp in 1..=4 {
+            // 3 literal pixels, 5 repeated pixels, end of line.
+            let line = pack(&[(1, 1), (2, 4), (0, 3 * bpp), (0, 1), (4, 4), (0, bpp), (0, 5)]);
+            assert_eq!(line_width(&line, bpp, false), 8, "{} bpp", bpp);
+            // A packet cut short by the line end isn't drawn.
+            let line = pack(&[(1, 1), (2, 4), (0, 3 * bpp), (1, 1), (15, 4)]);
+            assert_eq!(line_width(&line, bpp, false), 3, "{} bpp", bpp);
+        }
+        // Line without an end of line packet.
+        assert_eq!(line_width(&pack(&[(0, 1), (15, 4), (0, 3)]), 3, false), 16);
+    }
+
+    #[test]
+    fn measures_quadrants() {
+        // 1 bpp literal: 2 lines of 8 pixels down right, 3 up right, 1 up left.
+        let data = [2, 0xff, 2, 0xff, 1, 2, 0xff, 2, 0xff, 2, 0xff, 1, 2, 0xff, 0];
+        let literal = scb(0, SPRCTL1_LITERAL);
+        assert_eq!(measure(&literal, &data, (0x100, 0x100)), (43, 38, 57, 41));
+        assert_eq!(measure(&literal, &data, (0x200, 0x100)), (35, 38, 65, 41));
+        assert_eq!(measure(&literal, &data, (0x100, 0x300)), (43, 32, 57, 45));
+
+        // A quadrant change back to the start quadrant ends the sprite.
+        let data = [2, 0xff, 1, 1, 1, 1, 2, 0xff, 2, 0xff, 0];
+        assert_eq!(measure(&literal, &data, (0x100, 0x100)), (50, 40, 57, 40));
+    }
+
+    #[test]
+    fn measures_flips_and_start_quadrant() {
+        let data = [2, 0xff, 2, 0xff, 0];
+        assert_eq!(measure(&scb(0, SPRCTL1_LITERAL), &data, (0x100, 0x100)), (50, 40, 57, 41));
+        assert_eq!(measure(&scb(SPRCTL0_HFLIP, SPRCTL1_LITERAL), &data, (0x100, 0x100)), (43, 40, 50, 41));
+        assert_eq!(measure(&scb(SPRCTL0_VFLIP, SPRCTL1_LITERAL), &data, (0x100, 0x100)), (50, 39, 57, 40));
+        assert_eq!(measure(&scb(SPRCTL0_HFLIP | SPRCTL0_VFLIP, SPRCTL1_LITERAL), &data, (0x100, 0x100)), (43, 39, 50, 40));
+        let start = SPRCTL1_LITERAL | SPRCTL1_START_LEFT | SPRCTL1_START_UP;
+        assert_eq!(measure(&scb(0, start), &data, (0x100, 0x100)), (43, 39, 50, 40));
+        assert_eq!(measure(&scb(SPRCTL0_HFLIP, start), &data, (0x100, 0x100)), (50, 39, 57, 40));
+        assert_eq!(measure(&scb(0, SPRCTL1_LITERAL | SPRCTL1_START_LEFT), &data, (0x100, 0x100)), (43, 40, 50, 41));
+        assert_eq!(measure(&scb(0, SPRCTL1_LITERAL | SPRCTL1_START_UP), &data, (0x100, 0x100)), (50, 39, 57, 40));
+        assert!(scb(0, SPRCTL1_LITERAL).measure(|_| 0, (0x100, 0x100), (0, 0)).is_none());
+    }
+}
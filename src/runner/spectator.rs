@@ -0,0 +1,265 @@
+use std::{io::{self, ErrorKind, Read, Write}, iter::repeat_n, net::{TcpListener, TcpStream}, time::Duration};
+use holani::mikey::video::{LYNX_SCREEN_HEIGHT, LYNX_SCREEN_WIDTH};
+use log::{info, trace, warn};
+
+/// Sent first on every connection, protocol version last.
+const MAGIC: &[u8; 8] = b"HLNXSPC1";
+const KEYFRAME: u8 = b'K';
+const DELTA: u8 = b'D';
+const AUDIO: u8 = b'A';
+const SCREEN_LEN: usize = LYNX_SCREEN_WIDTH as usize * LYNX_SCREEN_HEIGHT as usize * 3;
+/// Larger packets are taken as a broken stream.
+const MAX_PACKET_LEN: usize = 2 * SCREEN_LEN;
+/// Frames queued for the broadcast thread, newer ones are dropped when the viewers can't keep up.
+const QUEUE_LEN: usize = 8;
+/// A viewer blocking a write longer is disconnected.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Longest PackBits run and literal.
+const MAX_RUN: usize = 129;
+const MAX_LITERAL: usize = 128;
+
+/// PackBits: a control byte below 0x80 is followed by `control + 1` literal bytes, from 0x80 it's a
+/// run of `control - 0x80 + 2` times the next byte.
+fn pack(data: &[u8]) -> Vec<u8> {
+    let mut packed = vec![];
+    let mut i = 0;
+    while i < data.len() {
+        let run = data[i..].iter().take(MAX_RUN).take_while(|b| **b == data[i]).count();
+        if run >= 2 {
+            packed.push(0x80 | (run - 2) as u8);
+            packed.push(data[i]);
+            i += run;
+            continue;
+        }
+        let start = i;
+        while i < data.len() && i - start < MAX_LITERAL && data.get(i + 1) != Some(&data[i]) {
+            i += 1;
+        }
+        let end = i.max(start + 1);
+        packed.push((end - start - 1) as u8);
+        packed.extend(&data[start..end]);
+        i = end;
+    }
+    packed
+}
+
+fn unpack(packed: &[u8], len: usize) -> Option<Vec<u8>> {
+    let mut data = Vec::with_capacity(len);
+    let mut bytes = packed.iter();
+    while let Some(control) = bytes.next() {
+        match control {
+            0x80.. => data.extend(repeat_n(*bytes.next()?, (control & 0x7f) as usize + 2)),
+            n => {
+                for _ in 0..=*n {
+                    data.push(*bytes.next()?);
+                }
+            }
+        }
+    }
+    (data.len() == len).then_some(data)
+}
+
+fn packet(kind: u8, payload: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(5 + payload.len());
+    packet.push(kind);
+    packet.extend((payload.len() as u32).to_le_bytes());
+    packet.extend(payload);
+    packet
+}
+
+/// Broadcasts the displayed frames and the audio to read-only viewers over TCP.
+///
+/// Frames are sent as the difference with the previous one, XORed then PackBits compressed, mostly
+/// runs of zeros. A viewer joining gets a whole frame first. Audio is the frame's stereo samples at
+/// `SAMPLE_RATE`, 16 bits little endian. Writes happen on a thread of their own, the core never waits
+/// for a viewer.
+pub(crate) struct SpectatorServer {
+    tx: kanal::Sender<(Vec<u8>, Vec<i16>)>,
+    samples: Vec<i16>,
+}
+
+impl SpectatorServer {
+    pub(crate) fn listen(addr: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        info!("Spectators can connect to {}.", listener.local_addr()?);
+
+        let (joined_tx, joined_rx) = kanal::unbounded::<TcpStream>();
+        std::thread::Builder::new()
+            .name("Spectator accept".to_string())
+            .spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let setup = stream.set_nodelay(true).and_then(|_| stream.set_write_timeout(Some(WRITE_TIMEOUT)));
+                    if let Err(err) = setup {
+                        warn!("Spectator: {}", err);
+                        continue;
+                    }
+                    if joined_tx.send(stream).is_err() {
+                        return;
+                    }
+                }
+            })?;
+
+        let (tx, rx) = kanal::bounded::<(Vec<u8>, Vec<i16>)>(QUEUE_LEN);
+        std::thread::Builder::new()
+            .name("Spectator broadcast".to_string())
+            .spawn(move || {
+                let mut viewers: Vec<TcpStream> = vec![];
+                let mut previous = vec![0; SCREEN_LEN];
+                while let Ok((screen, samples)) = rx.recv() {
+                    let delta: Vec<u8> = screen.iter().zip(&previous).map(|(a, b)| a ^ b).collect();
+                    let mut frame = packet(DELTA, &pack(&delta));
+                    frame.extend(packet(AUDIO, &samples.iter().flat_map(|s| s.to_le_bytes()).collect::<Vec<u8>>()));
+                    viewers.retain_mut(|viewer| Self::write(viewer, &frame));
+
+                    while let Ok(Some(mut viewer)) = joined_rx.try_recv() {
+                        let mut first = MAGIC.to_vec();
+                        first.extend(packet(KEYFRAME, &pack(&screen)));
+                        if Self::write(&mut viewer, &first) {
+                            info!("Spectator {} joined.", viewer.peer_addr().map(|a| a.to_string()).unwrap_or_default());
+                            viewers.push(viewer);
+                        }
+                    }
+                    previous = screen;
+                }
+            })?;
+
+        Ok(Self { tx, samples: vec![] })
+    }
+
+    fn write(viewer: &mut TcpStream, data: &[u8]) -> bool {
+        match viewer.write_all(data) {
+            Ok(()) => true,
+            Err(err) => {
+                info!("Spectator {} left: {}", viewer.peer_addr().map(|a| a.to_string()).unwrap_or_default(), err);
+                false
+            }
+        }
+    }
+
+    /// Audio sample, sent with the next frame.
+    pub(crate) fn sample(&mut self, (left, right): (i16, i16)) {
+        self.samples.push(left);
+        self.samples.push(right);
+    }
+
+    pub(crate) fn frame(&mut self, screen: &[u8]) {
+        let samples = std::mem::take(&mut self.samples);
+        if !matches!(self.tx.try_send((screen.to_vec(), samples)), Ok(true)) {
+            trace!("Spectator frame dropped.");
+        }
+    }
+}
+
+/// Frames and audio received from a `SpectatorServer`, decoded on a thread of their own.
+pub(crate) struct SpectatorStream {
+    pub screen_rx: kanal::Receiver<Vec<u8>>,
+    pub audio_rx: kanal::Receiver<Vec<i16>>,
+}
+
+impl SpectatorStream {
+    pub(crate) fn connect(addr: &str) -> io::Result<Self> {
+        let mut stream = TcpStream::connect(addr)?;
+        let mut magic = [0; MAGIC.len()];
+        stream.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(ErrorKind::InvalidData, "not a holani-tui spectator stream"));
+        }
+        info!("Spectating {}.", stream.peer_addr()?);
+
+        let (screen_tx, screen_rx) = kanal::unbounded::<Vec<u8>>();
+        let (audio_tx, audio_rx) = kanal::unbounded::<Vec<i16>>();
+        std::thread::Builder::new()
+            .name("Spectator receive".to_string())
+            .spawn(move || {
+                if let Err(err) = Self::receive(stream, screen_tx, audio_tx) {
+                    warn!("Spectator stream: {}", err);
+                }
+            })?;
+
+        Ok(Self { screen_rx, audio_rx })
+    }
+
+    fn receive(mut stream: TcpStream, screen_tx: kanal::Sender<Vec<u8>>, audio_tx: kanal::Sender<Vec<i16>>) -> io::Result<()> {
+        let invalid = |msg: &str| io::Error::new(ErrorKind::InvalidData, msg.to_string());
+        let mut screen: Option<Vec<u8>> = None;
+        let mut header = [0; 5];
+        loop {
+            stream.read_exact(&mut header)?;
+            let len = u32::from_le_bytes(header[1..].try_into().unwrap()) as usize;
+            if len > MAX_PACKET_LEN {
+                return Err(invalid("packet too large"));
+            }
+            let mut payload = vec![0; len];
+            stream.read_exact(&mut payload)?;
+            match header[0] {
+                KEYFRAME => screen = Some(unpack(&payload, SCREEN_LEN).ok_or_else(|| invalid("bad frame"))?),
+                DELTA => {
+                    let delta = unpack(&payload, SCREEN_LEN).ok_or_else(|| invalid("bad frame"))?;
+                    let previous = screen.as_mut().ok_or_else(|| invalid("frame difference before a whole frame"))?;
+                    previous.iter_mut().zip(delta).for_each(|(pixel, d)| *pixel ^= d);
+                }
+                AUDIO => {
+                    let samples = payload.chunks_exact(2).map(|s| i16::from_le_bytes([s[0], s[1]])).collect();
+                    if audio_tx.send(samples).is_err() {
+                        return Ok(());
+                    }
+                    continue;
+                }
+                _ => return Err(invalid("unknown packet")),
+            }
+            if screen_tx.send(screen.clone().unwrap()).is_err() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(data: &[u8]) -> Vec<u8> {
+        let packed = pack(data);
+        assert_eq!(unpack(&packed, data.len()).as_deref(), Some(data));
+        packed
+    }
+
+    #[test]
+    fn empty() {
+        assert!(round_trip(&[]).is_empty());
+    }
+
+    #[test]
+    fn runs_up_to_the_longest() {
+        assert_eq!(round_trip(&[7; 2]), [0x80, 7]);
+        assert_eq!(round_trip(&[7; MAX_RUN]), [0xff, 7]);
+        assert_eq!(round_trip(&[7; MAX_RUN + 1]), [0xff, 7, 0, 7]);
+        assert_eq!(round_trip(&[7; MAX_RUN + 2]), [0xff, 7, 0x80, 7]);
+    }
+
+    #[test]
+    fn literals_up_to_the_longest() {
+        let data: Vec<u8> = (0..=255).collect();
+        let packed = round_trip(&data[..MAX_LITERAL]);
+        assert_eq!(packed.len(), MAX_LITERAL + 1);
+        assert_eq!(packed[0], 0x7f);
+        let packed = round_trip(&data[..MAX_LITERAL + 1]);
+        assert_eq!(&packed[MAX_LITERAL + 1..], [0, 128]);
+        round_trip(&data);
+    }
+
+    #[test]
+    fn literals_and_runs_mixed() {
+        assert_eq!(round_trip(&[1, 2, 3, 3, 3, 4]), [1, 1, 2, 0x81, 3, 0, 4]);
+        assert_eq!(round_trip(&[5]), [0, 5]);
+        let data: Vec<u8> = (0..SCREEN_LEN).map(|n| (n / 3 % 7) as u8).collect();
+        round_trip(&data);
+    }
+
+    #[test]
+    fn truncated_or_wrong_length() {
+        assert_eq!(unpack(&[0x80], 2), None);
+        assert_eq!(unpack(&[2, 1, 2], 3), None);
+        assert_eq!(unpack(&[0x80, 7], 3), None);
+    }
+}
@@ -0,0 +1,96 @@
+use std::{collections::VecDeque, fs::File, io::{BufWriter, Write}, path::Path, sync::Arc};
+
+use crate::{disasm::{decode, disassemble}, symbols::Symbols};
+
+use super::lynx_ext::CpuRegisters;
+
+/// Executed instructions log, one line per instruction:
+/// `<tick> <pc> <opcode bytes> <disassembly> A X Y S P`, ticks are counted at the 16 MHz crystal frequency.
+///
+/// With a ring size only the last instructions are kept, they are written out when a breakpoint hits
+/// or the core thread panics.
+pub(crate) struct Tracer {
+    writer: BufWriter<File>,
+    ranges: Vec<(u16, u16)>,
+    ring: Option<(usize, VecDeque<String>)>,
+    enabled: bool,
+    symbols: Option<Arc<Symbols>>,
+}
+
+impl Tracer {
+    pub(crate) fn create(path: &Path, ranges: Vec<(u16, u16)>, ring: Option<usize>, symbols: Option<Arc<Symbols>>) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+            ranges,
+            ring: ring.map(|size| (size.max(1), VecDeque::with_capacity(size.max(1)))),
+            enabled: true,
+            symbols,
+        })
+    }
+
+    pub(crate) fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        let state = match self.enabled {
+            true => "resumed",
+            false => "paused",
+        };
+        self.write_line(format!("--- trace {} ---", state));
+    }
+
+    /// `bytes` starts at PC, at least 3 bytes long.
+    pub(crate) fn instruction(&mut self, tick: u64, registers: &CpuRegisters, bytes: &[u8]) {
+        let pc = registers.pc;
+        if !self.enabled || !(self.ranges.is_empty() || self.ranges.iter().any(|(start, end)| (*start..=*end).contains(&pc))) {
+            return;
+        }
+
+        let size = decode(bytes[0]).size() as usize;
+        let hex: Vec<String> = bytes[..size].iter().map(|b| format!("{:02X}", b)).collect();
+        let (text, _) = disassemble(pc, bytes, self.symbols.as_deref());
+        let line = format!(
+            "{:>12} {:04X}  {:<8} {:<24} A:{:02X} X:{:02X} Y:{:02X} S:{:02X} P:{}",
+            tick, pc, hex.join(" "), text, registers.a, registers.x, registers.y, registers.s, registers.flags(),
+        );
+
+        match self.ring.as_mut() {
+            Some((size, ring)) => {
+                if ring.len() == *size {
+                    ring.pop_front();
+                }
+                ring.push_back(line);
+            }
+            None => self.write_line(line),
+        }
+    }
+
+    /// Writes out the ring buffer.
+    pub(crate) fn dump(&mut self, reason: &str) {
+        let Some((_, ring)) = self.ring.as_mut() else {
+            return;
+        };
+        let lines: Vec<String> = ring.drain(..).collect();
+        self.write_line(format!("--- {} ---", reason));
+        for line in lines {
+            self.write_line(line);
+        }
+        self.flush();
+    }
+
+    pub(crate) fn flush(&mut self) {
+        let _ = self.writer.flush();
+    }
+
+    fn write_line(&mut self, line: String) {
+        let _ = writeln!(self.writer, "{}", line);
+    }
+}
+
+impl Drop for Tracer {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.dump("core thread panicked");
+        }
+        self.flush();
+    }
+}
@@ -0,0 +1,106 @@
+use ratatui::{crossterm::event::KeyCode, layout::{Constraint, Layout, Rect}, style::{Color, Modifier, Style}, text::Line, widgets::{Block, Paragraph}, Frame};
+
+use crate::{registers::{sprite_bpp, sprite_type}, runner::scb::{Scb, SpriteBounds}};
+
+const DETAIL_LINES: u16 = 5;
+
+fn fixed(value: Option<u16>) -> String {
+    match value {
+        Some(v) => format!("{:.2}", v as f64 / 256.),
+        None => "-".to_string(),
+    }
+}
+
+fn word(value: Option<u16>) -> String {
+    match value {
+        Some(v) => format!("{:04X}", v),
+        None => "-".to_string(),
+    }
+}
+
+/// SCB chain drawn by Suzy during the last frame.
+///
+/// Keys: `PageUp`/`PageDown` select a sprite, highlighted on the screen.
+pub(crate) struct SpritesView {
+    sprites: Vec<Scb>,
+    selected: usize,
+}
+
+impl SpritesView {
+    pub(crate) fn new() -> Self {
+        Self {
+            sprites: vec![],
+            selected: 0,
+        }
+    }
+
+    pub(crate) fn update(&mut self, sprites: Vec<Scb>) {
+        self.sprites = sprites;
+        self.selected = self.selected.min(self.sprites.len().saturating_sub(1));
+    }
+
+    /// Returns `true` if the key was consumed.
+    pub(crate) fn handle_key(&mut self, code: KeyCode) -> bool {
+        match code {
+            KeyCode::PageUp => self.selected = self.selected.saturating_sub(1),
+            KeyCode::PageDown => self.selected = (self.selected + 1).min(self.sprites.len().saturating_sub(1)),
+            _ => return false,
+        }
+        true
+    }
+
+    pub(crate) fn selected_bounds(&self) -> Option<SpriteBounds> {
+        self.sprites.get(self.selected).and_then(|scb| scb.bounds)
+    }
+
+    pub(crate) fn draw(&self, f: &mut Frame, area: Rect) {
+        let [list, details] = Layout::vertical([Constraint::Fill(1), Constraint::Length(DETAIL_LINES + 2)]).areas(area);
+
+        let height = list.height.saturating_sub(3) as usize;
+        let first = self.selected.saturating_sub(height.saturating_sub(1));
+        let mut lines = vec![Line::styled(" #   SCB  ctl0 ctl1 type          bpp  hpos  vpos hsize vsize data", Style::default().fg(Color::Cyan))];
+        lines.extend(self.sprites.iter().enumerate().skip(first).take(height).map(|(i, scb)| {
+            let text = format!(
+                "{:>3} {:04X} {:02X}   {:02X}   {:<13} {}  {:>5} {:>5} {:>5} {:>5} {:04X}",
+                i,
+                scb.address,
+                scb.sprctl0,
+                scb.sprctl1,
+                match scb.skipped() {
+                    true => "skipped",
+                    false => sprite_type(scb.sprctl0),
+                },
+                sprite_bpp(scb.sprctl0),
+                scb.hpos,
+                scb.vpos,
+                fixed(scb.hsize),
+                fixed(scb.vsize),
+                scb.data,
+            );
+            match i == self.selected {
+                true => Line::styled(text, Style::default().add_modifier(Modifier::REVERSED)),
+                false => Line::raw(text),
+            }
+        }));
+        let title = format!("Sprites - {} SCB", self.sprites.len());
+        f.render_widget(Paragraph::new(lines).block(Block::bordered().title(title)), list);
+
+        let detail = match self.sprites.get(self.selected) {
+            None => vec![Line::raw("No sprite drawn yet.")],
+            Some(scb) => vec![
+                Line::raw(format!("SCB:{:04X} next:{:04X} data:{:04X} SPRCOLL:{:02X}", scb.address, scb.next, scb.data, scb.sprcoll)),
+                Line::raw(format!("pos:{},{} size:{}x{}", scb.hpos, scb.vpos, fixed(scb.hsize), fixed(scb.vsize))),
+                Line::raw(format!("stretch:{} tilt:{}", word(scb.stretch), word(scb.tilt))),
+                Line::raw(format!(
+                    "palette:{}",
+                    scb.palette.map(|p| p.iter().map(|b| format!("{:02X}", b)).collect::<String>()).unwrap_or_else(|| "-".to_string()),
+                )),
+                Line::raw(match scb.bounds {
+                    Some(b) => format!("screen:{},{} to {},{}", b.left, b.top, b.right, b.bottom),
+                    None => "screen: -".to_string(),
+                }),
+            ],
+        };
+        f.render_widget(Paragraph::new(detail).block(Block::bordered().title("Selected sprite")), details);
+    }
+}
@@ -0,0 +1,485 @@
+//! Built-in SSH server, every session runs its own `App` and cores in the client's terminal.
+
+use std::{collections::HashSet, io::{self, Write}, net::SocketAddr, path::{Path, PathBuf}, process::ExitCode, sync::{atomic::{AtomicUsize, Ordering}, Arc, Mutex}, time::Duration};
+use async_trait::async_trait;
+use log::{error, info};
+use ratatui::{backend::{Backend, ClearType, CrosstermBackend, WindowSize}, buffer::Cell, crossterm::{cursor::{Hide, Show}, event::{Event, KeyCode, KeyEvent, KeyModifiers}, execute, terminal::{EnterAlternateScreen, LeaveAlternateScreen}}, layout::{Position, Size}, Terminal};
+use russh::{server::{Auth, Config, Handle, Handler, Msg, Server, Session}, Channel, ChannelId, CryptoVec, MethodSet, Pty};
+use russh_keys::key::{KeyPair, PublicKey};
+
+use crate::{app::App, runner::runner_config::RunnerConfig};
+
+const AUTH_REJECTION_TIME: Duration = Duration::from_secs(1);
+/// Until the client sends its pty size.
+const DEFAULT_SIZE: Size = Size::new(80, 24);
+/// Read when no password file is given.
+const PASSWORD_VAR: &str = "HOLANI_SSH_PASSWORD";
+
+/// Credentials the sessions are let in with, a listed public key or the password.
+pub(crate) struct SshAuth {
+    /// Fingerprints of the authorized keys.
+    keys: HashSet<String>,
+    password: Option<String>,
+}
+
+impl SshAuth {
+    /// Reads the authorized keys and the password file, or `HOLANI_SSH_PASSWORD`. One of them is required.
+    pub(crate) fn load(authorized_keys: &Option<PathBuf>, password_file: &Option<PathBuf>) -> Result<Self, String> {
+        let keys = match authorized_keys {
+            None => HashSet::new(),
+            Some(path) => parse_authorized_keys(&read(path)?).map_err(|e| format!("{}: {}", path.display(), e))?,
+        };
+        let password = match password_file {
+            Some(path) => Some(read(path)?.lines().next().unwrap_or_default().to_string()),
+            None => std::env::var(PASSWORD_VAR).ok(),
+        };
+        let password = password.filter(|password| !password.is_empty());
+        if keys.is_empty() && password.is_none() {
+            return Err(format!("The SSH server needs --ssh-authorized-keys, --ssh-password-file or {}.", PASSWORD_VAR));
+        }
+        Ok(Self { keys, password })
+    }
+
+    fn methods(&self) -> MethodSet {
+        let mut methods = MethodSet::empty();
+        if !self.keys.is_empty() {
+            methods |= MethodSet::PUBLICKEY;
+        }
+        if self.password.is_some() {
+            methods |= MethodSet::PASSWORD;
+        }
+        methods
+    }
+}
+
+fn read(path: &Path) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Fingerprints of the keys of an OpenSSH `authorized_keys` file, `[options] <type> <base64 key> [comment]`
+/// lines. Options are ignored.
+fn parse_authorized_keys(text: &str) -> Result<HashSet<String>, String> {
+    let mut keys = HashSet::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let key = line.split_whitespace()
+            .find_map(|field| russh_keys::parse_public_key_base64(field).ok())
+            .ok_or(format!("line {}: no public key.", n + 1))?;
+        keys.insert(key.fingerprint());
+    }
+    Ok(keys)
+}
+
+/// Session output, sent to the client on flush.
+struct SessionWriter {
+    runtime: tokio::runtime::Handle,
+    handle: Handle,
+    channel: ChannelId,
+    buffer: Vec<u8>,
+}
+
+impl Write for SessionWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            // A closed session ends the app through its events channel.
+            let _ = self.runtime.block_on(self.handle.data(self.channel, CryptoVec::from_slice(&self.buffer)));
+            self.buffer.clear();
+        }
+        Ok(())
+    }
+}
+
+/// Crossterm output into a session, sized by the client's window instead of the local terminal.
+struct SessionBackend {
+    backend: CrosstermBackend<SessionWriter>,
+    size: Arc<Mutex<Size>>,
+}
+
+impl Backend for SessionBackend {
+    fn draw<'a, I>(&mut self, content: I) -> io::Result<()>
+    where
+        I: Iterator<Item = (u16, u16, &'a Cell)>,
+    {
+        self.backend.draw(content)
+    }
+
+    fn hide_cursor(&mut self) -> io::Result<()> {
+        self.backend.hide_cursor()
+    }
+
+    fn show_cursor(&mut self) -> io::Result<()> {
+        self.backend.show_cursor()
+    }
+
+    fn get_cursor_position(&mut self) -> io::Result<Position> {
+        Ok(Position::ORIGIN)
+    }
+
+    fn set_cursor_position<P: Into<Position>>(&mut self, position: P) -> io::Result<()> {
+        self.backend.set_cursor_position(position)
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.backend.clear()
+    }
+
+    fn clear_region(&mut self, clear_type: ClearType) -> io::Result<()> {
+        self.backend.clear_region(clear_type)
+    }
+
+    fn size(&self) -> io::Result<Size> {
+        Ok(*self.size.lock().unwrap())
+    }
+
+    fn window_size(&mut self) -> io::Result<WindowSize> {
+        Ok(WindowSize { columns_rows: self.size()?, pixels: Size::default() })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Backend::flush(&mut self.backend)
+    }
+}
+
+/// Key of a `CSI` or `SS3` escape sequence, `seq` is what follows the introducer.
+fn escape_key(intro: char, seq: &str) -> Option<KeyCode> {
+    let code = match (intro, seq.chars().last()?) {
+        (_, 'A') => KeyCode::Up,
+        (_, 'B') => KeyCode::Down,
+        (_, 'C') => KeyCode::Right,
+        (_, 'D') => KeyCode::Left,
+        (_, 'H') => KeyCode::Home,
+        (_, 'F') => KeyCode::End,
+        ('O', 'P') => KeyCode::F(1),
+        ('O', 'Q') => KeyCode::F(2),
+        ('O', 'R') => KeyCode::F(3),
+        ('O', 'S') => KeyCode::F(4),
+        ('[', '~') => match seq.trim_end_matches('~').split(';').next()?.parse::<u8>().ok()? {
+            1 | 7 => KeyCode::Home,
+            2 => KeyCode::Insert,
+            3 => KeyCode::Delete,
+            4 | 8 => KeyCode::End,
+            5 => KeyCode::PageUp,
+            6 => KeyCode::PageDown,
+            n @ 11..=15 => KeyCode::F(n - 10),
+            n @ 17..=21 => KeyCode::F(n - 11),
+            n @ 23..=24 => KeyCode::F(n - 12),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// Key presses in the bytes typed in an xterm compatible client. A lone `ESC` is the Esc key, followed
+/// by a character it's Alt.
+fn parse_keys(data: &[u8]) -> Vec<KeyEvent> {
+    let text = String::from_utf8_lossy(data);
+    let mut chars = text.chars();
+    let mut keys = vec![];
+    while let Some(c) = chars.next() {
+        let key = match c {
+            '\x1b' => match chars.next() {
+                None => Some(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)),
+                Some(intro @ ('[' | 'O')) => {
+                    let mut seq = String::new();
+                    for c in chars.by_ref() {
+                        seq.push(c);
+                        if c.is_ascii_alphabetic() || c == '~' {
+                            break;
+                        }
+                    }
+                    escape_key(intro, &seq).map(|code| KeyEvent::new(code, KeyModifiers::NONE))
+                }
+                Some(c) => Some(KeyEvent::new(KeyCode::Char(c), KeyModifiers::ALT)),
+            },
+            '\r' | '\n' => Some(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)),
+            '\t' => Some(KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE)),
+            '\x7f' | '\x08' => Some(KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE)),
+            '\x01'..='\x1a' => Some(KeyEvent::new(KeyCode::Char((c as u8 + 0x60) as char), KeyModifiers::CONTROL)),
+            c => Some(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)),
+        };
+        keys.extend(key);
+    }
+    keys
+}
+
+/// Runs a session's app until Esc or the client leaves, a game that doesn't load ends the session with
+/// the error.
+fn run_app(config: RunnerConfig, mut writer: SessionWriter, size: Arc<Mutex<Size>>, events: kanal::Receiver<Event>) {
+    let (runtime, handle, channel) = (writer.runtime.clone(), writer.handle.clone(), writer.channel);
+    match App::with_events(config, events) {
+        Err(err) => {
+            error!("SSH session: {}", err);
+            let _ = write!(writer, "Error: {}\r\n", err);
+            let _ = writer.flush();
+        }
+        Ok(mut app) => {
+            let _ = execute!(writer, EnterAlternateScreen, Hide);
+            match Terminal::new(SessionBackend { backend: CrosstermBackend::new(writer), size }) {
+                Ok(mut terminal) => {
+                    app.run(&mut terminal);
+                    let _ = execute!(terminal.backend_mut().backend, LeaveAlternateScreen, Show);
+                }
+                Err(err) => error!("SSH session terminal: {}", err),
+            }
+        }
+    }
+    let _ = runtime.block_on(handle.close(channel));
+}
+
+#[derive(Clone)]
+struct SshServer {
+    config: RunnerConfig,
+    auth: Arc<SshAuth>,
+    /// Sessions running a game, up to `max_sessions`.
+    sessions: Arc<AtomicUsize>,
+    max_sessions: usize,
+    runtime: tokio::runtime::Handle,
+}
+
+impl Server for SshServer {
+    type Handler = SshSession;
+
+    fn new_client(&mut self, peer: Option<SocketAddr>) -> SshSession {
+        SshSession {
+            server: self.clone(),
+            peer,
+            channel: None,
+            size: Arc::new(Mutex::new(DEFAULT_SIZE)),
+            events_tx: None,
+        }
+    }
+}
+
+/// One SSH connection, a single terminal channel.
+struct SshSession {
+    server: SshServer,
+    peer: Option<SocketAddr>,
+    channel: Option<ChannelId>,
+    size: Arc<Mutex<Size>>,
+    /// Keys and resizes for the running app, dropped to end it.
+    events_tx: Option<kanal::Sender<Event>>,
+}
+
+impl SshSession {
+    fn peer(&self) -> String {
+        self.peer.map(|peer| peer.to_string()).unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl Handler for SshSession {
+    type Error = russh::Error;
+
+    async fn auth_none(&mut self, _user: &str) -> Result<Auth, Self::Error> {
+        Ok(Auth::Reject { proceed_with_methods: Some(self.server.auth.methods()) })
+    }
+
+    async fn auth_publickey(&mut self, user: &str, public_key: &PublicKey) -> Result<Auth, Self::Error> {
+        let fingerprint = public_key.fingerprint();
+        if self.server.auth.keys.contains(&fingerprint) {
+            return Ok(Auth::Accept);
+        }
+        info!("SSH: key {} of {} from {} isn't authorized.", fingerprint, user, self.peer());
+        Ok(Auth::Reject { proceed_with_methods: Some(self.server.auth.methods()) })
+    }
+
+    async fn auth_password(&mut self, user: &str, password: &str) -> Result<Auth, Self::Error> {
+        if self.server.auth.password.as_deref() == Some(password) {
+            return Ok(Auth::Accept);
+        }
+        info!("SSH: wrong password for {} from {}.", user, self.peer());
+        Ok(Auth::Reject { proceed_with_methods: Some(self.server.auth.methods()) })
+    }
+
+    async fn channel_open_session(&mut self, channel: Channel<Msg>, _session: &mut Session) -> Result<bool, Self::Error> {
+        if self.channel.is_some() {
+            return Ok(false);
+        }
+        self.channel = Some(channel.id());
+        Ok(true)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn pty_request(
+        &mut self,
+        channel: ChannelId,
+        _term: &str,
+        col_width: u32,
+        row_height: u32,
+        _pix_width: u32,
+        _pix_height: u32,
+        _modes: &[(Pty, u32)],
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        *self.size.lock().unwrap() = Size::new(col_width as u16, row_height as u16);
+        session.channel_success(channel);
+        Ok(())
+    }
+
+    async fn shell_request(&mut self, channel: ChannelId, session: &mut Session) -> Result<(), Self::Error> {
+        if self.events_tx.is_some() {
+            session.channel_failure(channel);
+            return Ok(());
+        }
+        let sessions = self.server.sessions.clone();
+        if sessions.fetch_add(1, Ordering::SeqCst) >= self.server.max_sessions {
+            sessions.fetch_sub(1, Ordering::SeqCst);
+            info!("SSH: {} turned away, {} sessions running.", self.peer(), self.server.max_sessions);
+            session.data(channel, CryptoVec::from_slice(b"Too many sessions, try again later.\r\n"));
+            session.close(channel);
+            return Ok(());
+        }
+        let (events_tx, events_rx) = kanal::unbounded::<Event>();
+        self.events_tx = Some(events_tx);
+        let writer = SessionWriter {
+            runtime: self.server.runtime.clone(),
+            handle: session.handle(),
+            channel,
+            buffer: vec![],
+        };
+        let config = self.server.config.clone();
+        let size = self.size.clone();
+        let peer = self.peer();
+        info!("SSH session started for {}.", peer);
+        let started = std::thread::Builder::new()
+            .name(format!("SSH {}", peer))
+            .spawn({
+                let sessions = sessions.clone();
+                move || {
+                    run_app(config, writer, size, events_rx);
+                    sessions.fetch_sub(1, Ordering::SeqCst);
+                    info!("SSH session ended for {}.", peer);
+                }
+            });
+        match started {
+            Ok(_) => session.channel_success(channel),
+            Err(err) => {
+                error!("SSH session thread: {}", err);
+                sessions.fetch_sub(1, Ordering::SeqCst);
+                session.channel_failure(channel);
+            }
+        }
+        Ok(())
+    }
+
+    async fn data(&mut self, _channel: ChannelId, data: &[u8], _session: &mut Session) -> Result<(), Self::Error> {
+        if let Some(events_tx) = &self.events_tx {
+            parse_keys(data).into_iter().for_each(|key| {
+                let _ = events_tx.send(Event::Key(key));
+            });
+        }
+        Ok(())
+    }
+
+    async fn window_change_request(
+        &mut self,
+        _channel: ChannelId,
+        col_width: u32,
+        row_height: u32,
+        _pix_width: u32,
+        _pix_height: u32,
+        _session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        let size = Size::new(col_width as u16, row_height as u16);
+        *self.size.lock().unwrap() = size;
+        if let Some(events_tx) = &self.events_tx {
+            let _ = events_tx.send(Event::Resize(size.width, size.height));
+        }
+        Ok(())
+    }
+
+    async fn channel_close(&mut self, _channel: ChannelId, _session: &mut Session) -> Result<(), Self::Error> {
+        self.events_tx = None;
+        Ok(())
+    }
+}
+
+/// Serves the TUI on `addr` until killed, `host_key` is an OpenSSH private key file, generated for
+/// this run when missing. Up to `max_sessions` games run at once.
+pub(crate) fn serve(addr: &str, host_key: &Option<PathBuf>, auth: SshAuth, max_sessions: usize, config: RunnerConfig) -> ExitCode {
+    let key = match host_key {
+        None => KeyPair::generate_ed25519().expect("Could not generate the SSH host key."),
+        Some(path) => match russh_keys::load_secret_key(path, None) {
+            Ok(key) => key,
+            Err(err) => {
+                println!("Error: Couldn't load the SSH host key. {}", err);
+                return ExitCode::FAILURE;
+            }
+        },
+    };
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(err) => {
+            println!("Error: Couldn't start the SSH server. {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let ssh_config = Arc::new(Config {
+        keys: vec![key],
+        inactivity_timeout: None,
+        auth_rejection_time: AUTH_REJECTION_TIME,
+        auth_rejection_time_initial: Some(Duration::ZERO),
+        methods: auth.methods(),
+        ..Default::default()
+    });
+    let mut server = SshServer {
+        config,
+        auth: Arc::new(auth),
+        sessions: Arc::new(AtomicUsize::new(0)),
+        max_sessions,
+        runtime: runtime.handle().clone(),
+    };
+
+    println!("Serving holani-tui over SSH on {}.", addr);
+    match runtime.block_on(server.run_on_address(ssh_config, addr)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            println!("Error: SSH server on {}. {}", addr, err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(data: &[u8]) -> Vec<(KeyCode, KeyModifiers)> {
+        parse_keys(data).into_iter().map(|key| (key.code, key.modifiers)).collect()
+    }
+
+    #[test]
+    fn plain_and_control_characters() {
+        assert_eq!(codes(b"aZ "), [(KeyCode::Char('a'), KeyModifiers::NONE), (KeyCode::Char('Z'), KeyModifiers::NONE), (KeyCode::Char(' '), KeyModifiers::NONE)]);
+        assert_eq!(codes(b"\r\t\x7f"), [(KeyCode::Enter, KeyModifiers::NONE), (KeyCode::Tab, KeyModifiers::NONE), (KeyCode::Backspace, KeyModifiers::NONE)]);
+        assert_eq!(codes(b"\x03"), [(KeyCode::Char('c'), KeyModifiers::CONTROL)]);
+        assert_eq!(codes("é".as_bytes()), [(KeyCode::Char('é'), KeyModifiers::NONE)]);
+    }
+
+    #[test]
+    fn escape_alone_and_alt() {
+        assert_eq!(codes(b"\x1b"), [(KeyCode::Esc, KeyModifiers::NONE)]);
+        assert_eq!(codes(b"\x1bx"), [(KeyCode::Char('x'), KeyModifiers::ALT)]);
+    }
+
+    #[test]
+    fn escape_sequences() {
+        let keys = codes(b"\x1b[A\x1b[B\x1bOC\x1b[1;5D\x1bOP\x1b[15~\x1b[24~\x1b[3~\x1b[5~\x1b[H");
+        let expected = [KeyCode::Up, KeyCode::Down, KeyCode::Right, KeyCode::Left, KeyCode::F(1), KeyCode::F(5), KeyCode::F(12), KeyCode::Delete, KeyCode::PageUp, KeyCode::Home];
+        assert_eq!(keys, expected.map(|code| (code, KeyModifiers::NONE)));
+    }
+
+    #[test]
+    fn unknown_sequences_are_dropped() {
+        assert_eq!(codes(b"\x1b[99~q\x1b[Z"), [(KeyCode::Char('q'), KeyModifiers::NONE)]);
+    }
+}
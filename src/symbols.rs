@@ -0,0 +1,155 @@
+use std::{collections::{BTreeMap, HashMap}, ops::Bound, path::Path};
+
+/// Label names loaded from a cc65 debug info file (`ld65 --dbgfile`, `.dbg`) or
+/// a VICE label file (`ld65 -Ln`, `al <addr> .<name>` lines).
+#[derive(Default)]
+pub(crate) struct Symbols {
+    names: BTreeMap<u16, String>,
+    addresses: HashMap<String, u16>,
+}
+
+impl Symbols {
+    pub(crate) fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut symbols = Self::default();
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("dbg") => symbols.parse_dbg(&text)?,
+            _ => symbols.parse_labels(&text)?,
+        }
+
+        if symbols.names.is_empty() {
+            return Err(format!("{}: no symbols found.", path.display()));
+        }
+
+        Ok(symbols)
+    }
+
+    fn insert(&mut self, name: &str, addr: u16) {
+        self.names.entry(addr).or_insert_with(|| name.to_string());
+        self.addresses.entry(name.to_string()).or_insert(addr);
+    }
+
+    /// `sym id=0,name="main",addrsize=absolute,...,val=0x400,seg=1,type=lab`
+    fn parse_dbg(&mut self, text: &str) -> Result<(), String> {
+        for (n, line) in text.lines().enumerate() {
+            let Some(fields) = line.strip_prefix("sym\t") else {
+                continue;
+            };
+
+            let mut name = None;
+            let mut value = None;
+            let mut label = false;
+            for (key, v) in fields.split(',').filter_map(|f| f.split_once('=')) {
+                match key {
+                    "name" => name = Some(v.trim_matches('"')),
+                    "val" => value = Some(v),
+                    "type" => label = v == "lab",
+                    _ => (),
+                }
+            }
+
+            if let (true, Some(name), Some(value)) = (label, name, value) {
+                let hex = value.strip_prefix("0x").unwrap_or(value);
+                let addr = u16::from_str_radix(hex, 16).map_err(|_| format!("line {}: invalid value '{}'.", n + 1, value))?;
+                self.insert(name, addr);
+            }
+        }
+        Ok(())
+    }
+
+    /// `al 000400 .main`
+    fn parse_labels(&mut self, text: &str) -> Result<(), String> {
+        for (n, line) in text.lines().enumerate() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let ["al", addr, name] = fields[..] else {
+                continue;
+            };
+            let addr = u32::from_str_radix(addr, 16).map_err(|_| format!("line {}: invalid address '{}'.", n + 1, addr))?;
+            self.insert(name.trim_start_matches('.'), addr as u16);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn name(&self, addr: u16) -> Option<&str> {
+        self.names.get(&addr).map(String::as_str)
+    }
+
+    pub(crate) fn address(&self, name: &str) -> Option<u16> {
+        self.addresses.get(name).copied()
+    }
+
+    /// Closest symbol at or before `addr` as `name` or `name+offset`.
+    pub(crate) fn describe(&self, addr: u16) -> Option<String> {
+        let (sym_addr, name) = self.routine(addr)?;
+        match addr - sym_addr {
+            0 => Some(name.to_string()),
+            offset => Some(format!("{}+{}", name, offset)),
+        }
+    }
+
+    /// Closest symbol at or before `addr`.
+    pub(crate) fn routine(&self, addr: u16) -> Option<(u16, &str)> {
+        self.names.range(..=addr).next_back().map(|(sym_addr, name)| (*sym_addr, name.as_str()))
+    }
+
+    /// Addresses from the symbol up to the next one.
+    pub(crate) fn extent(&self, name: &str) -> Option<(u16, u16)> {
+        let start = self.address(name)?;
+        let end = self.names.range((Bound::Excluded(start), Bound::Unbounded)).next().map(|(next, _)| next - 1).unwrap_or(u16::MAX);
+        Some((start, end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DBG: &str = "version\tmajor=2,minor=0\n\
+        sym\tid=0,name=\"main\",addrsize=absolute,scope=0,def=1,val=0x400,seg=1,type=lab\n\
+        sym\tid=1,name=\"SCREEN\",addrsize=absolute,scope=0,def=2,val=0xC000,type=equ\n\
+        sym\tid=2,name=\"loop\",addrsize=absolute,scope=0,def=3,val=0x410,seg=1,type=lab\n";
+
+    #[test]
+    fn parses_cc65_debug_info_labels() {
+        let mut symbols = Symbols::default();
+        symbols.parse_dbg(DBG).unwrap();
+        assert_eq!(symbols.address("main"), Some(0x400));
+        assert_eq!(symbols.address("SCREEN"), None);
+        assert_eq!(symbols.name(0x410), Some("loop"));
+        assert_eq!(symbols.describe(0x40f).as_deref(), Some("main+15"));
+        assert_eq!(symbols.describe(0x3ff), None);
+        assert_eq!(symbols.extent("main"), Some((0x400, 0x40f)));
+        assert_eq!(symbols.extent("loop"), Some((0x410, 0xffff)));
+    }
+
+    #[test]
+    fn parses_vice_labels() {
+        let mut symbols = Symbols::default();
+        symbols.parse_labels("al 000400 .main\nal 000400 .start\nbreak 0400\nal 00FFFA .nmi\n").unwrap();
+        assert_eq!(symbols.name(0x400), Some("main"));
+        assert_eq!(symbols.address("start"), Some(0x400));
+        assert_eq!(symbols.routine(0xfffb), Some((0xfffa, "nmi")));
+    }
+
+    #[test]
+    fn reports_the_bad_line() {
+        let mut symbols = Symbols::default();
+        assert_eq!(symbols.parse_labels("al 0400 .main\nal zz .bad").err().unwrap(), "line 2: invalid address 'zz'.");
+        let dbg = "sym\tid=0,name=\"main\",val=0xzz,type=lab";
+        assert_eq!(symbols.parse_dbg(dbg).err().unwrap(), "line 1: invalid value '0xzz'.");
+    }
+
+    #[test]
+    fn loads_by_extension() {
+        let dir = std::env::temp_dir().join(format!("holani-symbols-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dbg = dir.join("game.dbg");
+        std::fs::write(&dbg, DBG).unwrap();
+        assert_eq!(Symbols::load(&dbg).unwrap().address("loop"), Some(0x410));
+        let labels = dir.join("game.lbl");
+        std::fs::write(&labels, DBG).unwrap();
+        assert!(Symbols::load(&labels).err().unwrap().ends_with("no symbols found."));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
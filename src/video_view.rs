@@ -0,0 +1,121 @@
+use ratatui::{crossterm::event::KeyCode, layout::{Constraint, Layout, Rect}, style::{Color, Style}, text::{Line, Span}, widgets::{Block, Paragraph}, Frame};
+
+use crate::{app::screen_canvas, debugger_view::parse_address, registers::palette_rgb, runner::debugger::{DebugCommand, VideoState, FRAMEBUFFER_LEN}};
+
+/// Palette swatches and any RAM area drawn as a 4 bits per pixel framebuffer.
+///
+/// Keys: `:` framebuffer address, `PageUp`/`PageDown` previous/next framebuffer sized area, `Home` displayed framebuffer.
+pub(crate) struct VideoView {
+    state: Option<VideoState>,
+    prompt: Option<String>,
+    message: String,
+    command_tx: kanal::Sender<DebugCommand>,
+}
+
+impl VideoView {
+    pub(crate) fn new(command_tx: kanal::Sender<DebugCommand>) -> Self {
+        Self {
+            state: None,
+            prompt: None,
+            message: String::new(),
+            command_tx,
+        }
+    }
+
+    pub(crate) fn update(&mut self, state: VideoState) {
+        self.state = Some(state);
+    }
+
+    fn view(&self, addr: Option<u16>) {
+        self.command_tx.send(DebugCommand::FramebufferView(addr)).unwrap();
+    }
+
+    /// Returns `true` if the key was consumed.
+    pub(crate) fn handle_key(&mut self, code: KeyCode) -> bool {
+        if let Some(prompt) = self.prompt.as_mut() {
+            match code {
+                KeyCode::Char(c) => prompt.push(c),
+                KeyCode::Backspace => {
+                    prompt.pop();
+                }
+                KeyCode::Enter => {
+                    let line = self.prompt.take().unwrap_or_default();
+                    match line.trim() {
+                        "" => self.view(None),
+                        addr => match parse_address(addr) {
+                            Some(addr) => self.view(Some(addr)),
+                            None => self.message = format!("Invalid address '{}'.", addr),
+                        },
+                    }
+                }
+                KeyCode::Esc => self.prompt = None,
+                _ => (),
+            }
+            return true;
+        }
+
+        let current = self.state.as_ref().map(|s| s.framebuffer_address).unwrap_or_default();
+        match code {
+            KeyCode::Char(':') => {
+                self.prompt = Some(String::new());
+                self.message.clear();
+            }
+            KeyCode::PageUp => self.view(Some(current.wrapping_sub(FRAMEBUFFER_LEN as u16))),
+            KeyCode::PageDown => self.view(Some(current.wrapping_add(FRAMEBUFFER_LEN as u16))),
+            KeyCode::Home => self.view(None),
+            _ => return false,
+        }
+        true
+    }
+
+    pub(crate) fn draw(&self, f: &mut Frame, area: Rect) {
+        let Some(state) = &self.state else {
+            f.render_widget(Paragraph::new("Waiting for the core..."), area);
+            return;
+        };
+
+        let [palette, framebuffer, status] = Layout::vertical([
+            Constraint::Length(4),
+            Constraint::Fill(1),
+            Constraint::Length(1),
+        ]).areas(area);
+
+        let colors: Vec<(u8, u8, u8)> = state.green.iter().zip(&state.bluered)
+            .map(|(green, bluered)| palette_rgb(*green, *bluered))
+            .collect();
+
+        let swatches: Vec<Span> = colors.iter().enumerate().map(|(n, (r, g, b))| {
+            Span::styled(format!(" {:X}  ", n), Style::default().fg(Color::Black).bg(Color::Rgb(*r, *g, *b)))
+        }).collect();
+        let values: Vec<Span> = state.green.iter().zip(&state.bluered)
+            .map(|(green, bluered)| Span::raw(format!("{:X}{:02X} ", green & 0x0f, bluered)))
+            .collect();
+        f.render_widget(
+            Paragraph::new(vec![Line::from(swatches), Line::from(values)]).block(Block::bordered().title("Palette - GBR")),
+            palette,
+        );
+
+        let rgb_buffer: Vec<u8> = state.framebuffer.iter()
+            .flat_map(|b| [b >> 4, b & 0x0f])
+            .flat_map(|pen| {
+                let (r, g, b) = colors[pen as usize];
+                [r, g, b]
+            })
+            .collect();
+        let title = format!(
+            "Framebuffer ${:04X}-${:04X}{}",
+            state.framebuffer_address,
+            state.framebuffer_address.wrapping_add(FRAMEBUFFER_LEN as u16 - 1),
+            if state.displayed { " - displayed" } else { "" },
+        );
+        let block = Block::bordered().title(title);
+        f.render_widget(screen_canvas(&rgb_buffer, None), block.inner(framebuffer));
+        f.render_widget(block, framebuffer);
+
+        let status_line = match &self.prompt {
+            Some(prompt) => Line::from(vec![Span::styled("address: ", Style::default().fg(Color::Yellow)), Span::raw(prompt.clone())]),
+            None => Line::raw(self.message.clone()),
+        };
+        f.render_widget(Paragraph::new(status_line), status);
+    }
+}
@@ -0,0 +1,83 @@
+use std::{io::Stdout, time::Duration};
+use ratatui::{crossterm::{self, event::{Event, KeyCode, KeyEventKind}}, layout::{Constraint, Layout}, prelude::CrosstermBackend, widgets::Paragraph, Terminal};
+use rodio::{buffer::SamplesBuffer, OutputStream, Sink};
+
+use crate::{app::screen_canvas, runner::{spectator::SpectatorStream, SAMPLE_RATE}};
+
+const INPUT_POLL: Duration = Duration::from_millis(2);
+
+/// Read-only view of another instance's screen and sound, Esc exits.
+pub(crate) struct Viewer {
+    addr: String,
+    stream: SpectatorStream,
+    screen: Vec<u8>,
+    ended: bool,
+    sink: Option<Sink>,
+    _output: Option<OutputStream>,
+}
+
+impl Viewer {
+    pub fn new(addr: String, stream: SpectatorStream, mute: bool) -> Self {
+        let (output, sink) = match mute {
+            true => (None, None),
+            false => {
+                let (output, handle) = OutputStream::try_default().unwrap();
+                (Some(output), Some(Sink::try_new(&handle).unwrap()))
+            }
+        };
+        Self {
+            addr,
+            stream,
+            screen: vec![],
+            ended: false,
+            sink,
+            _output: output,
+        }
+    }
+
+    pub fn run(&mut self, terminal: &mut Terminal<CrosstermBackend<Stdout>>) {
+        let mut redraw = true;
+        while !self.exit_requested() {
+            while let Ok(Some(screen)) = self.stream.screen_rx.try_recv() {
+                self.screen = screen;
+                redraw = true;
+            }
+            while let Ok(Some(samples)) = self.stream.audio_rx.try_recv() {
+                if let Some(sink) = self.sink.as_ref().filter(|_| !samples.is_empty()) {
+                    sink.append(SamplesBuffer::new(2, SAMPLE_RATE, samples));
+                }
+            }
+            if !self.ended && self.stream.screen_rx.is_disconnected() {
+                self.ended = true;
+                redraw = true;
+            }
+            if redraw {
+                self.draw(terminal);
+                redraw = false;
+            }
+        }
+    }
+
+    fn draw(&self, terminal: &mut Terminal<CrosstermBackend<Stdout>>) {
+        terminal.draw(|f| {
+            let [title, main] = Layout::vertical([Constraint::Length(1), Constraint::Fill(1)]).areas(f.area());
+            let status = match self.ended {
+                true => format!("Spectating {} - stream ended, Esc to quit", self.addr),
+                false => format!("Spectating {}", self.addr),
+            };
+            f.render_widget(Paragraph::new(status), title);
+            f.render_widget(screen_canvas(&self.screen, None), main);
+        }).unwrap();
+    }
+
+    fn exit_requested(&self) -> bool {
+        while let Ok(true) = crossterm::event::poll(INPUT_POLL) {
+            if let Ok(Event::Key(input)) = crossterm::event::read() {
+                if input.kind == KeyEventKind::Press && input.code == KeyCode::Esc {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}